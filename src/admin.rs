@@ -0,0 +1,369 @@
+use std::{fs::File, io::BufReader, path::PathBuf, sync::Arc};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    delete,
+    dev::{Server, ServiceRequest, ServiceResponse},
+    get,
+    http::header,
+    middleware::{self, Next},
+    post, put, web, App, HttpRequest, HttpResponse, HttpServer,
+};
+use rustls::{server::WebPkiClientVerifier, RootCertStore, ServerConfig};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    auth::constant_time_eq,
+    config::{self, AdminConfig, AdminTlsConfig},
+    downloads,
+    error::HttpError,
+    i18n::Message,
+    maintenance::{self, MaintenanceWindow},
+    render, request_id,
+    storage::{self, select_provider, StorageError, StorageProvider},
+    sync, upload, upstream, BASE_PATH,
+};
+
+/// 启动管理接口，配置中没有`[admin]`时返回`None`
+pub fn server() -> std::io::Result<Option<Server>> {
+    let config = config::current();
+    let admin = match &config.admin {
+        Some(admin) => admin,
+        None => return Ok(None),
+    };
+    if admin.tokens.is_empty() && !client_cert_required(admin) {
+        log::error!("Admin API requires tokens or TLS client certificates, not starting it");
+        return Ok(None);
+    }
+
+    let server = HttpServer::new(|| {
+        App::new()
+            .wrap(middleware::from_fn(authenticate))
+            .wrap(middleware::from_fn(request_id::assign_request_id))
+            .wrap(middleware::Logger::new(crate::ACCESS_LOG_FORMAT))
+            .service(
+                web::scope("/admin")
+                    .service(reload)
+                    .service(purge)
+                    .service(active_downloads)
+                    .service(health)
+                    .service(get_maintenance)
+                    .service(set_maintenance)
+                    .service(reset_maintenance)
+                    .service(storage_info)
+                    .service(put_file)
+                    .service(delete_file)
+                    .service(rename_file)
+                    .service(make_directory)
+                    .service(start_sync),
+            )
+    })
+    .workers(1);
+
+    let server = match &admin.tls {
+        Some(tls) => {
+            let tls_config = tls_config(tls).map_err(std::io::Error::other)?;
+            server.bind_rustls_0_23(&admin.listen, tls_config)?
+        }
+        None => server.bind(&admin.listen)?,
+    };
+    log::info!("Admin API listening on {}", admin.listen);
+    Ok(Some(server.run()))
+}
+
+fn client_cert_required(admin: &AdminConfig) -> bool {
+    admin
+        .tls
+        .as_ref()
+        .is_some_and(|tls| tls.client_ca.is_some())
+}
+
+fn tls_config(tls: &AdminTlsConfig) -> anyhow::Result<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&tls.cert)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(&tls.key)?))?
+        .ok_or_else(|| anyhow!("No private key found in {}", tls.key))?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match &tls.client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in rustls_pemfile::certs(&mut BufReader::new(File::open(ca)?)) {
+                roots.add(cert?)?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(builder.with_single_cert(certs, key)?)
+}
+
+/// 校验`Authorization: Bearer <token>`。只配置了客户端证书时，TLS握手已经完成了认证
+async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let config = config::current();
+    let authorized = match &config.admin {
+        Some(admin) if admin.tokens.is_empty() => client_cert_required(admin),
+        Some(admin) => req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .is_some_and(|token| {
+                admin
+                    .tokens
+                    .iter()
+                    .any(|t| constant_time_eq(t.as_bytes(), token.trim().as_bytes()))
+            }),
+        None => false,
+    };
+    if !authorized {
+        let resp = HttpError::unauthorized(
+            Message::Unauthorized,
+            Message::UnauthorizedDesc,
+            r#"Bearer realm="admin""#.to_string(),
+        )
+        .to_http_response(req.request());
+        return Ok(req.into_response(resp).map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// 重新读取配置文件，重建存储提供者（缓存随之清空）和主题
+async fn reload_config() -> anyhow::Result<()> {
+    let config = config::load_config(config::CONFIG_FILE).await?;
+    storage::reload(&config)?;
+    render::theme::reload(&config);
+    config::set_current(config);
+    Ok(())
+}
+
+#[post("/reload")]
+async fn reload() -> HttpResponse {
+    match reload_config().await {
+        Ok(()) => {
+            log::info!("Configuration reloaded from {}", config::CONFIG_FILE);
+            HttpResponse::Ok().json(json!({ "status": "ok" }))
+        }
+        Err(e) => {
+            log::error!("Failed to reload configuration: {:#}", e);
+            HttpResponse::BadRequest()
+                .json(json!({ "status": "error", "error": format!("{:#}", e) }))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PurgeQuery {
+    /// 请求路径前缀，例如`/pub/dragonos/`
+    prefix: String,
+}
+
+#[post("/purge")]
+async fn purge(query: web::Query<PurgeQuery>) -> HttpResponse {
+    if !query.prefix.starts_with('/') {
+        return HttpResponse::BadRequest()
+            .json(json!({ "status": "error", "error": "prefix must start with '/'" }));
+    }
+    let purged = storage::purge(&query.prefix);
+    log::info!("Purged {} cache entries under {}", purged, query.prefix);
+    HttpResponse::Ok().json(json!({ "status": "ok", "purged": purged }))
+}
+
+#[derive(Debug, Deserialize)]
+struct SyncQuery {
+    name: String,
+}
+
+/// 在后台立即执行一次同步任务，执行结果通过`/sync/status.json`查看
+#[post("/sync")]
+async fn start_sync(query: web::Query<SyncQuery>) -> HttpResponse {
+    let config = config::current();
+    let job = match config.sync.iter().find(|j| j.name == query.name) {
+        Some(job) => job.clone(),
+        None => {
+            return HttpResponse::NotFound()
+                .json(json!({ "status": "error", "error": "unknown sync job" }))
+        }
+    };
+    if sync::is_running(&job.name) {
+        return HttpResponse::Conflict()
+            .json(json!({ "status": "error", "error": "sync job is already running" }));
+    }
+    actix_web::rt::spawn(async move {
+        let _ = sync::run(&job).await;
+    });
+    HttpResponse::Accepted().json(json!({ "status": "ok" }))
+}
+
+#[get("/downloads")]
+async fn active_downloads() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "downloads": downloads::active() }))
+}
+
+#[get("/health")]
+async fn health() -> HttpResponse {
+    let providers = storage::health().await;
+    let mut builder = if providers.iter().all(|p| p.healthy) {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    builder.json(json!({ "providers": providers, "origins": upstream::statuses() }))
+}
+
+fn maintenance_status() -> HttpResponse {
+    let (source, windows) = maintenance::windows();
+    HttpResponse::Ok().json(json!({ "source": source, "windows": windows }))
+}
+
+#[derive(Debug, Deserialize)]
+struct MaintenanceRequest {
+    windows: Vec<MaintenanceWindow>,
+}
+
+#[get("/maintenance")]
+async fn get_maintenance() -> HttpResponse {
+    maintenance_status()
+}
+
+/// 设置维护窗口，覆盖配置文件中的`[[maintenance]]`，空列表表示结束维护
+#[put("/maintenance")]
+async fn set_maintenance(body: web::Json<MaintenanceRequest>) -> HttpResponse {
+    maintenance::set_override(Some(body.into_inner().windows));
+    maintenance_status()
+}
+
+/// 清除管理接口设置的维护窗口，恢复使用配置文件
+#[delete("/maintenance")]
+async fn reset_maintenance() -> HttpResponse {
+    maintenance::set_override(None);
+    maintenance_status()
+}
+
+#[get("/storage")]
+async fn storage_info() -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "backend": config::current().storage.backend,
+        "capabilities": storage::capabilities(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct FileQuery {
+    /// 完整的请求路径，例如`/pub/dragonos/nightly/dragonos.iso`
+    path: String,
+    #[serde(default)]
+    overwrite: bool,
+    #[serde(default)]
+    recursive: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct RenameRequest {
+    from: String,
+    to: String,
+}
+
+/// 检查管理接口收到的请求路径，返回对应的存储和存储中的路径
+/// 检查并规范化管理接口中的完整路径
+fn normalize(path: &str) -> Result<String, HttpError> {
+    if !path.starts_with(BASE_PATH) {
+        return Err(HttpError::bad_request(
+            Message::InvalidRequest,
+            Message::InvalidRequestPath,
+        ));
+    }
+    crate::validate_path(&PathBuf::from(path))
+}
+
+fn resolve(path: &str) -> Result<(Arc<dyn StorageProvider>, String), HttpError> {
+    select_provider(&normalize(path)?)
+        .ok_or_else(|| HttpError::not_found(Message::PathNotFound, Message::ResourceNotFound))
+}
+
+fn storage_error(path: &str, e: StorageError) -> HttpError {
+    log::error!("Storage operation on {} failed: {}", path, e);
+    HttpError::from_storage(&e, Message::ServerError)
+}
+
+/// 写入文件，请求体即文件内容，支持`X-Checksum-Sha256`校验
+#[put("/files")]
+async fn put_file(
+    req: HttpRequest,
+    query: web::Query<FileQuery>,
+    payload: web::Payload,
+) -> HttpResponse {
+    let checked =
+        normalize(&query.path).and_then(|path| Ok((path, upload::expected_checksum(&req)?)));
+    let result = match checked {
+        Ok((path, expected)) => {
+            upload::store(&path, payload, expected, None, query.overwrite).await
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(result) => {
+            // 写入时可能新建了上级目录，清除全部列表缓存
+            storage::purge(BASE_PATH);
+            HttpResponse::Created().json(result)
+        }
+        Err(e) => e.to_http_response(&req),
+    }
+}
+
+#[delete("/files")]
+async fn delete_file(req: HttpRequest, query: web::Query<FileQuery>) -> HttpResponse {
+    let result = match resolve(&query.path) {
+        Ok((provider, path)) => provider
+            .delete(&path, query.recursive)
+            .await
+            .map_err(|e| storage_error(&query.path, e)),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(true) => HttpResponse::Ok().json(json!({ "status": "ok" })),
+        Ok(false) => HttpError::not_found(Message::FileNotFound, Message::ResourceNotFound)
+            .to_http_response(&req),
+        Err(e) => e.to_http_response(&req),
+    }
+}
+
+#[post("/rename")]
+async fn rename_file(req: HttpRequest, body: web::Json<RenameRequest>) -> HttpResponse {
+    let result = match resolve(&body.from).and_then(|from| Ok((from, resolve(&body.to)?))) {
+        Ok(((provider, from), (_, to))) => provider
+            .rename(&from, &to)
+            .await
+            .map_err(|e| storage_error(&body.from, e)),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(true) => HttpResponse::Ok().json(json!({ "status": "ok" })),
+        Ok(false) => HttpError::not_found(Message::FileNotFound, Message::ResourceNotFound)
+            .to_http_response(&req),
+        Err(e) => e.to_http_response(&req),
+    }
+}
+
+#[post("/mkdir")]
+async fn make_directory(req: HttpRequest, query: web::Query<FileQuery>) -> HttpResponse {
+    let result = match resolve(&query.path) {
+        Ok((provider, path)) => provider
+            .mkdir(&path)
+            .await
+            .map_err(|e| storage_error(&query.path, e)),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => HttpResponse::Ok().json(json!({ "status": "ok" })),
+        Err(e) => e.to_http_response(&req),
+    }
+}
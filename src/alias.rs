@@ -0,0 +1,312 @@
+use std::cmp::Ordering;
+
+use regex::Regex;
+
+use crate::{
+    config::{AliasAction, AliasRule, AliasSort},
+    storage::{select_provider, StorageEntry, StorageResult},
+};
+
+/// 别名解析的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedAlias {
+    /// 解析得到的真实文件的完整请求路径（例如 /pub/dragonos/dragonos-0.1.9.iso）
+    pub target: String,
+    pub action: AliasAction,
+}
+
+/// 查找与请求路径匹配的别名规则
+pub fn find_rule<'a>(rules: &'a [AliasRule], full_path: &str) -> Option<&'a AliasRule> {
+    rules
+        .iter()
+        .find(|rule| rule.path.trim_end_matches('/') == full_path.trim_end_matches('/'))
+}
+
+/// 将别名解析为目录中最新的匹配文件。
+///
+/// 返回`Ok(None)`表示目录不存在或者没有任何文件匹配。
+pub async fn resolve(rule: &AliasRule) -> StorageResult<Option<ResolvedAlias>> {
+    let directory = rule.directory.trim_end_matches('/');
+    let (provider, path_in_provider) = match select_provider(directory) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let entries = match provider.list_directory(&path_in_provider).await? {
+        Some(entries) => entries,
+        None => return Ok(None),
+    };
+
+    let matcher = PatternMatcher::new(&rule.pattern)?;
+    let newest = select_newest(&matcher, rule.sort, entries);
+    log::debug!(
+        "Alias {} resolved in {} to {:?}",
+        rule.path,
+        directory,
+        newest.as_ref().map(|e| &e.name)
+    );
+
+    Ok(newest.map(|entry| ResolvedAlias {
+        target: format!("{}/{}", directory, entry.name),
+        action: rule.action,
+    }))
+}
+
+fn select_newest(
+    matcher: &PatternMatcher,
+    sort: AliasSort,
+    entries: Vec<StorageEntry>,
+) -> Option<StorageEntry> {
+    entries
+        .into_iter()
+        .filter(|e| !e.name.ends_with('/') && e.size.is_some())
+        .filter_map(|e| {
+            let version = matcher.captures(&e.name)?;
+            Some((Version::parse(&version), e))
+        })
+        .max_by(|(va, a), (vb, b)| match sort {
+            AliasSort::Semver => va.cmp(vb).then_with(|| a.modified.cmp(&b.modified)),
+            AliasSort::Date => a.modified.cmp(&b.modified).then_with(|| va.cmp(vb)),
+        })
+        .map(|(_, e)| e)
+}
+
+/// 文件名模式匹配器。
+///
+/// 模式中`*`匹配任意字符，`{version}`匹配版本号并被捕获用于排序。
+pub struct PatternMatcher {
+    regex: Regex,
+}
+
+impl PatternMatcher {
+    pub fn new(pattern: &str) -> anyhow::Result<Self> {
+        let mut re = String::from("^");
+        let mut rest = pattern;
+        while !rest.is_empty() {
+            if let Some(r) = rest.strip_prefix("{version}") {
+                re.push_str("(?P<version>.+?)");
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix('*') {
+                re.push_str(".*?");
+                rest = r;
+            } else {
+                let ch = rest.chars().next().unwrap();
+                re.push_str(&regex::escape(ch.encode_utf8(&mut [0; 4])));
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+        re.push('$');
+        let regex = Regex::new(&re)
+            .map_err(|e| anyhow::anyhow!("Invalid alias pattern '{}': {}", pattern, e))?;
+        Ok(Self { regex })
+    }
+
+    /// 若名称匹配，返回捕获到的版本号（模式中没有`{version}`时返回空字符串）
+    pub fn captures(&self, name: &str) -> Option<String> {
+        let caps = self.regex.captures(name)?;
+        Some(
+            caps.name("version")
+                .map(|m| m.as_str().to_string())
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// 宽松的语义化版本号，兼容`v`前缀、任意数量的数字段以及预发布后缀。
+/// 相等与大小比较一致，因此`1.0`与`1.0.0`相等
+#[derive(Debug)]
+pub struct Version {
+    numbers: Vec<u64>,
+    pre: Option<String>,
+}
+
+impl Version {
+    pub fn parse(s: &str) -> Self {
+        let s = s.trim_start_matches(['v', 'V']);
+        let (core, pre) = match s.split_once(['-', '~']) {
+            Some((core, pre)) => (core, Some(pre.to_string())),
+            None => (s, None),
+        };
+        let numbers = core
+            .split('.')
+            .map(|part| {
+                let digits: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+                digits.parse().unwrap_or(0)
+            })
+            .collect();
+        Self { numbers, pre }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.numbers.len().max(other.numbers.len());
+        for i in 0..len {
+            let a = self.numbers.get(i).copied().unwrap_or(0);
+            let b = other.numbers.get(i).copied().unwrap_or(0);
+            match a.cmp(&b) {
+                Ordering::Equal => continue,
+                ord => return ord,
+            }
+        }
+        // 正式版本高于预发布版本
+        match (&self.pre, &other.pre) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) => cmp_pre(a, b),
+        }
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+/// 按语义化版本的规则比较预发布后缀：按`.`分段逐段比较，字段较少的更低。
+/// 每段中的数字按数值比较，数字低于字母，因此`rc2 < rc10`、`1 < alpha`
+fn cmp_pre(a: &str, b: &str) -> Ordering {
+    let mut a_ids = a.split('.');
+    let mut b_ids = b.split('.');
+    loop {
+        match (a_ids.next(), b_ids.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match cmp_identifier(a, b) {
+                Ordering::Equal => continue,
+                ord => return ord,
+            },
+        }
+    }
+}
+
+fn cmp_identifier(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (a_chunk, a_rest) = split_chunk(a);
+        let (b_chunk, b_rest) = split_chunk(b);
+        let a_digits = a_chunk.starts_with(|c: char| c.is_ascii_digit());
+        let b_digits = b_chunk.starts_with(|c: char| c.is_ascii_digit());
+        let ord = match (a_chunk.is_empty(), b_chunk.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            _ => match (a_digits, b_digits) {
+                (true, true) => {
+                    let (a_num, b_num) = (
+                        a_chunk.trim_start_matches('0'),
+                        b_chunk.trim_start_matches('0'),
+                    );
+                    a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num))
+                }
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                (false, false) => a_chunk.cmp(b_chunk),
+            },
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+        (a, b) = (a_rest, b_rest);
+    }
+}
+
+/// 取出开头连续的数字或非数字部分
+fn split_chunk(s: &str) -> (&str, &str) {
+    let digits = s.starts_with(|c: char| c.is_ascii_digit());
+    let end = s
+        .find(|c: char| c.is_ascii_digit() != digits)
+        .unwrap_or(s.len());
+    s.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    fn entry(name: &str, age_secs: u64) -> StorageEntry {
+        StorageEntry {
+            name: name.to_string(),
+            url: format!("/{}", name),
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 - age_secs)),
+            size: Some(1),
+            approximate_size: false,
+        }
+    }
+
+    #[test]
+    fn test_version_ordering() {
+        assert!(Version::parse("0.1.10") > Version::parse("0.1.9"));
+        assert!(Version::parse("v1.0") > Version::parse("0.9.9"));
+        assert!(Version::parse("1.0.0") > Version::parse("1.0.0-rc1"));
+        assert!(Version::parse("1.0.0-rc2") > Version::parse("1.0.0-rc1"));
+        assert_eq!(Version::parse("1.0"), Version::parse("1.0"));
+    }
+
+    #[test]
+    fn test_pre_release_ordering() {
+        assert!(Version::parse("1.0.0-rc10") > Version::parse("1.0.0-rc2"));
+        assert!(Version::parse("1.0.0-rc.10") > Version::parse("1.0.0-rc.2"));
+        assert!(Version::parse("1.0.0-beta") > Version::parse("1.0.0-alpha.1"));
+        assert!(Version::parse("1.0.0-alpha.1") > Version::parse("1.0.0-alpha"));
+        assert!(Version::parse("1.0.0-alpha.beta") > Version::parse("1.0.0-alpha.1"));
+        assert!(Version::parse("1.0.0") > Version::parse("1.0.0-rc10"));
+        // 相等与排序一致
+        assert_eq!(Version::parse("1.0"), Version::parse("v1.0.0"));
+        assert_eq!(Version::parse("1.0.0-rc02"), Version::parse("1.0.0-rc2"));
+        assert_ne!(Version::parse("1.0.0-rc2"), Version::parse("1.0.0-rc10"));
+
+        let mut versions: Vec<_> = ["1.0.0", "1.0.0-rc10", "1.0.0-rc9", "1.0.0-rc2"]
+            .iter()
+            .map(|v| Version::parse(v))
+            .collect();
+        versions.sort();
+        assert_eq!(
+            versions,
+            ["1.0.0-rc2", "1.0.0-rc9", "1.0.0-rc10", "1.0.0"]
+                .iter()
+                .map(|v| Version::parse(v))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_pattern_matcher() {
+        let m = PatternMatcher::new("dragonos-{version}.iso").unwrap();
+        assert_eq!(m.captures("dragonos-0.1.9.iso").as_deref(), Some("0.1.9"));
+        assert_eq!(m.captures("dragonos-0.1.9.iso.sha256"), None);
+        assert_eq!(m.captures("other-0.1.9.iso"), None);
+
+        let m = PatternMatcher::new("nightly-*.img").unwrap();
+        assert_eq!(m.captures("nightly-20251019.img").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_select_newest() {
+        let entries = vec![
+            entry("dragonos-0.1.9.iso", 10),
+            entry("dragonos-0.1.10.iso", 20),
+            entry("dragonos-0.1.8.iso", 0),
+            entry("README.md", 0),
+        ];
+        let m = PatternMatcher::new("dragonos-{version}.iso").unwrap();
+
+        let newest = select_newest(&m, AliasSort::Semver, entries.clone()).unwrap();
+        assert_eq!(newest.name, "dragonos-0.1.10.iso");
+
+        let newest = select_newest(&m, AliasSort::Date, entries).unwrap();
+        assert_eq!(newest.name, "dragonos-0.1.8.iso");
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use actix_web::{http::header, web, HttpRequest};
+use argon2::{password_hash::PasswordHash, Argon2, PasswordVerifier};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{self, is_under, AuthRule},
+    error::HttpError,
+    i18n::Message,
+    storage::StorageEntry,
+};
+
+/// 验证成功的口令在内存中缓存的时间，避免每个请求都重新计算bcrypt/argon2
+const VERIFIED_TTL: Duration = Duration::from_secs(300);
+
+lazy_static! {
+    static ref HTPASSWD_FILES: Mutex<HashMap<PathBuf, (SystemTime, Arc<Htpasswd>)>> =
+        Mutex::new(HashMap::new());
+    static ref VERIFIED: Mutex<HashMap<[u8; 32], Instant>> = Mutex::new(HashMap::new());
+}
+
+/// 适用于该路径的认证规则，路径前缀最长（最具体）的优先
+fn rule_for<'a>(rules: &'a [AuthRule], full_path: &str) -> Option<&'a AuthRule> {
+    rules
+        .iter()
+        .filter(|r| is_under(full_path, &r.path))
+        .max_by_key(|r| r.path.len())
+}
+
+/// 路径是否受保护，受保护的响应不能被共享缓存保存
+pub fn is_protected(full_path: &str) -> bool {
+    rule_for(&config::current().auth, full_path).is_some()
+}
+
+/// 目录列表是否因人而异：目录本身受保护，或者其中可能有被隐藏的受保护项
+pub fn listing_varies(dir_path: &str) -> bool {
+    config::current()
+        .auth
+        .iter()
+        .any(|r| is_under(dir_path, &r.path) || is_under(&r.path, dir_path))
+}
+
+/// 在访问存储之前检查请求是否有权访问该路径，未通过时返回带`WWW-Authenticate`的401
+pub async fn authorize(req: &HttpRequest, full_path: &str) -> Result<(), HttpError> {
+    let config = config::current();
+    let rule = match rule_for(&config.auth, full_path) {
+        Some(rule) => rule,
+        None => return Ok(()),
+    };
+    if is_authorized(req, rule).await {
+        return Ok(());
+    }
+    log::debug!("Unauthorized request to protected path {}", full_path);
+    Err(HttpError::unauthorized(
+        Message::Unauthorized,
+        Message::UnauthorizedDesc,
+        challenge(rule),
+    ))
+}
+
+/// 从目录列表中去掉请求无权访问的受保护目录和文件
+pub async fn visible_entries(
+    req: &HttpRequest,
+    dir_path: &str,
+    entries: Vec<StorageEntry>,
+) -> Vec<StorageEntry> {
+    let config = config::current();
+    if config.auth.is_empty() {
+        return entries;
+    }
+    let dir_path = dir_path.trim_end_matches('/');
+    let mut visible = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let child = format!("{}/{}", dir_path, entry.name.trim_end_matches('/'));
+        let allowed = match rule_for(&config.auth, &child) {
+            // 目录本身已经通过了认证，同一规则下的子项不需要再检查
+            Some(rule) if !is_under(dir_path, &rule.path) => is_authorized(req, rule).await,
+            _ => true,
+        };
+        if allowed {
+            visible.push(entry);
+        }
+    }
+    visible
+}
+
+fn challenge(rule: &AuthRule) -> String {
+    let realm = rule.realm.replace('"', "'");
+    match (rule.htpasswd.is_some(), rule.tokens.is_empty()) {
+        (true, true) => format!(r#"Basic realm="{}", charset="UTF-8""#, realm),
+        (true, false) => format!(
+            r#"Basic realm="{}", charset="UTF-8", Bearer realm="{}""#,
+            realm, realm
+        ),
+        (false, _) => format!(r#"Bearer realm="{}""#, realm),
+    }
+}
+
+async fn is_authorized(req: &HttpRequest, rule: &AuthRule) -> bool {
+    let value = match req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+    {
+        Some(v) => v.trim(),
+        None => return false,
+    };
+    let (scheme, credentials) = value.split_once(' ').unwrap_or((value, ""));
+    let credentials = credentials.trim();
+
+    if scheme.eq_ignore_ascii_case("bearer") {
+        return rule
+            .tokens
+            .iter()
+            .any(|t| constant_time_eq(t.as_bytes(), credentials.as_bytes()));
+    }
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return false;
+    }
+    let htpasswd_path = match &rule.htpasswd {
+        Some(path) => path,
+        None => return false,
+    };
+    let decoded = match STANDARD
+        .decode(credentials)
+        .ok()
+        .and_then(|d| String::from_utf8(d).ok())
+    {
+        Some(d) => d,
+        None => return false,
+    };
+    let (user, password) = match decoded.split_once(':') {
+        Some(v) => v,
+        None => return false,
+    };
+
+    let htpasswd = match load_htpasswd(htpasswd_path) {
+        Ok(htpasswd) => htpasswd,
+        Err(e) => {
+            log::error!("Failed to load htpasswd file {}: {}", htpasswd_path, e);
+            return false;
+        }
+    };
+    let hash = match htpasswd.users.get(user) {
+        Some(hash) => hash.clone(),
+        None => return false,
+    };
+    verify_cached(hash, password.to_string()).await
+}
+
+/// 解析后的htpasswd文件
+struct Htpasswd {
+    users: HashMap<String, String>,
+}
+
+impl Htpasswd {
+    fn parse(content: &str) -> Self {
+        let users = content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|l| l.split_once(':'))
+            .map(|(user, hash)| (user.to_string(), hash.trim().to_string()))
+            .collect();
+        Self { users }
+    }
+}
+
+/// 读取htpasswd文件，文件未修改时使用已解析的结果
+fn load_htpasswd(path: &str) -> std::io::Result<Arc<Htpasswd>> {
+    let path = PathBuf::from(path);
+    let modified = std::fs::metadata(&path)?.modified()?;
+    let mut files = HTPASSWD_FILES.lock().unwrap();
+    if let Some((mtime, htpasswd)) = files.get(&path) {
+        if *mtime == modified {
+            return Ok(htpasswd.clone());
+        }
+    }
+    let htpasswd = Arc::new(Htpasswd::parse(&std::fs::read_to_string(&path)?));
+    files.insert(path, (modified, htpasswd.clone()));
+    Ok(htpasswd)
+}
+
+async fn verify_cached(hash: String, password: String) -> bool {
+    let key: [u8; 32] = Sha256::new()
+        .chain_update(&hash)
+        .chain_update([0])
+        .chain_update(&password)
+        .finalize()
+        .into();
+    {
+        let mut verified = VERIFIED.lock().unwrap();
+        verified.retain(|_, at| at.elapsed() < VERIFIED_TTL);
+        if verified.contains_key(&key) {
+            return true;
+        }
+    }
+
+    // bcrypt和argon2都是刻意设计得很慢的算法，放到阻塞线程池中执行
+    let ok = web::block(move || verify_password(&hash, &password))
+        .await
+        .unwrap_or(false);
+    if ok {
+        VERIFIED.lock().unwrap().insert(key, Instant::now());
+    }
+    ok
+}
+
+/// 校验htpasswd中的口令哈希，支持bcrypt（`$2a$`/`$2b$`/`$2y$`）和argon2（`$argon2id$`等）
+fn verify_password(hash: &str, password: &str) -> bool {
+    if hash.starts_with("$2") {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    } else if hash.starts_with("$argon2") {
+        PasswordHash::new(hash)
+            .map(|h| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &h)
+                    .is_ok()
+            })
+            .unwrap_or(false)
+    } else {
+        log::warn!("Unsupported htpasswd hash format, only bcrypt and argon2 are supported");
+        false
+    }
+}
+
+/// 与输入内容无关的耗时比较，避免通过响应时间猜测口令
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::password_hash::{PasswordHasher, SaltString};
+
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+
+    #[test]
+    fn test_verify_password() {
+        let bcrypt_hash = bcrypt::hash("hunter2", 4).unwrap();
+        assert!(verify_password(&bcrypt_hash, "hunter2"));
+        assert!(!verify_password(&bcrypt_hash, "hunter3"));
+
+        let salt = SaltString::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+        let argon2_hash = Argon2::default()
+            .hash_password(b"hunter2", &salt)
+            .unwrap()
+            .to_string();
+        assert!(verify_password(&argon2_hash, "hunter2"));
+        assert!(!verify_password(&argon2_hash, "hunter3"));
+
+        assert!(!verify_password(
+            "{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=",
+            "password"
+        ));
+    }
+
+    #[test]
+    fn test_htpasswd_and_rules() {
+        let htpasswd = Htpasswd::parse("# comment\nalice:$2y$05$abc\n\nbob:$argon2id$x\n");
+        assert_eq!(htpasswd.users.len(), 2);
+        assert_eq!(htpasswd.users["alice"], "$2y$05$abc");
+
+        let rules: Vec<AuthRule> = toml::from_str::<HashMap<String, Vec<AuthRule>>>(
+            r#"
+            [[auth]]
+            path = "/pub/prerelease"
+            tokens = ["t"]
+            [[auth]]
+            path = "/pub/prerelease/internal"
+            htpasswd = "/etc/htpasswd"
+            "#,
+        )
+        .unwrap()
+        .remove("auth")
+        .unwrap();
+        assert_eq!(
+            rule_for(&rules, "/pub/prerelease/internal/x.iso").map(|r| r.path.as_str()),
+            Some("/pub/prerelease/internal")
+        );
+        assert_eq!(
+            rule_for(&rules, "/pub/prerelease").map(|r| r.path.as_str()),
+            Some("/pub/prerelease")
+        );
+        assert!(rule_for(&rules, "/pub/prerelease-old/").is_none());
+        assert_eq!(
+            challenge(&rules[1]),
+            r#"Basic realm="Restricted", charset="UTF-8""#
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::time::SystemTime;
+
+use actix_web::{
+    http::header::{
+        self, CacheControl, CacheDirective, EntityTag, Header, HttpDate, IfModifiedSince,
+        IfNoneMatch,
+    },
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use sha2::{Digest, Sha256};
+
+use crate::{i18n::Locale, storage::StorageEntry};
+
+/// 目录列表的缓存校验信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validators {
+    pub etag: EntityTag,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// 由目录项集合计算弱ETag，Last-Modified取最新的目录项修改时间。
+    ///
+    /// 渲染结果还取决于语言和维护横幅，因此它们也参与ETag的计算。
+    pub fn for_listing(entries: &[StorageEntry], locale: Locale, banner: Option<&str>) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(locale.tag());
+        if let Some(banner) = banner {
+            hasher.update([1]);
+            hasher.update(banner);
+        }
+        for e in entries {
+            hasher.update([0]);
+            hasher.update(&e.name);
+            hasher.update([0]);
+            hasher.update(e.size.map(|s| s as u64).unwrap_or(u64::MAX).to_le_bytes());
+            let mtime = e
+                .modified
+                .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            hasher.update(mtime.to_le_bytes());
+        }
+        let digest = hex::encode(&hasher.finalize()[..12]);
+
+        Self {
+            etag: EntityTag::new_weak(digest),
+            last_modified: entries.iter().filter_map(|e| e.modified).max(),
+        }
+    }
+
+    /// 按RFC 9110判断是否可以返回304：有`If-None-Match`时只比较ETag，否则比较`If-Modified-Since`
+    pub fn is_not_modified(&self, req: &HttpRequest) -> bool {
+        if req.headers().contains_key(header::IF_NONE_MATCH) {
+            return match IfNoneMatch::parse(req) {
+                Ok(IfNoneMatch::Any) => true,
+                Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(&self.etag)),
+                Err(_) => false,
+            };
+        }
+
+        match (IfModifiedSince::parse(req), self.last_modified) {
+            (Ok(IfModifiedSince(since)), Some(last_modified)) => {
+                // HTTP日期只精确到秒
+                truncate_to_secs(last_modified) <= SystemTime::from(since)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn apply(&self, builder: &mut HttpResponseBuilder) {
+        builder.insert_header(header::ETag(self.etag.clone()));
+        if let Some(last_modified) = self.last_modified {
+            builder.insert_header(header::LastModified(HttpDate::from(last_modified)));
+        }
+    }
+
+    pub fn not_modified(&self, max_age: u64, private: bool) -> HttpResponse {
+        let mut builder = HttpResponse::NotModified();
+        self.apply(&mut builder);
+        if private {
+            builder.insert_header(private_cache_control(max_age));
+        } else {
+            builder.insert_header(cache_control(max_age));
+        }
+        builder.insert_header((header::VARY, "Accept-Language, Cookie, Accept-Encoding"));
+        builder.finish()
+    }
+}
+
+/// `max_age`为0时不允许缓存
+pub fn cache_control(max_age: u64) -> CacheControl {
+    if max_age == 0 {
+        CacheControl(vec![CacheDirective::NoCache])
+    } else {
+        CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(max_age as u32),
+        ])
+    }
+}
+
+/// 只允许浏览器缓存，用于需要身份验证的响应
+pub fn private_cache_control(max_age: u64) -> CacheControl {
+    if max_age == 0 {
+        CacheControl(vec![CacheDirective::Private, CacheDirective::NoCache])
+    } else {
+        CacheControl(vec![
+            CacheDirective::Private,
+            CacheDirective::MaxAge(max_age as u32),
+        ])
+    }
+}
+
+fn truncate_to_secs(t: SystemTime) -> SystemTime {
+    match t.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(d.as_secs()),
+        Err(_) => t,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn entries() -> Vec<StorageEntry> {
+        vec![
+            StorageEntry {
+                name: "a.iso".to_string(),
+                url: "/a.iso".to_string(),
+                modified: Some(SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_500)),
+                size: Some(10),
+                approximate_size: false,
+            },
+            StorageEntry {
+                name: "b".to_string(),
+                url: "/b/".to_string(),
+                modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)),
+                size: None,
+                approximate_size: false,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_etag_depends_on_entries_and_locale() {
+        let v1 = Validators::for_listing(&entries(), Locale::ZhCn, None);
+        assert!(v1.etag.weak);
+        assert_eq!(v1, Validators::for_listing(&entries(), Locale::ZhCn, None));
+        assert_ne!(
+            v1.etag,
+            Validators::for_listing(&entries(), Locale::En, None).etag
+        );
+
+        assert_ne!(
+            v1.etag,
+            Validators::for_listing(&entries(), Locale::ZhCn, Some("维护中")).etag
+        );
+
+        let mut changed = entries();
+        changed[0].size = Some(11);
+        assert_ne!(
+            v1.etag,
+            Validators::for_listing(&changed, Locale::ZhCn, None).etag
+        );
+    }
+
+    #[test]
+    fn test_conditional_requests() {
+        let v = Validators::for_listing(&entries(), Locale::ZhCn, None);
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, v.etag.to_string()))
+            .to_http_request();
+        assert!(v.is_not_modified(&req));
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, "W/\"other\""))
+            .insert_header((header::IF_MODIFIED_SINCE, "Sun, 01 Jan 2040 00:00:00 GMT"))
+            .to_http_request();
+        assert!(!v.is_not_modified(&req));
+
+        let since = HttpDate::from(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let req = TestRequest::default()
+            .insert_header((header::IF_MODIFIED_SINCE, since.to_string()))
+            .to_http_request();
+        assert!(v.is_not_modified(&req));
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_MODIFIED_SINCE, "Thu, 01 Jan 2015 00:00:00 GMT"))
+            .to_http_request();
+        assert!(!v.is_not_modified(&req));
+    }
+}
@@ -0,0 +1,228 @@
+use actix_files::NamedFile;
+use actix_http::encoding::Encoder;
+use actix_web::{
+    body::{BodySize, MessageBody},
+    dev::{ResponseHead, ServiceRequest, ServiceResponse},
+    http::{
+        header::{self, AcceptEncoding, ContentEncoding, Encoding, EntityTag, Header},
+        StatusCode,
+    },
+    middleware::Next,
+    HttpRequest,
+};
+
+use crate::{
+    config::{self, CompressionConfig},
+    storage::{StorageProvider, StorageResult},
+};
+
+/// 本地存储中预压缩文件的编码和后缀，按优先级排列
+const PRECOMPRESSED: [(ContentEncoding, &str); 2] = [
+    (ContentEncoding::Brotli, "br"),
+    (ContentEncoding::Gzip, "gz"),
+];
+
+/// 实时压缩支持的编码，按服务端偏好排列
+const SUPPORTED: [Encoding; 4] = [
+    Encoding::brotli(),
+    Encoding::zstd(),
+    Encoding::gzip(),
+    Encoding::identity(),
+];
+
+/// 对目录列表、错误页面和文本文件等可压缩的响应进行gzip/brotli/zstd压缩。
+///
+/// 已经带有`Content-Encoding`的响应（例如预压缩文件）和范围响应不会被压缩，
+/// 压缩后的响应去掉`Accept-Ranges`。
+pub async fn compress(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let accept = AcceptEncoding::parse(&req).ok();
+    let res = next.call(req).await?;
+    let config = config::current();
+
+    Ok(res.map_body(|head, body| {
+        let encoding = select_encoding(head, body.size(), accept.as_ref(), &config.compression);
+        Encoder::response(encoding, head, body)
+    }))
+}
+
+/// 选择响应使用的编码，并为可压缩的响应设置`Vary`和弱ETag
+fn select_encoding(
+    head: &mut ResponseHead,
+    size: BodySize,
+    accept: Option<&AcceptEncoding>,
+    config: &CompressionConfig,
+) -> ContentEncoding {
+    if !config.enabled
+        || matches!(
+            head.status,
+            StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED | StatusCode::NO_CONTENT
+        )
+        || head.headers.contains_key(header::CONTENT_ENCODING)
+        || head.headers.contains_key(header::CONTENT_RANGE)
+        || !is_compressible(head)
+    {
+        return ContentEncoding::Identity;
+    }
+    if let BodySize::Sized(size) = size {
+        if size < config.min_size {
+            return ContentEncoding::Identity;
+        }
+    }
+
+    let encoding = match accept.and_then(|a| a.negotiate(SUPPORTED.iter())) {
+        Some(Encoding::Known(encoding)) => encoding,
+        _ => ContentEncoding::Identity,
+    };
+    if encoding == ContentEncoding::Identity {
+        // Encoder只在实际压缩时添加Vary
+        head.headers.append(
+            header::VARY,
+            header::HeaderValue::from_static("Accept-Encoding"),
+        );
+    } else {
+        weaken_etag(head);
+        // 范围请求返回的是未压缩的字节，与压缩后的内容对不上，不再声明支持范围请求
+        head.headers.remove(header::ACCEPT_RANGES);
+    }
+    encoding
+}
+
+/// 压缩后的内容与原文件不再逐字节一致，强ETag需要改为弱ETag
+fn weaken_etag(head: &mut ResponseHead) {
+    let etag = head
+        .headers
+        .get(header::ETAG)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.parse::<EntityTag>().ok());
+    if let Some(etag) = etag.filter(|e| !e.weak) {
+        if let Ok(value) = EntityTag::new_weak(etag.tag().to_string())
+            .to_string()
+            .parse()
+        {
+            head.headers.insert(header::ETAG, value);
+        }
+    }
+}
+
+fn is_compressible(head: &ResponseHead) -> bool {
+    head.headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .map(is_compressible_type)
+        .unwrap_or(false)
+}
+
+/// 文本类的内容类型才值得压缩，压缩包、镜像和多媒体文件本身已经是压缩过的
+pub fn is_compressible_type(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/json" | "application/xml" | "application/javascript"
+        )
+}
+
+/// 如果客户端接受并且存在预压缩的`<文件>.br`/`<文件>.gz`，返回预压缩文件及其编码
+pub async fn open_precompressed(
+    provider: &dyn StorageProvider,
+    path_in_provider: &str,
+    req: &HttpRequest,
+) -> StorageResult<Option<(NamedFile, ContentEncoding)>> {
+    let config = config::current();
+    let content_type = mime_guess::from_path(path_in_provider).first_or_octet_stream();
+    if !config.compression.precompressed || !is_compressible_type(content_type.as_ref()) {
+        return Ok(None);
+    }
+    let accept = match AcceptEncoding::parse(req) {
+        Ok(accept) => accept,
+        Err(_) => return Ok(None),
+    };
+
+    for (encoding, ext) in PRECOMPRESSED {
+        let candidates = [Encoding::Known(encoding), Encoding::identity()];
+        if accept.negotiate(candidates.iter()) != Some(Encoding::Known(encoding)) {
+            continue;
+        }
+        let sibling = format!("{}.{}", path_in_provider, ext);
+        if let Some(file) = provider.stream_file(&sibling).await? {
+            log::debug!("Serving precompressed {} for {}", sibling, path_in_provider);
+            return Ok(Some((file, encoding)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body::BoxBody, HttpResponse};
+
+    use super::*;
+
+    fn head(content_type: &str) -> ResponseHead {
+        let res: HttpResponse<BoxBody> = HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((header::ETAG, "\"abc\""))
+            .insert_header((header::ACCEPT_RANGES, "bytes"))
+            .finish();
+        res.head().clone()
+    }
+
+    fn accept(value: &str) -> AcceptEncoding {
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((header::ACCEPT_ENCODING, value))
+            .to_http_request();
+        AcceptEncoding::parse(&req).unwrap()
+    }
+
+    #[test]
+    fn test_select_encoding() {
+        let config = CompressionConfig::default();
+        let size = BodySize::Sized(4096);
+
+        let mut h = head("text/html; charset=utf-8");
+        let enc = select_encoding(&mut h, size, Some(&accept("gzip, br")), &config);
+        assert_eq!(enc, ContentEncoding::Brotli);
+        assert_eq!(h.headers.get(header::ETAG).unwrap(), "W/\"abc\"");
+        assert!(h.headers.get(header::ACCEPT_RANGES).is_none());
+
+        let mut h = head("text/plain");
+        let enc = select_encoding(&mut h, size, Some(&accept("gzip;q=1, br;q=0.5")), &config);
+        assert_eq!(enc, ContentEncoding::Gzip);
+
+        // 不接受压缩时仍然需要Vary，以免缓存把未压缩版本返回给支持压缩的客户端
+        let mut h = head("text/plain");
+        let enc = select_encoding(&mut h, size, None, &config);
+        assert_eq!(enc, ContentEncoding::Identity);
+        assert_eq!(h.headers.get(header::VARY).unwrap(), "Accept-Encoding");
+        assert_eq!(h.headers.get(header::ACCEPT_RANGES).unwrap(), "bytes");
+
+        let mut h = head("application/x-iso9660-image");
+        let enc = select_encoding(&mut h, size, Some(&accept("gzip")), &config);
+        assert_eq!(enc, ContentEncoding::Identity);
+        assert!(h.headers.get(header::VARY).is_none());
+
+        let mut h = head("text/plain");
+        let enc = select_encoding(&mut h, BodySize::Sized(10), Some(&accept("gzip")), &config);
+        assert_eq!(enc, ContentEncoding::Identity);
+    }
+
+    #[test]
+    fn test_is_compressible_type() {
+        assert!(is_compressible_type("text/html; charset=utf-8"));
+        assert!(is_compressible_type("application/json"));
+        assert!(is_compressible_type("application/problem+json"));
+        assert!(is_compressible_type("image/svg+xml"));
+        assert!(!is_compressible_type("application/gzip"));
+        assert!(!is_compressible_type("application/octet-stream"));
+    }
+}
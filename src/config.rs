@@ -1,32 +1,281 @@
-use serde::Deserialize;
-use std::{collections::HashSet, path::Path};
+use crate::maintenance::MaintenanceWindow;
+use globset::{GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{Arc, RwLock},
+};
 use tokio::fs;
 
+lazy_static! {
+    static ref CURRENT: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+}
+
+/// 配置文件路径，管理接口重新加载配置时从这里读取
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// 当前生效的配置。通过管理接口重新加载后，之后的请求使用新的配置
+pub fn current() -> Arc<Config> {
+    CURRENT
+        .read()
+        .unwrap()
+        .clone()
+        .expect("Config not initialized")
+}
+
+/// 替换当前生效的配置
+pub fn set_current(config: Config) {
+    *CURRENT.write().unwrap() = Some(Arc::new(config));
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub storage: StorageConfig,
     pub download_rules: DownloadRules,
+    #[serde(default)]
+    pub aliases: Vec<AliasRule>,
+    #[serde(default)]
+    pub listing: ListingConfig,
+    #[serde(default)]
+    pub site: SiteConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceWindow>,
+    #[serde(default)]
+    pub auth: Vec<AuthRule>,
+    #[serde(default)]
+    pub signed_links: Option<SignedLinkConfig>,
+    #[serde(default)]
+    pub upload: Vec<UploadRule>,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    #[serde(default)]
+    pub rsync: Option<RsyncConfig>,
+    #[serde(default)]
+    pub sync: Vec<SyncJob>,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
 pub enum StorageBackend {
     #[serde(rename = "local")]
     Local,
-    #[serde(rename = "nginx")]
-    Nginx,
+    /// 通过HTTP目录列表浏览的源站（nginx、Apache、Caddy、lighttpd等），兼容旧的`nginx`写法
+    #[serde(rename = "http", alias = "nginx")]
+    HttpIndex,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct StorageConfig {
     pub backend: StorageBackend,
     pub local: Option<LocalStorageConfig>,
-    pub nginx: Option<NginxStorageConfig>,
+    #[serde(alias = "nginx")]
+    pub http: Option<HttpIndexStorageConfig>,
+    /// 存储暂时不可用或限流、且源站没有给出`Retry-After`时，响应中`Retry-After`的秒数
+    #[serde(default = "default_retry_after")]
+    pub retry_after: u64,
+}
+
+fn default_retry_after() -> u64 {
+    60
 }
 
 #[derive(Debug, Deserialize)]
-pub struct NginxStorageConfig {
+pub struct HttpIndexStorageConfig {
     pub base_url: String,
     pub public_url: String, // 用于对外返回的url
+    /// 配置后重定向地址带上nginx `secure_link`模块可以校验的`md5`和`expires`参数
+    #[serde(default)]
+    pub secure_link: Option<SecureLinkConfig>,
+    /// 源站目录列表的格式，默认根据响应自动识别
+    #[serde(default)]
+    pub autoindex_format: AutoindexFormat,
+    /// 目录列表中不带时区的时间所用的时区，例如nginx开启`autoindex_localtime`时的`+08:00`
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// 列表缺少大小或修改时间时，每次列目录最多发出的HEAD请求数，0表示不发出
+    #[serde(default = "default_head_requests")]
+    pub head_requests: usize,
+    /// 访问源站的HTTP客户端设置
+    #[serde(default)]
+    pub client: UpstreamClientConfig,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_head_requests() -> usize {
+    64
+}
+
+/// 访问源站或上游的HTTP客户端设置，每个后端和同步任务共用一个带连接池的客户端
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpstreamClientConfig {
+    /// 建立连接（含TLS握手）的超时（秒）
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+    /// 两次读取之间的最长等待（秒），0表示不限制
+    #[serde(default = "default_read_timeout")]
+    pub read_timeout: u64,
+    /// 单个请求从发出到读完响应的最长时间（秒），0表示不限制
+    #[serde(default)]
+    pub timeout: u64,
+    /// 每个源站最多保留的空闲连接数
+    #[serde(default = "default_pool_max_idle")]
+    pub pool_max_idle_per_host: usize,
+    /// 空闲连接保留的时间（秒）
+    #[serde(default = "default_pool_idle_timeout")]
+    pub pool_idle_timeout: u64,
+    #[serde(default)]
+    pub http_version: HttpVersion,
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+    /// 每个请求都带上的请求头，例如源站要求的认证头
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// 出站代理，支持`http://`、`https://`、`socks5://`和`socks5h://`
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// 额外信任的CA证书（PEM文件，可以包含多个证书），用于自签名的源站
+    #[serde(default)]
+    pub ca_certs: Vec<String>,
+    /// 幂等请求（GET、HEAD）遇到连接错误、超时、429或5xx时的重试次数，0表示不重试
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// 第一次重试前的最长等待（毫秒），之后每次翻倍，实际等待时间在0到该值之间随机
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+    /// 重试等待的上限（毫秒），源站`Retry-After`要求的时间超过该值时不再重试
+    #[serde(default = "default_retry_max_backoff_ms")]
+    pub retry_max_backoff_ms: u64,
+    /// 同一源站连续失败多少次（包括重试）后熔断，熔断期间的请求直接失败，0表示不熔断
+    #[serde(default = "default_breaker_threshold")]
+    pub breaker_threshold: u32,
+    /// 熔断持续的时间（秒），之后放行一个请求试探源站是否恢复
+    #[serde(default = "default_breaker_cooldown")]
+    pub breaker_cooldown: u64,
+}
+
+fn default_connect_timeout() -> u64 {
+    10
+}
+
+fn default_read_timeout() -> u64 {
+    30
+}
+
+fn default_pool_max_idle() -> usize {
+    32
+}
+
+fn default_pool_idle_timeout() -> u64 {
+    90
+}
+
+fn default_retries() -> u32 {
+    2
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    200
+}
+
+fn default_retry_max_backoff_ms() -> u64 {
+    5000
+}
+
+fn default_breaker_threshold() -> u32 {
+    5
+}
+
+fn default_breaker_cooldown() -> u64 {
+    30
+}
+
+fn default_user_agent() -> String {
+    format!("mirror-proxy/{}", env!("CARGO_PKG_VERSION"))
+}
+
+impl Default for UpstreamClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: default_connect_timeout(),
+            read_timeout: default_read_timeout(),
+            timeout: 0,
+            pool_max_idle_per_host: default_pool_max_idle(),
+            pool_idle_timeout: default_pool_idle_timeout(),
+            http_version: HttpVersion::default(),
+            user_agent: default_user_agent(),
+            headers: HashMap::new(),
+            proxy: None,
+            ca_certs: Vec::new(),
+            retries: default_retries(),
+            retry_backoff_ms: default_retry_backoff_ms(),
+            retry_max_backoff_ms: default_retry_max_backoff_ms(),
+            breaker_threshold: default_breaker_threshold(),
+            breaker_cooldown: default_breaker_cooldown(),
+        }
+    }
+}
+
+/// 与源站之间使用的HTTP版本
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum HttpVersion {
+    /// https通过ALPN协商，http使用HTTP/1.1
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "http1")]
+    Http1,
+    /// 直接使用HTTP/2（prior knowledge），源站必须支持，明文的h2c也可以使用
+    #[serde(rename = "http2")]
+    Http2,
+}
+
+/// 源站目录列表的格式，`html`、`json`、`xml`与nginx的`autoindex_format`对应
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum AutoindexFormat {
+    /// 根据响应的`Content-Type`识别，无法识别时按HTML解析
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "html")]
+    Html,
+    #[serde(rename = "json")]
+    Json,
+    #[serde(rename = "xml")]
+    Xml,
+    /// Apache `mod_autoindex`，支持`FancyIndexing`（表格或`<pre>`）和普通列表
+    #[serde(rename = "apache")]
+    Apache,
+    /// Caddy `file_server browse`的json列表
+    #[serde(rename = "caddy")]
+    Caddy,
+    /// lighttpd `mod_dirlisting`
+    #[serde(rename = "lighttpd")]
+    Lighttpd,
+    /// Python `http.server`，列表中没有大小和修改时间
+    #[serde(rename = "python")]
+    Python,
+}
+
+/// nginx `secure_link`设置，需要与nginx中`secure_link_md5`的表达式保持一致
+#[derive(Debug, Clone, Deserialize)]
+pub struct SecureLinkConfig {
+    /// `secure_link_md5`表达式，支持`$secure_link_expires`、`$uri`和`$remote_addr`，
+    /// 例如`"$secure_link_expires$uri$remote_addr secret"`
+    pub md5: String,
+    /// 重定向地址的有效期（秒），应当大于`[cache] redirect_max_age`
+    #[serde(default = "default_link_ttl")]
+    pub ttl: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,6 +286,493 @@ pub struct LocalStorageConfig {
 #[derive(Debug, Deserialize)]
 pub struct DownloadRules {
     pub extensions: HashSet<String>,
+    /// 在浏览器中直接打开（`Content-Disposition: inline`）的文件后缀，其余文件作为附件下载
+    #[serde(default)]
+    pub inline_extensions: HashSet<String>,
+}
+
+/// 站点信息，作为`site`变量提供给所有模板
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SiteConfig {
+    #[serde(default = "default_site_name")]
+    pub name: String,
+    /// 站点logo的URL，为空时不显示
+    #[serde(default)]
+    pub logo: Option<String>,
+    #[serde(default = "default_contact_email")]
+    pub contact_email: String,
+    #[serde(default = "default_copyright")]
+    pub copyright: String,
+    /// 页脚中额外显示的链接
+    #[serde(default)]
+    pub footer_links: Vec<FooterLink>,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            name: default_site_name(),
+            logo: None,
+            contact_email: default_contact_email(),
+            copyright: default_copyright(),
+            footer_links: Vec::new(),
+        }
+    }
+}
+
+fn default_site_name() -> String {
+    "DragonOS 镜像站".to_string()
+}
+
+fn default_contact_email() -> String {
+    "contact@dragonos.org".to_string()
+}
+
+fn default_copyright() -> String {
+    "©2022-2025 DragonOS Community".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FooterLink {
+    pub title: String,
+    pub url: String,
+}
+
+/// 主题配置
+#[derive(Debug, Deserialize, Default)]
+pub struct ThemeConfig {
+    /// 主题目录。目录中的`index.html`、`autoindex.html`、`error.html`会在运行时加载
+    /// 并覆盖内置模板，`assets/`子目录中的文件会优先于内置资源在`/assets`下提供。
+    pub dir: Option<String>,
+}
+
+/// 目录列表页面的配置
+#[derive(Debug, Deserialize)]
+pub struct ListingConfig {
+    /// 渲染在文件列表上方的说明文件，按顺序查找第一个存在的
+    #[serde(default = "default_header_files")]
+    pub header_files: Vec<String>,
+    /// 渲染在文件列表下方的说明文件，按顺序查找第一个存在的
+    #[serde(default = "default_readme_files")]
+    pub readme_files: Vec<String>,
+    /// 说明文件的最大字节数，超过则不渲染
+    #[serde(default = "default_max_note_size")]
+    pub max_note_size: usize,
+}
+
+impl Default for ListingConfig {
+    fn default() -> Self {
+        Self {
+            header_files: default_header_files(),
+            readme_files: default_readme_files(),
+            max_note_size: default_max_note_size(),
+        }
+    }
+}
+
+fn default_header_files() -> Vec<String> {
+    vec!["HEADER.html".to_string(), "HEADER.md".to_string()]
+}
+
+fn default_readme_files() -> Vec<String> {
+    vec![
+        "README.md".to_string(),
+        "README.html".to_string(),
+        "README.txt".to_string(),
+        ".message".to_string(),
+    ]
+}
+
+fn default_max_note_size() -> usize {
+    64 * 1024
+}
+
+/// 缓存相关的响应头配置
+#[derive(Debug, Deserialize)]
+pub struct CacheConfig {
+    /// 目录列表的`Cache-Control: max-age`（秒）
+    #[serde(default = "default_listing_max_age")]
+    pub listing_max_age: u64,
+    /// 下载重定向的`Cache-Control: max-age`（秒）
+    #[serde(default = "default_redirect_max_age")]
+    pub redirect_max_age: u64,
+    /// 存储目录列表和目录说明在内存中的缓存时间（秒），0表示不缓存
+    #[serde(default = "default_storage_ttl")]
+    pub storage_ttl: u64,
+    /// 按路径覆盖上面的默认值，按顺序使用第一条匹配的规则
+    #[serde(default)]
+    pub rules: Vec<CacheRule>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            listing_max_age: default_listing_max_age(),
+            redirect_max_age: default_redirect_max_age(),
+            storage_ttl: default_storage_ttl(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl CacheConfig {
+    /// 返回请求路径适用的(目录列表max-age, 重定向max-age)
+    pub fn max_ages(&self, full_path: &str) -> (u64, u64) {
+        match self.rules.iter().find(|r| r.path.matches(full_path)) {
+            Some(rule) => (
+                rule.listing_max_age.unwrap_or(self.listing_max_age),
+                rule.redirect_max_age.unwrap_or(self.redirect_max_age),
+            ),
+            None => (self.listing_max_age, self.redirect_max_age),
+        }
+    }
+}
+
+/// 需要身份验证才能访问的路径
+#[derive(Debug, Deserialize)]
+pub struct AuthRule {
+    /// 受保护的路径前缀，例如`/pub/prerelease`
+    pub path: String,
+    /// 浏览器登录框中显示的名称
+    #[serde(default = "default_auth_realm")]
+    pub realm: String,
+    /// htpasswd文件路径，用于HTTP Basic认证，支持bcrypt和argon2哈希
+    #[serde(default)]
+    pub htpasswd: Option<String>,
+    /// 允许访问的Bearer Token
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+fn default_auth_realm() -> String {
+    "Restricted".to_string()
+}
+
+/// 管理接口配置，未配置时不启动管理接口。监听地址和TLS设置修改后需要重启才能生效
+#[derive(Debug, Deserialize)]
+pub struct AdminConfig {
+    /// 管理接口的监听地址，应当只对内网开放，例如`127.0.0.1:8081`
+    pub listen: String,
+    /// 允许访问的Bearer Token，为空时只依靠TLS客户端证书认证
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    #[serde(default)]
+    pub tls: Option<AdminTlsConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminTlsConfig {
+    /// PEM格式的证书链
+    pub cert: String,
+    /// PEM格式的私钥
+    pub key: String,
+    /// 用于验证客户端证书的CA（PEM格式），配置后启用双向TLS
+    #[serde(default)]
+    pub client_ca: Option<String>,
+}
+
+/// 自动清理旧文件的配置
+#[derive(Debug, Deserialize)]
+pub struct RetentionConfig {
+    /// 后台任务执行清理的间隔（秒），0表示只能通过`retention`命令手动执行
+    #[serde(default = "default_retention_interval")]
+    pub interval: u64,
+    #[serde(default)]
+    pub rules: Vec<RetentionRule>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            interval: default_retention_interval(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// 一个目录的保留规则。目录中的直接子项（文件或目录）满足任意一个保留条件就会保留，其余的被删除；
+/// 没有配置`keep_last`和`keep_days`时不删除任何内容
+#[derive(Debug, Deserialize)]
+pub struct RetentionRule {
+    /// 要清理的目录（完整请求路径），例如`/pub/dragonos/nightly`
+    pub path: String,
+    /// 参与清理的名称模式，语法与别名相同，`{version}`捕获的版本号用于`sort = "semver"`
+    #[serde(default = "default_retention_pattern")]
+    pub pattern: String,
+    /// 排序方式，决定哪些是“最新的”
+    #[serde(default = "default_retention_sort")]
+    pub sort: AliasSort,
+    /// 保留最新的N项
+    #[serde(default)]
+    pub keep_last: Option<usize>,
+    /// 保留最近D天内修改过的项
+    #[serde(default)]
+    pub keep_days: Option<u64>,
+    /// 总是保留的名称模式，例如`["latest*"]`
+    #[serde(default)]
+    pub keep: Vec<String>,
+}
+
+fn default_retention_interval() -> u64 {
+    86400
+}
+
+fn default_retention_pattern() -> String {
+    "*".to_string()
+}
+
+fn default_retention_sort() -> AliasSort {
+    AliasSort::Date
+}
+
+/// 允许上传文件的目录，只有本地存储支持
+#[derive(Debug, Deserialize)]
+pub struct UploadRule {
+    /// 允许上传的路径前缀，例如`/pub/dragonos/nightly`，嵌套时路径最长的规则生效
+    pub path: String,
+    /// 允许上传的Bearer Token
+    pub tokens: Vec<String>,
+    /// 单个文件的最大大小（字节）
+    #[serde(default = "default_upload_max_size")]
+    pub max_size: u64,
+    /// 允许上传的文件后缀，为空时使用`download_rules.extensions`
+    #[serde(default)]
+    pub extensions: HashSet<String>,
+    /// 是否允许覆盖已有文件
+    #[serde(default)]
+    pub overwrite: bool,
+    /// 上传完成后是否清除所在目录的列表缓存
+    #[serde(default = "default_true")]
+    pub purge_cache: bool,
+}
+
+fn default_upload_max_size() -> u64 {
+    4 << 30
+}
+
+/// 从上游镜像同步到本地存储的任务
+#[derive(Debug, Clone, Deserialize)]
+pub struct SyncJob {
+    /// 任务名称，用于状态页面、命令行和管理接口
+    pub name: String,
+    /// 上游地址：HTTP目录列表的`http(s)://`地址，或者`rsync://`地址
+    pub upstream: String,
+    /// 同步到的请求路径，例如`/pub/dragonos`，必须由本地存储提供
+    pub path: String,
+    /// 同步间隔（秒），0表示只手动执行
+    #[serde(default = "default_sync_interval")]
+    pub interval: u64,
+    /// 只同步匹配这些模式的文件（相对于任务目录，例如`**/*.iso`），为空时同步全部文件
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// 不同步匹配这些模式的文件和目录，优先于`include`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// 是否删除上游已经不存在的文件
+    #[serde(default)]
+    pub delete: bool,
+    /// 一次最多删除本地文件的比例，超过时认为上游列表不完整，不删除任何文件并报错。1表示不限制
+    #[serde(default = "default_max_delete_ratio")]
+    pub max_delete_ratio: f64,
+    /// 传给rsync的额外参数，只用于`rsync://`上游
+    #[serde(default)]
+    pub rsync_options: Vec<String>,
+    /// 单次同步的最长时间（秒），0表示不限制
+    #[serde(default)]
+    pub timeout: u64,
+    /// 访问`http(s)://`上游的HTTP客户端设置
+    #[serde(default)]
+    pub client: UpstreamClientConfig,
+}
+
+fn default_sync_interval() -> u64 {
+    3600
+}
+
+fn default_max_delete_ratio() -> f64 {
+    0.5
+}
+
+/// 只读的rsync服务，只支持本地存储
+#[derive(Debug, Deserialize)]
+pub struct RsyncConfig {
+    /// 监听地址，例如`0.0.0.0:873`
+    pub listen: String,
+    /// 连接后显示给客户端的欢迎信息
+    #[serde(default)]
+    pub motd: Option<String>,
+    /// 同时处理的最大连接数，超过时拒绝新连接
+    #[serde(default = "default_rsync_max_connections")]
+    pub max_connections: usize,
+    /// 连接空闲超时（秒）
+    #[serde(default = "default_rsync_timeout")]
+    pub timeout: u64,
+    /// 对外提供的模块
+    #[serde(default)]
+    pub modules: Vec<RsyncModule>,
+}
+
+fn default_rsync_max_connections() -> usize {
+    16
+}
+
+fn default_rsync_timeout() -> u64 {
+    600
+}
+
+/// rsync模块，对应一个请求路径，例如`/pub/dragonos`
+#[derive(Debug, Clone, Deserialize)]
+pub struct RsyncModule {
+    pub name: String,
+    pub path: String,
+    /// 模块列表中显示的说明
+    #[serde(default)]
+    pub comment: String,
+}
+
+/// 带签名的限时下载链接配置
+#[derive(Debug, Deserialize)]
+pub struct SignedLinkConfig {
+    /// HMAC密钥，修改后之前签发的链接全部失效
+    pub secret: String,
+    /// `sign`命令默认的有效期（秒）
+    #[serde(default = "default_link_ttl")]
+    pub default_ttl: u64,
+    /// `sign`命令生成链接时使用的站点地址
+    #[serde(default = "default_signed_link_base_url")]
+    pub base_url: String,
+    /// 绑定客户端IP时是否信任`X-Forwarded-For`，只有位于可信的反向代理之后时才应开启
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+}
+
+fn default_link_ttl() -> u64 {
+    3600
+}
+
+fn default_signed_link_base_url() -> String {
+    "http://localhost:8080/".to_string()
+}
+
+/// 响应压缩配置
+#[derive(Debug, Deserialize)]
+pub struct CompressionConfig {
+    /// 是否对目录列表和文本文件进行实时压缩
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 小于该大小（字节）的响应不压缩
+    #[serde(default = "default_compression_min_size")]
+    pub min_size: u64,
+    /// 本地存储中存在`<文件>.br`/`<文件>.gz`时直接返回预压缩文件
+    #[serde(default = "default_true")]
+    pub precompressed: bool,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_size: default_compression_min_size(),
+            precompressed: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_compression_min_size() -> u64 {
+    1024
+}
+
+fn default_storage_ttl() -> u64 {
+    30
+}
+
+fn default_listing_max_age() -> u64 {
+    60
+}
+
+fn default_redirect_max_age() -> u64 {
+    3600
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CacheRule {
+    pub path: PathPattern,
+    pub listing_max_age: Option<u64>,
+    pub redirect_max_age: Option<u64>,
+}
+
+/// 请求路径的glob模式，例如`/pub/dragonos/**`。`*`不匹配`/`，`**`匹配任意层级的目录。
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    matcher: GlobMatcher,
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> anyhow::Result<Self> {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Invalid path pattern '{}': {}", pattern, e))?;
+        Ok(Self {
+            matcher: glob.compile_matcher(),
+        })
+    }
+
+    /// 目录路径带或不带结尾的`/`都可以匹配
+    pub fn matches(&self, path: &str) -> bool {
+        let trimmed = path.trim_end_matches('/');
+        self.matcher.is_match(path)
+            || self.matcher.is_match(trimmed)
+            || self.matcher.is_match(format!("{}/", trimmed))
+    }
+}
+
+impl<'de> Deserialize<'de> for PathPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        PathPattern::new(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+/// 稳定路径别名，例如将 /pub/dragonos/latest.iso 指向目录中最新的发行版
+#[derive(Debug, Deserialize)]
+pub struct AliasRule {
+    /// 对外暴露的完整请求路径
+    pub path: String,
+    /// 在哪个目录（完整请求路径）中查找匹配的文件
+    pub directory: String,
+    /// 文件名模式，`*`匹配任意字符，`{version}`捕获版本号
+    pub pattern: String,
+    #[serde(default)]
+    pub sort: AliasSort,
+    #[serde(default)]
+    pub action: AliasAction,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum AliasSort {
+    /// 按`{version}`捕获的版本号排序
+    #[default]
+    #[serde(rename = "semver")]
+    Semver,
+    /// 按文件修改时间排序
+    #[serde(rename = "date")]
+    Date,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum AliasAction {
+    /// 302重定向到真实文件
+    #[default]
+    #[serde(rename = "redirect")]
+    Redirect,
+    /// 直接返回真实文件的内容
+    #[serde(rename = "stream")]
+    Stream,
 }
 
 pub async fn load_config(path: &str) -> anyhow::Result<Config> {
@@ -45,6 +781,45 @@ pub async fn load_config(path: &str) -> anyhow::Result<Config> {
     Ok(config)
 }
 
+/// 测试共用的配置：本地存储，`/pub/prerelease`需要认证。配置是全局的，所有测试使用同一份
+#[cfg(test)]
+pub fn init_test_config() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        let root = std::env::temp_dir().join(format!("mirror-proxy-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let config = format!(
+            r#"
+[storage]
+backend = "local"
+[storage.local]
+root_path = "{}"
+[download_rules]
+extensions = ["iso"]
+[[auth]]
+path = "/pub/prerelease"
+tokens = ["t0ken"]
+[signed_links]
+secret = "s3cret"
+"#,
+            root.display()
+        );
+        set_current(toml::from_str(&config).unwrap());
+    });
+}
+
+/// 路径是否等于`prefix`或位于其下。按路径段比较，忽略`.`和空的路径段，
+/// 因此`/pub/a`不包含`/pub/ab`，而`/pub/./a/`和`/pub//a`都位于`/pub/a`之下
+pub fn is_under(full_path: &str, prefix: &str) -> bool {
+    let mut path = path_segments(full_path);
+    path_segments(prefix).all(|segment| path.next() == Some(segment))
+}
+
+fn path_segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+}
+
 pub fn has_matching_extension(path: &str, extensions: &HashSet<String>) -> bool {
     let path = Path::new(path);
     if let Some(ext) = path.extension() {
@@ -64,3 +839,49 @@ pub fn has_matching_extension(path: &str, extensions: &HashSet<String>) -> bool
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_under() {
+        assert!(is_under("/pub/prerelease", "/pub/prerelease"));
+        assert!(is_under("/pub/prerelease/x.iso", "/pub/prerelease/"));
+        assert!(!is_under("/pub/prerelease-old/x.iso", "/pub/prerelease"));
+        assert!(!is_under("/pub", "/pub/prerelease"));
+        assert!(is_under("/pub/./prerelease/x.iso", "/pub/prerelease"));
+        assert!(is_under(
+            "/pub//prerelease//internal/x",
+            "/pub/prerelease/internal"
+        ));
+    }
+
+    #[test]
+    fn test_path_pattern() {
+        let p = PathPattern::new("/pub/nightly/**").unwrap();
+        assert!(p.matches("/pub/nightly"));
+        assert!(p.matches("/pub/nightly/"));
+        assert!(p.matches("/pub/nightly/2025/a.img"));
+        assert!(!p.matches("/pub/nightly-old/a.img"));
+
+        let p = PathPattern::new("/pub/*/latest.iso").unwrap();
+        assert!(p.matches("/pub/dragonos/latest.iso"));
+        assert!(!p.matches("/pub/a/b/latest.iso"));
+    }
+
+    #[test]
+    fn test_cache_rules() {
+        let config: CacheConfig = toml::from_str(
+            r#"
+            listing_max_age = 30
+            [[rules]]
+            path = "/pub/nightly/**"
+            listing_max_age = 5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.max_ages("/pub/nightly/"), (5, 3600));
+        assert_eq!(config.max_ages("/pub/release/"), (30, 3600));
+    }
+}
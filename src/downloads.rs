@@ -0,0 +1,90 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use serde::Serialize;
+
+lazy_static! {
+    static ref ACTIVE: Mutex<HashMap<u64, Arc<Download>>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 正在进行的本地文件下载
+struct Download {
+    id: u64,
+    path: String,
+    client: String,
+    started: SystemTime,
+    total: u64,
+    sent: AtomicU64,
+}
+
+/// 下载状态的快照，由管理接口返回
+#[derive(Debug, Serialize)]
+pub struct DownloadInfo {
+    pub id: u64,
+    pub path: String,
+    pub client: String,
+    /// 开始时间（Unix时间戳，秒）
+    pub started: u64,
+    pub bytes_sent: u64,
+    pub total_bytes: u64,
+}
+
+/// 下载的登记凭据，响应体发送结束或连接断开时被丢弃，随之从活动下载中移除
+pub struct DownloadGuard(Arc<Download>);
+
+impl DownloadGuard {
+    pub fn add_sent(&self, n: u64) {
+        self.0.sent.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+impl Drop for DownloadGuard {
+    fn drop(&mut self) {
+        ACTIVE.lock().unwrap().remove(&self.0.id);
+    }
+}
+
+/// 登记一次新的下载，`total`为响应体的字节数
+pub fn start(path: &str, client: &str, total: u64) -> DownloadGuard {
+    let download = Arc::new(Download {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        path: path.to_string(),
+        client: client.to_string(),
+        started: SystemTime::now(),
+        total,
+        sent: AtomicU64::new(0),
+    });
+    ACTIVE.lock().unwrap().insert(download.id, download.clone());
+    DownloadGuard(download)
+}
+
+/// 当前所有活动下载，按开始时间排序
+pub fn active() -> Vec<DownloadInfo> {
+    let mut downloads: Vec<DownloadInfo> = ACTIVE
+        .lock()
+        .unwrap()
+        .values()
+        .map(|d| DownloadInfo {
+            id: d.id,
+            path: d.path.clone(),
+            client: d.client.clone(),
+            started: d
+                .started
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            bytes_sent: d.sent.load(Ordering::Relaxed),
+            total_bytes: d.total,
+        })
+        .collect();
+    downloads.sort_by_key(|d| d.id);
+    downloads
+}
@@ -1,136 +1,566 @@
-use actix_web::HttpResponse;
-use askama::Template;
+use std::time::Duration;
 
-use crate::render::ErrorTemplate;
+use actix_web::{
+    http::{header, StatusCode},
+    HttpRequest, HttpResponse,
+};
+use serde::Serialize;
+
+use crate::{
+    i18n::{Locale, Message},
+    render::{self, theme, ErrorTemplate},
+    request_id::RequestId,
+    storage::StorageError,
+};
 
 #[derive(Debug, Clone)]
 pub enum HttpError {
+    Unauthorized {
+        message: Message,
+        description: Message,
+        /// `WWW-Authenticate`响应头的值
+        challenge: String,
+    },
     Forbidden {
-        message: String,
-        description: String,
+        message: Message,
+        description: Message,
     },
     NotFound {
-        message: String,
-        description: String,
+        message: Message,
+        description: Message,
     },
     BadRequest {
-        message: String,
-        description: String,
+        message: Message,
+        description: Message,
+    },
+    MethodNotAllowed {
+        message: Message,
+        description: Message,
+    },
+    RangeNotSatisfiable {
+        message: Message,
+        description: Message,
+    },
+    PreconditionFailed {
+        message: Message,
+        description: Message,
+    },
+    Conflict {
+        message: Message,
+        description: Message,
+    },
+    PayloadTooLarge {
+        message: Message,
+        description: Message,
+    },
+    TooManyRequests {
+        message: Message,
+        description: Message,
+        retry_after: Option<Duration>,
     },
     InternalServerError {
-        message: String,
-        description: String,
+        message: Message,
+        description: Message,
+    },
+    BadGateway {
+        message: Message,
+        description: Message,
+    },
+    ServiceUnavailable {
+        message: Message,
+        description: Message,
+        retry_after: Option<Duration>,
+    },
+    GatewayTimeout {
+        message: Message,
+        description: Message,
     },
 }
 
 impl HttpError {
-    pub fn forbidden(message: &str, description: &str) -> Self {
+    pub fn unauthorized(message: Message, description: Message, challenge: String) -> Self {
+        Self::Unauthorized {
+            message,
+            description,
+            challenge,
+        }
+    }
+
+    pub fn forbidden(message: Message, description: Message) -> Self {
         Self::Forbidden {
-            message: message.to_string(),
-            description: description.to_string(),
+            message,
+            description,
         }
     }
 
-    pub fn not_found(message: &str, description: &str) -> Self {
+    pub fn not_found(message: Message, description: Message) -> Self {
         Self::NotFound {
-            message: message.to_string(),
-            description: description.to_string(),
+            message,
+            description,
         }
     }
 
-    pub fn bad_request(message: &str, description: &str) -> Self {
+    pub fn bad_request(message: Message, description: Message) -> Self {
         Self::BadRequest {
-            message: message.to_string(),
-            description: description.to_string(),
+            message,
+            description,
+        }
+    }
+
+    pub fn method_not_allowed(message: Message, description: Message) -> Self {
+        Self::MethodNotAllowed {
+            message,
+            description,
+        }
+    }
+
+    pub fn range_not_satisfiable(message: Message, description: Message) -> Self {
+        Self::RangeNotSatisfiable {
+            message,
+            description,
+        }
+    }
+
+    pub fn precondition_failed(message: Message, description: Message) -> Self {
+        Self::PreconditionFailed {
+            message,
+            description,
         }
     }
 
-    pub fn internal_error(message: &str, description: &str) -> Self {
+    pub fn conflict(message: Message, description: Message) -> Self {
+        Self::Conflict {
+            message,
+            description,
+        }
+    }
+
+    pub fn payload_too_large(message: Message, description: Message) -> Self {
+        Self::PayloadTooLarge {
+            message,
+            description,
+        }
+    }
+
+    pub fn too_many_requests(
+        message: Message,
+        description: Message,
+        retry_after: Option<Duration>,
+    ) -> Self {
+        Self::TooManyRequests {
+            message,
+            description,
+            retry_after,
+        }
+    }
+
+    pub fn internal_error(message: Message, description: Message) -> Self {
         Self::InternalServerError {
-            message: message.to_string(),
-            description: description.to_string(),
+            message,
+            description,
+        }
+    }
+
+    pub fn bad_gateway(message: Message, description: Message) -> Self {
+        Self::BadGateway {
+            message,
+            description,
+        }
+    }
+
+    pub fn service_unavailable(
+        message: Message,
+        description: Message,
+        retry_after: Option<Duration>,
+    ) -> Self {
+        Self::ServiceUnavailable {
+            message,
+            description,
+            retry_after,
+        }
+    }
+
+    pub fn gateway_timeout(message: Message, description: Message) -> Self {
+        Self::GatewayTimeout {
+            message,
+            description,
+        }
+    }
+
+    /// 将存储错误转换为对应的HTTP错误，`Other`类错误使用`fallback`作为描述返回500
+    pub fn from_storage(e: &StorageError, fallback: Message) -> Self {
+        // 源站没有给出等待时间时使用配置的默认值，客户端不必猜测何时重试
+        let or_default = |retry_after: &Option<Duration>| {
+            retry_after.or_else(|| {
+                Some(Duration::from_secs(
+                    crate::config::current().storage.retry_after,
+                ))
+            })
+        };
+        match e {
+            StorageError::Connect(_) | StorageError::BadUpstream(_) => {
+                Self::bad_gateway(Message::BadGateway, Message::StorageUnavailable)
+            }
+            StorageError::Timeout(_) => {
+                Self::gateway_timeout(Message::GatewayTimeout, Message::StorageTimeout)
+            }
+            StorageError::Unavailable { retry_after } => Self::service_unavailable(
+                Message::ServiceUnavailable,
+                Message::StorageBusy,
+                or_default(retry_after),
+            ),
+            StorageError::RateLimited { retry_after } => Self::too_many_requests(
+                Message::TooManyRequests,
+                Message::TooManyRequestsDesc,
+                or_default(retry_after),
+            ),
+            StorageError::RangeNotSatisfiable => Self::range_not_satisfiable(
+                Message::RangeNotSatisfiable,
+                Message::RangeNotSatisfiableDesc,
+            ),
+            StorageError::MethodNotAllowed => {
+                Self::method_not_allowed(Message::MethodNotAllowed, Message::MethodNotAllowedDesc)
+            }
+            StorageError::Conflict(_) => {
+                Self::conflict(Message::Conflict, Message::StorageConflict)
+            }
+            StorageError::Other(_) => Self::internal_error(Message::ServerError, fallback),
         }
     }
 
-    #[allow(dead_code)]
     pub fn status_code(&self) -> u16 {
         match self {
+            Self::Unauthorized { .. } => 401,
             Self::Forbidden { .. } => 403,
             Self::NotFound { .. } => 404,
             Self::BadRequest { .. } => 400,
+            Self::MethodNotAllowed { .. } => 405,
+            Self::RangeNotSatisfiable { .. } => 416,
+            Self::PreconditionFailed { .. } => 412,
+            Self::Conflict { .. } => 409,
+            Self::PayloadTooLarge { .. } => 413,
+            Self::TooManyRequests { .. } => 429,
             Self::InternalServerError { .. } => 500,
+            Self::BadGateway { .. } => 502,
+            Self::ServiceUnavailable { .. } => 503,
+            Self::GatewayTimeout { .. } => 504,
+        }
+    }
+
+    fn error_code(&self) -> &'static str {
+        match self {
+            Self::Unauthorized { .. } => "UNAUTHORIZED",
+            Self::Forbidden { .. } => "FORBIDDEN",
+            Self::NotFound { .. } => "NOT_FOUND",
+            Self::BadRequest { .. } => "BAD_REQUEST",
+            Self::MethodNotAllowed { .. } => "METHOD_NOT_ALLOWED",
+            Self::RangeNotSatisfiable { .. } => "RANGE_NOT_SATISFIABLE",
+            Self::PreconditionFailed { .. } => "PRECONDITION_FAILED",
+            Self::Conflict { .. } => "CONFLICT",
+            Self::PayloadTooLarge { .. } => "PAYLOAD_TOO_LARGE",
+            Self::TooManyRequests { .. } => "TOO_MANY_REQUESTS",
+            Self::InternalServerError { .. } => "INTERNAL_ERROR",
+            Self::BadGateway { .. } => "BAD_GATEWAY",
+            Self::ServiceUnavailable { .. } => "SERVICE_UNAVAILABLE",
+            Self::GatewayTimeout { .. } => "GATEWAY_TIMEOUT",
         }
     }
 
-    pub fn to_http_response(&self) -> HttpResponse {
-        let (status_code, message, error_code, description) = match self {
-            Self::Forbidden {
+    fn messages(&self) -> (Message, Message) {
+        match self {
+            Self::Unauthorized {
                 message,
                 description,
-            } => (
-                403,
-                message.clone(),
-                "FORBIDDEN".to_string(),
-                description.clone(),
-            ),
-            Self::NotFound {
+                ..
+            }
+            | Self::Forbidden {
                 message,
                 description,
-            } => (
-                404,
-                message.clone(),
-                "NOT_FOUND".to_string(),
-                description.clone(),
-            ),
-            Self::BadRequest {
+            }
+            | Self::NotFound {
                 message,
                 description,
-            } => (
-                400,
-                message.clone(),
-                "BAD_REQUEST".to_string(),
-                description.clone(),
-            ),
-            Self::InternalServerError {
+            }
+            | Self::BadRequest {
                 message,
                 description,
-            } => (
-                500,
-                message.clone(),
-                "INTERNAL_ERROR".to_string(),
-                description.clone(),
-            ),
-        };
+            }
+            | Self::MethodNotAllowed {
+                message,
+                description,
+            }
+            | Self::RangeNotSatisfiable {
+                message,
+                description,
+            }
+            | Self::PreconditionFailed {
+                message,
+                description,
+            }
+            | Self::Conflict {
+                message,
+                description,
+            }
+            | Self::PayloadTooLarge {
+                message,
+                description,
+            }
+            | Self::TooManyRequests {
+                message,
+                description,
+                ..
+            }
+            | Self::InternalServerError {
+                message,
+                description,
+            }
+            | Self::BadGateway {
+                message,
+                description,
+            }
+            | Self::ServiceUnavailable {
+                message,
+                description,
+                ..
+            }
+            | Self::GatewayTimeout {
+                message,
+                description,
+            } => (*message, *description),
+        }
+    }
 
-        let template = ErrorTemplate {
-            title: format!("{} - 错误", status_code),
-            status_code: status_code.to_string(),
-            message,
-            error_code,
-            description,
-            home_url: "/".to_string(),
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::TooManyRequests { retry_after, .. }
+            | Self::ServiceUnavailable { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// 根据请求的`Accept`和`User-Agent`返回HTML错误页面、RFC 7807 JSON问题文档或纯文本
+    pub fn to_http_response(&self, req: &HttpRequest) -> HttpResponse {
+        let locale = Locale::from_request(req);
+        let request_id = RequestId::of(req);
+        let status_code = self.status_code();
+        let error_code = self.error_code().to_string();
+        let (message, description) = self.messages();
+        let (message, description) = (
+            locale.text(message).to_string(),
+            locale.text(description).to_string(),
+        );
+        let status = StatusCode::from_u16(status_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut builder = HttpResponse::build(status);
+        if let Some(retry_after) = self.retry_after() {
+            builder.insert_header((header::RETRY_AFTER, retry_after.as_secs().to_string()));
+        }
+        if let Self::MethodNotAllowed { .. } = self {
+            builder.insert_header((header::ALLOW, "GET, HEAD"));
+        }
+        if let Self::Unauthorized { challenge, .. } = self {
+            builder.insert_header((header::WWW_AUTHENTICATE, challenge.as_str()));
+        }
+
+        match ErrorFormat::negotiate(req) {
+            ErrorFormat::Json => {
+                let problem = ProblemDetails {
+                    type_: format!("urn:mirror-proxy:error:{}", error_code.to_lowercase()),
+                    title: message,
+                    status: status_code,
+                    detail: description,
+                    error_code,
+                    request_id,
+                };
+                match serde_json::to_string(&problem) {
+                    Ok(json) => builder.content_type("application/problem+json").body(json),
+                    Err(e) => HttpResponse::InternalServerError()
+                        .body(format!("Failed to serialize error: {}", e)),
+                }
+            }
+            ErrorFormat::Text => builder
+                .content_type("text/plain; charset=utf-8")
+                .body(format!(
+                    "{} {}\n{}\nerror_code: {}\nrequest_id: {}\n",
+                    status_code, message, description, error_code, request_id
+                )),
+            ErrorFormat::Html => {
+                let template = ErrorTemplate {
+                    lang: locale.tag(),
+                    ui: locale.ui(),
+                    site: render::site_config(),
+                    title: format!("{} - {}", status_code, locale.text(Message::ErrorTitle)),
+                    status_code: status_code.to_string(),
+                    message,
+                    error_code,
+                    description,
+                    request_id,
+                    home_url: "/".to_string(),
+                };
+
+                match theme::render("error.html", &template) {
+                    Ok(html) => builder.content_type("text/html").body(html),
+                    Err(e) => HttpResponse::InternalServerError()
+                        .body(format!("Failed to render error page: {}", e)),
+                }
+            }
+        }
+    }
+}
+
+/// RFC 7807 问题文档
+#[derive(Serialize)]
+struct ProblemDetails {
+    #[serde(rename = "type")]
+    type_: String,
+    title: String,
+    status: u16,
+    detail: String,
+    error_code: String,
+    request_id: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum ErrorFormat {
+    Html,
+    Json,
+    Text,
+}
+
+impl ErrorFormat {
+    /// 明确接受JSON（且不低于HTML）时返回JSON；明确接受HTML时返回HTML；
+    /// 否则浏览器返回HTML，curl、包管理器等非浏览器客户端返回纯文本
+    fn negotiate(req: &HttpRequest) -> Self {
+        let accept = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default();
+        let (mut json, mut html, mut text) = (0.0f32, 0.0f32, 0.0f32);
+        for item in accept.split(',') {
+            let mut parts = item.split(';');
+            let media = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let slot = match media.as_str() {
+                "application/json" | "application/problem+json" => &mut json,
+                "text/html" | "application/xhtml+xml" => &mut html,
+                "text/plain" => &mut text,
+                _ => continue,
+            };
+            *slot = slot.max(q);
+        }
+
+        if json > 0.0 && json >= html {
+            Self::Json
+        } else if html > 0.0 {
+            Self::Html
+        } else if text > 0.0 {
+            Self::Text
+        } else if is_browser(req) {
+            Self::Html
+        } else {
+            Self::Text
+        }
+    }
+}
+
+fn is_browser(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::USER_AGENT)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|ua| ua.starts_with("Mozilla/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn format_of(accept: Option<&str>, ua: Option<&str>) -> ErrorFormat {
+        let mut req = TestRequest::default();
+        if let Some(accept) = accept {
+            req = req.insert_header((header::ACCEPT, accept));
+        }
+        if let Some(ua) = ua {
+            req = req.insert_header((header::USER_AGENT, ua));
+        }
+        ErrorFormat::negotiate(&req.to_http_request())
+    }
+
+    #[test]
+    fn test_error_format_negotiation() {
+        let firefox =
+            Some("Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0");
+        assert_eq!(
+            format_of(Some("text/html,application/xhtml+xml,*/*;q=0.8"), firefox),
+            ErrorFormat::Html
+        );
+        assert_eq!(format_of(Some("application/json"), None), ErrorFormat::Json);
+        assert_eq!(
+            format_of(Some("text/html;q=0.9, application/problem+json"), firefox),
+            ErrorFormat::Json
+        );
+        assert_eq!(
+            format_of(Some("*/*"), Some("curl/8.5.0")),
+            ErrorFormat::Text
+        );
+        assert_eq!(
+            format_of(None, Some("Debian APT-HTTP/1.3")),
+            ErrorFormat::Text
+        );
+        assert_eq!(format_of(Some("*/*"), firefox), ErrorFormat::Html);
+    }
+
+    #[test]
+    fn test_storage_errors_map_to_status_codes() {
+        crate::config::init_test_config();
+        let fallback = Message::ListDirectoryFailed;
+        let cases = [
+            (StorageError::Connect("refused".to_string()), 502),
+            (StorageError::BadUpstream("garbage".to_string()), 502),
+            (StorageError::Timeout("slow".to_string()), 504),
+            (StorageError::Unavailable { retry_after: None }, 503),
+            (StorageError::RateLimited { retry_after: None }, 429),
+            (StorageError::RangeNotSatisfiable, 416),
+            (StorageError::MethodNotAllowed, 405),
+            (StorageError::Conflict("exists".to_string()), 409),
+            (StorageError::Other(anyhow::anyhow!("boom")), 500),
+        ];
+        for (e, status) in cases {
+            assert_eq!(HttpError::from_storage(&e, fallback).status_code(), status);
+        }
+    }
+
+    #[test]
+    fn test_retry_after_header() {
+        let e = StorageError::Unavailable {
+            retry_after: Some(Duration::from_secs(120)),
         };
+        let req = TestRequest::default()
+            .insert_header((header::ACCEPT, "application/json"))
+            .to_http_request();
+        let resp = HttpError::from_storage(&e, Message::ServerError).to_http_response(&req);
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "120");
 
-        match template.render() {
-            Ok(html) => match status_code {
-                403 => HttpResponse::Forbidden()
-                    .content_type("text/html")
-                    .body(html),
-                404 => HttpResponse::NotFound()
-                    .content_type("text/html")
-                    .body(html),
-                400 => HttpResponse::BadRequest()
-                    .content_type("text/html")
-                    .body(html),
-                500 => HttpResponse::InternalServerError()
-                    .content_type("text/html")
-                    .body(html),
-                _ => HttpResponse::InternalServerError()
-                    .content_type("text/html")
-                    .body(html),
-            },
-            Err(e) => HttpResponse::InternalServerError()
-                .body(format!("Failed to render error page: {}", e)),
+        // 源站没有给出时使用默认值
+        crate::config::init_test_config();
+        for (e, status) in [
+            (
+                StorageError::Unavailable { retry_after: None },
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                StorageError::RateLimited { retry_after: None },
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+        ] {
+            let resp = HttpError::from_storage(&e, Message::ServerError).to_http_response(&req);
+            assert_eq!(resp.status(), status);
+            assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "60");
         }
     }
 }
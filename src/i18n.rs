@@ -0,0 +1,488 @@
+use actix_web::{
+    body::MessageBody,
+    cookie::{time::Duration, Cookie},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    HttpRequest,
+};
+use serde::Serialize;
+
+/// 保存用户语言选择的cookie名称，也是查询参数的名称（`?lang=en`）
+const LANG_COOKIE: &str = "lang";
+
+/// 界面语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    ZhCn,
+    En,
+}
+
+impl Locale {
+    /// BCP 47 语言标签，用于`<html lang>`和cookie
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::ZhCn => "zh-CN",
+            Self::En => "en",
+        }
+    }
+
+    /// 解析语言标签，只比较主语言部分（`en-US` -> `En`）
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        match primary.as_str() {
+            "zh" => Some(Self::ZhCn),
+            "en" => Some(Self::En),
+            _ => None,
+        }
+    }
+
+    /// 按`?lang=`查询参数、`lang` cookie、`Accept-Language`的优先级选择语言
+    pub fn from_request(req: &HttpRequest) -> Self {
+        if let Some(locale) = query_locale(req.query_string()) {
+            return locale;
+        }
+        if let Some(locale) = req
+            .cookie(LANG_COOKIE)
+            .and_then(|c| Self::from_tag(c.value()))
+        {
+            return locale;
+        }
+        req.headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|h| h.to_str().ok())
+            .and_then(negotiate)
+            .unwrap_or_default()
+    }
+
+    pub fn text(&self, msg: Message) -> &'static str {
+        match self {
+            Self::ZhCn => msg.zh_cn(),
+            Self::En => msg.en(),
+        }
+    }
+
+    /// 模板中使用的界面文本
+    pub fn ui(&self) -> &'static UiStrings {
+        match self {
+            Self::ZhCn => &UI_ZH_CN,
+            Self::En => &UI_EN,
+        }
+    }
+}
+
+fn query_locale(query: &str) -> Option<Locale> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(k, _)| k == LANG_COOKIE)
+        .and_then(|(_, v)| Locale::from_tag(&v))
+}
+
+/// 从`Accept-Language`中选出q值最高的受支持语言
+fn negotiate(accept_language: &str) -> Option<Locale> {
+    accept_language
+        .split(',')
+        .enumerate()
+        .filter_map(|(idx, item)| {
+            let mut parts = item.split(';');
+            let locale = Locale::from_tag(parts.next()?)?;
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (q > 0.0).then_some((q, idx, locale))
+        })
+        // q值相同时保留先出现的语言
+        .max_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(&a.1)))
+        .map(|(_, _, locale)| locale)
+}
+
+/// 当请求中带有`?lang=`时，将选择写入cookie，使后续页面保持相同的语言
+pub async fn remember_locale(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let locale = query_locale(req.query_string());
+    let mut res = next.call(req).await?;
+    if let Some(locale) = locale {
+        let cookie = Cookie::build(LANG_COOKIE, locale.tag())
+            .path("/")
+            .max_age(Duration::days(365))
+            .finish();
+        if let Err(e) = res.response_mut().add_cookie(&cookie) {
+            log::warn!("Failed to set lang cookie: {}", e);
+        }
+    }
+    Ok(res)
+}
+
+/// 错误页面等处使用的可翻译消息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    ErrorTitle,
+    PathNotFound,
+    ResourceNotFound,
+    PageNotFound,
+    PageNotFoundDesc,
+    FileNotFound,
+    DownloadNotFound,
+    DirectoryNotFound,
+    DirectoryNotFoundDesc,
+    AliasNoMatch,
+    AccessDenied,
+    InvalidPath,
+    InvalidRequest,
+    InvalidRequestPath,
+    ServerError,
+    FileProcessingFailed,
+    DownloadUrlFailed,
+    AliasResolveFailed,
+    StorageUnavailable,
+    ListDirectoryFailed,
+    RenderDirectoryFailed,
+    RenderIndexFailed,
+    MethodNotAllowed,
+    MethodNotAllowedDesc,
+    RangeNotSatisfiable,
+    RangeNotSatisfiableDesc,
+    PreconditionFailed,
+    PreconditionFailedDesc,
+    TooManyRequests,
+    TooManyRequestsDesc,
+    BadGateway,
+    ServiceUnavailable,
+    StorageBusy,
+    GatewayTimeout,
+    StorageTimeout,
+    Unauthorized,
+    UnauthorizedDesc,
+    Maintenance,
+    MaintenanceDesc,
+    MaintenanceBanner,
+    SignedLinkInvalid,
+    SignedLinkExpired,
+    UploadNotAllowed,
+    UploadExtensionNotAllowed,
+    UploadInvalidPath,
+    UploadFailed,
+    PayloadTooLarge,
+    PayloadTooLargeDesc,
+    Conflict,
+    FileExists,
+    InvalidChecksum,
+    ChecksumMismatch,
+    StorageConflict,
+    PropfindInfiniteDepth,
+}
+
+impl Message {
+    fn zh_cn(&self) -> &'static str {
+        match self {
+            Self::ErrorTitle => "错误",
+            Self::PathNotFound => "路径不存在",
+            Self::ResourceNotFound => "请求的资源不存在",
+            Self::PageNotFound => "页面不存在",
+            Self::PageNotFoundDesc => "您访问的页面不存在，请检查URL是否正确",
+            Self::FileNotFound => "文件不存在",
+            Self::DownloadNotFound => "请求的下载文件不存在",
+            Self::DirectoryNotFound => "目录不存在",
+            Self::DirectoryNotFoundDesc => "请求的目录不存在",
+            Self::AliasNoMatch => "别名没有匹配到任何文件",
+            Self::AccessDenied => "访问被拒绝",
+            Self::InvalidPath => "请求的路径无效",
+            Self::InvalidRequest => "无效请求",
+            Self::InvalidRequestPath => "请求路径无效",
+            Self::ServerError => "服务器错误",
+            Self::FileProcessingFailed => "文件处理失败",
+            Self::DownloadUrlFailed => "获取下载链接失败",
+            Self::AliasResolveFailed => "解析别名失败",
+            Self::StorageUnavailable => "无法连接到存储服务",
+            Self::ListDirectoryFailed => "获取目录列表失败",
+            Self::RenderDirectoryFailed => "渲染目录时发生内部错误",
+            Self::RenderIndexFailed => "无法加载首页",
+            Self::MethodNotAllowed => "不支持的请求方法",
+            Self::MethodNotAllowedDesc => "该资源不支持此请求方法",
+            Self::RangeNotSatisfiable => "请求范围无效",
+            Self::RangeNotSatisfiableDesc => "请求的范围超出了文件大小",
+            Self::PreconditionFailed => "前置条件不满足",
+            Self::PreconditionFailedDesc => "文件在您上次获取之后已被修改",
+            Self::TooManyRequests => "请求过于频繁",
+            Self::TooManyRequestsDesc => "请求过于频繁，请稍后再试",
+            Self::BadGateway => "网关错误",
+            Self::ServiceUnavailable => "服务暂不可用",
+            Self::StorageBusy => "存储服务暂时不可用，请稍后再试",
+            Self::GatewayTimeout => "网关超时",
+            Self::StorageTimeout => "存储服务响应超时",
+            Self::Unauthorized => "需要身份验证",
+            Self::UnauthorizedDesc => "请提供有效的访问凭据",
+            Self::Maintenance => "站点维护中",
+            Self::MaintenanceDesc => "镜像站正在维护，请稍后再试",
+            Self::MaintenanceBanner => "镜像站正在维护，部分文件可能暂时无法下载",
+            Self::SignedLinkInvalid => "下载链接无效",
+            Self::SignedLinkExpired => "下载链接已过期",
+            Self::UploadNotAllowed => "该路径不允许上传文件",
+            Self::UploadExtensionNotAllowed => "不允许上传该类型的文件",
+            Self::UploadInvalidPath => "上传路径必须是文件路径",
+            Self::UploadFailed => "保存上传的文件失败",
+            Self::PayloadTooLarge => "文件过大",
+            Self::PayloadTooLargeDesc => "上传的文件超过了大小限制",
+            Self::Conflict => "冲突",
+            Self::FileExists => "文件已存在，且不允许覆盖",
+            Self::InvalidChecksum => "X-Checksum-Sha256 必须是64位十六进制的SHA-256值",
+            Self::ChecksumMismatch => "上传内容的SHA-256与提供的校验值不一致",
+            Self::StorageConflict => "目标已存在，或者目录不为空",
+            Self::PropfindInfiniteDepth => "不支持Depth为infinity的PROPFIND请求，请使用0或1",
+        }
+    }
+
+    fn en(&self) -> &'static str {
+        match self {
+            Self::ErrorTitle => "Error",
+            Self::PathNotFound => "Path not found",
+            Self::ResourceNotFound => "The requested resource does not exist",
+            Self::PageNotFound => "Page not found",
+            Self::PageNotFoundDesc => "The page you requested does not exist, please check the URL",
+            Self::FileNotFound => "File not found",
+            Self::DownloadNotFound => "The requested download does not exist",
+            Self::DirectoryNotFound => "Directory not found",
+            Self::DirectoryNotFoundDesc => "The requested directory does not exist",
+            Self::AliasNoMatch => "The alias does not match any file",
+            Self::AccessDenied => "Access denied",
+            Self::InvalidPath => "The requested path is invalid",
+            Self::InvalidRequest => "Invalid request",
+            Self::InvalidRequestPath => "The request path is invalid",
+            Self::ServerError => "Server error",
+            Self::FileProcessingFailed => "Failed to process the file",
+            Self::DownloadUrlFailed => "Failed to get the download URL",
+            Self::AliasResolveFailed => "Failed to resolve the alias",
+            Self::StorageUnavailable => "Unable to connect to the storage service",
+            Self::ListDirectoryFailed => "Failed to list the directory",
+            Self::RenderDirectoryFailed => {
+                "An internal error occurred while rendering the directory"
+            }
+            Self::RenderIndexFailed => "Failed to load the home page",
+            Self::MethodNotAllowed => "Method not allowed",
+            Self::MethodNotAllowedDesc => "This resource does not support the request method",
+            Self::RangeNotSatisfiable => "Range not satisfiable",
+            Self::RangeNotSatisfiableDesc => "The requested range is beyond the end of the file",
+            Self::PreconditionFailed => "Precondition failed",
+            Self::PreconditionFailedDesc => "The file has changed since you last retrieved it",
+            Self::TooManyRequests => "Too many requests",
+            Self::TooManyRequestsDesc => "Too many requests, please try again later",
+            Self::BadGateway => "Bad gateway",
+            Self::ServiceUnavailable => "Service unavailable",
+            Self::StorageBusy => {
+                "The storage service is temporarily unavailable, please try again later"
+            }
+            Self::GatewayTimeout => "Gateway timeout",
+            Self::StorageTimeout => "The storage service timed out",
+            Self::Unauthorized => "Authentication required",
+            Self::UnauthorizedDesc => "Valid credentials are required to access this resource",
+            Self::Maintenance => "Under maintenance",
+            Self::MaintenanceDesc => "The mirror is undergoing maintenance, please try again later",
+            Self::MaintenanceBanner => {
+                "The mirror is under maintenance, some files may be temporarily unavailable"
+            }
+            Self::SignedLinkInvalid => "The download link is invalid",
+            Self::SignedLinkExpired => "The download link has expired",
+            Self::UploadNotAllowed => "Uploads are not allowed to this path",
+            Self::UploadExtensionNotAllowed => "Uploading files of this type is not allowed",
+            Self::UploadInvalidPath => "The upload path must be a file path",
+            Self::UploadFailed => "Failed to store the uploaded file",
+            Self::PayloadTooLarge => "Payload too large",
+            Self::PayloadTooLargeDesc => "The uploaded file exceeds the size limit",
+            Self::Conflict => "Conflict",
+            Self::FileExists => "The file already exists and may not be overwritten",
+            Self::InvalidChecksum => "X-Checksum-Sha256 must be a hex-encoded SHA-256 digest",
+            Self::ChecksumMismatch => {
+                "The SHA-256 of the uploaded content does not match the supplied checksum"
+            }
+            Self::StorageConflict => "The target already exists or the directory is not empty",
+            Self::PropfindInfiniteDepth => {
+                "PROPFIND with Depth: infinity is not supported, use 0 or 1"
+            }
+        }
+    }
+}
+
+/// 模板中的固定界面文本
+#[derive(Serialize)]
+pub struct UiStrings {
+    pub index_of: &'static str,
+    pub name: &'static str,
+    pub last_modified: &'static str,
+    pub size: &'static str,
+    pub error_code: &'static str,
+    pub request_id: &'static str,
+    pub back_home: &'static str,
+    pub contact_us: &'static str,
+    pub improve_page: &'static str,
+    pub sync_status: &'static str,
+    pub upstream: &'static str,
+    pub status: &'static str,
+    pub last_success: &'static str,
+    pub last_failure: &'static str,
+    pub next_run: &'static str,
+    pub manual_only: &'static str,
+    pub mirrors: &'static str,
+    pub last_update: &'static str,
+    /// 首页
+    pub home_tagline: &'static str,
+    pub home_resources: &'static str,
+    pub home_learn_more: &'static str,
+    pub home_sponsor_us: &'static str,
+    pub home_resources_title: &'static str,
+    pub home_resources_subtitle: &'static str,
+    pub home_website: &'static str,
+    pub home_website_before: &'static str,
+    pub home_website_after: &'static str,
+    pub home_downloads: &'static str,
+    pub home_downloads_before: &'static str,
+    pub home_downloads_after: &'static str,
+    pub home_github: &'static str,
+    pub home_git_mirror: &'static str,
+    pub home_qq_group: &'static str,
+    pub home_qq_qrcode: &'static str,
+    pub home_join_us: &'static str,
+    pub home_sponsor_title: &'static str,
+    pub home_sponsor_desc: &'static str,
+    pub home_sponsor_use_developers: &'static str,
+    pub home_sponsor_use_cloud: &'static str,
+    pub home_sponsor_use_devices: &'static str,
+    pub home_sponsor_use_other: &'static str,
+    pub home_sponsor_now: &'static str,
+    pub home_sponsors: &'static str,
+}
+
+static UI_ZH_CN: UiStrings = UiStrings {
+    index_of: "目录",
+    name: "名称",
+    last_modified: "修改时间",
+    size: "大小",
+    error_code: "错误代码",
+    request_id: "请求ID",
+    back_home: "返回主页",
+    contact_us: "联系我们",
+    improve_page: "完善此页面",
+    sync_status: "同步状态",
+    upstream: "上游",
+    status: "状态",
+    last_success: "上次成功",
+    last_failure: "上次失败",
+    next_run: "下次同步",
+    manual_only: "手动",
+    mirrors: "镜像列表",
+    last_update: "上次更新",
+    home_tagline: "DragonOS是面向无服务器计算的轻量级内核。",
+    home_resources: "资源中心",
+    home_learn_more: "了解更多",
+    home_sponsor_us: "赞助我们",
+    home_resources_title: "DragonOS 资源中心",
+    home_resources_subtitle: "获取DragonOS最新版本和资源",
+    home_website: "官网",
+    home_website_before: "点击访问",
+    home_website_after: "了解更多信息。",
+    home_downloads: "文件下载",
+    home_downloads_before: "访问",
+    home_downloads_after: "获取DragonOS各版本的代码和镜像文件",
+    home_github: "GitHub仓库",
+    home_git_mirror: "Git镜像站",
+    home_qq_group: "开发交流QQ群",
+    home_qq_qrcode: "QQ群二维码",
+    home_join_us: "立即加入我们",
+    home_sponsor_title: "赞助DragonOS",
+    home_sponsor_desc: "DragonOS的发展离不开资金的支持，我们保证，所有赞助的资金及物品，将会用于：",
+    home_sponsor_use_developers: "为活跃的社区开发者发放补贴或设备支持",
+    home_sponsor_use_cloud: "DragonOS的云服务开支",
+    home_sponsor_use_devices: "设备购置",
+    home_sponsor_use_other: "任何有助于DragonOS发展的用途",
+    home_sponsor_now: "前往赞助",
+    home_sponsors: "赞助商",
+};
+
+static UI_EN: UiStrings = UiStrings {
+    index_of: "Index of",
+    name: "Name",
+    last_modified: "Last Modified",
+    size: "Size",
+    error_code: "Error code",
+    request_id: "Request ID",
+    back_home: "Back to home",
+    contact_us: "Contact us",
+    improve_page: "Improve this page",
+    sync_status: "Sync status",
+    upstream: "Upstream",
+    status: "Status",
+    last_success: "Last success",
+    last_failure: "Last failure",
+    next_run: "Next sync",
+    manual_only: "Manual",
+    mirrors: "Mirrors",
+    last_update: "Last updated",
+    home_tagline: "DragonOS is a Light-weight kernel for serverless computing.",
+    home_resources: "Resources",
+    home_learn_more: "Learn more",
+    home_sponsor_us: "Sponsor us",
+    home_resources_title: "DragonOS Resource Center",
+    home_resources_subtitle: "Get the latest releases and resources of DragonOS",
+    home_website: "Website",
+    home_website_before: "Visit ",
+    home_website_after: " to learn more.",
+    home_downloads: "Downloads",
+    home_downloads_before: "Visit ",
+    home_downloads_after: " for the source code and images of every DragonOS release",
+    home_github: "GitHub repository",
+    home_git_mirror: "Git mirror",
+    home_qq_group: "Developer QQ group",
+    home_qq_qrcode: "QQ group QR code",
+    home_join_us: "Join us now",
+    home_sponsor_title: "Sponsor DragonOS",
+    home_sponsor_desc: "DragonOS relies on financial support. We promise that all sponsored funds and goods will be used for:",
+    home_sponsor_use_developers: "Allowances or equipment for active community developers",
+    home_sponsor_use_cloud: "Cloud services of DragonOS",
+    home_sponsor_use_devices: "Purchasing devices",
+    home_sponsor_use_other: "Anything else that helps DragonOS grow",
+    home_sponsor_now: "Sponsor now",
+    home_sponsors: "Sponsors",
+};
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn test_negotiate_accept_language() {
+        assert_eq!(negotiate("en-US,en;q=0.9"), Some(Locale::En));
+        assert_eq!(
+            negotiate("fr-FR, en;q=0.5, zh-CN;q=0.8"),
+            Some(Locale::ZhCn)
+        );
+        assert_eq!(negotiate("zh-TW, en"), Some(Locale::ZhCn));
+        assert_eq!(negotiate("en;q=0, zh;q=0"), None);
+        assert_eq!(negotiate("fr, de"), None);
+    }
+
+    #[test]
+    fn test_locale_priority() {
+        let req = TestRequest::with_uri("/pub/?lang=en")
+            .insert_header((header::ACCEPT_LANGUAGE, "zh-CN"))
+            .to_http_request();
+        assert_eq!(Locale::from_request(&req), Locale::En);
+
+        let req = TestRequest::with_uri("/pub/")
+            .cookie(Cookie::new(LANG_COOKIE, "zh-CN"))
+            .insert_header((header::ACCEPT_LANGUAGE, "en"))
+            .to_http_request();
+        assert_eq!(Locale::from_request(&req), Locale::ZhCn);
+
+        let req = TestRequest::with_uri("/pub/")
+            .insert_header((header::ACCEPT_LANGUAGE, "en-GB,en;q=0.9"))
+            .to_http_request();
+        assert_eq!(Locale::from_request(&req), Locale::En);
+
+        let req = TestRequest::with_uri("/pub/").to_http_request();
+        assert_eq!(Locale::from_request(&req), Locale::ZhCn);
+    }
+}
@@ -1,12 +1,15 @@
 use self::error::HttpError;
-use crate::config::{has_matching_extension, Config};
-use actix_files::NamedFile;
-use actix_web::{get, http::header, web, App, HttpRequest, HttpResponse, HttpServer};
-use anyhow::Context;
+use crate::config::{has_matching_extension, AliasAction, AliasRule};
+use actix_web::{
+    get,
+    http::{header, Method},
+    middleware, route, web, App, HttpRequest, HttpResponse, HttpServer,
+};
+use i18n::{Locale, Message};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 use storage::select_provider;
 
-use std::path::PathBuf;
-use std::sync::OnceLock;
+use std::path::{Path, PathBuf};
 
 #[macro_use]
 extern crate lazy_static;
@@ -14,123 +17,291 @@ extern crate lazy_static;
 #[macro_use]
 extern crate anyhow;
 
-static CONFIG: OnceLock<Config> = OnceLock::new();
-
+mod admin;
+mod alias;
+mod auth;
+mod cache;
+mod compress;
 mod config;
+mod downloads;
 mod error;
+mod i18n;
+mod maintenance;
+mod mirrors;
 mod render;
+mod request_id;
+mod retention;
+mod rsync;
+mod serve;
+mod signed_link;
 mod storage;
+mod sync;
+mod upload;
+mod upstream;
+mod webdav;
 
 const BASE_PATH: &str = "/pub";
+const ACCESS_LOG_FORMAT: &str =
+    r#"%{r}a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T request_id=%{x-request-id}o"#;
 
 async fn handle_download_request(
     path_str: &str,
     req: &HttpRequest,
 ) -> Result<HttpResponse, HttpError> {
-    let config = CONFIG.get().expect("Config not initialized");
+    let config = config::current();
     if !has_matching_extension(path_str, &config.download_rules.extensions) {
-        return Err(HttpError::not_found("路径不存在", "请求的资源不存在"));
+        return Err(HttpError::not_found(
+            Message::PathNotFound,
+            Message::ResourceNotFound,
+        ));
     }
 
+    serve_file(path_str, req).await
+}
+
+/// 返回文件内容（本地存储）或重定向到下载链接（远程存储），不检查文件后缀
+async fn serve_file(path_str: &str, req: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    maintenance::check_download(path_str)?;
     match select_provider(path_str) {
         Some((provider, path_in_provider)) => {
             if provider.is_local() {
                 log::debug!("Local storage provider selected, attempting to stream file (path in provider: {:?})", path_in_provider);
-                match provider.stream_file(&path_in_provider).await {
-                    Ok(Some(file)) => {
-                        return named_file_to_response(
+                let file =
+                    match compress::open_precompressed(&*provider, &path_in_provider, req).await {
+                        Ok(Some((file, encoding))) => Ok(Some((file, Some(encoding)))),
+                        Ok(None) => provider
+                            .stream_file(&path_in_provider)
+                            .await
+                            .map(|f| f.map(|f| (f, None))),
+                        Err(e) => Err(e),
+                    };
+                match file {
+                    Ok(Some((file, encoding))) => {
+                        let config = config::current();
+                        serve::file_response(
                             file,
-                            req.headers().get("range").and_then(|h| h.to_str().ok()),
                             req,
+                            &config.download_rules.inline_extensions,
+                            encoding,
                         )
                         .await
-                        .map_err(|_| HttpError::internal_error("服务器错误", "文件处理失败"));
-                    }
-                    Ok(None) => {
-                        return Err(HttpError::not_found("文件不存在", "请求的下载文件不存在"));
                     }
+                    Ok(None) => Err(HttpError::not_found(
+                        Message::FileNotFound,
+                        Message::DownloadNotFound,
+                    )),
                     Err(e) => {
                         log::error!("文件流处理失败 - 路径: {}, 错误: {}", path_in_provider, e);
-                        return Err(HttpError::internal_error("服务器错误", "文件处理失败"));
+                        Err(HttpError::from_storage(&e, Message::FileProcessingFailed))
                     }
                 }
             } else {
-                match provider.get_download_url(path_str).await {
+                let config = config::current();
+                let trust_forwarded_for = config
+                    .signed_links
+                    .as_ref()
+                    .is_some_and(|c| c.trust_forwarded_for);
+                let client_ip = signed_link::client_ip(req, trust_forwarded_for);
+                match provider
+                    .get_download_url(path_str, client_ip.as_deref())
+                    .await
+                {
                     Ok(Some(download_url)) => {
-                        return Ok(HttpResponse::Found()
+                        let (_, max_age) = config.cache.max_ages(path_str);
+                        let cache_control = if auth::is_protected(path_str) {
+                            cache::private_cache_control(max_age)
+                        } else {
+                            cache::cache_control(max_age)
+                        };
+                        Ok(HttpResponse::Found()
                             .append_header((header::LOCATION, download_url))
-                            .finish());
-                    }
-                    Ok(None) => {
-                        return Err(HttpError::not_found("文件不存在", "请求的下载文件不存在"));
+                            .insert_header(cache_control)
+                            .finish())
                     }
+                    Ok(None) => Err(HttpError::not_found(
+                        Message::FileNotFound,
+                        Message::DownloadNotFound,
+                    )),
                     Err(e) => {
                         log::error!("Failed to get download URL: {}", e);
-                        return Err(HttpError::internal_error("服务器错误", "获取下载链接失败"));
+                        Err(HttpError::from_storage(&e, Message::DownloadUrlFailed))
                     }
                 }
             }
         }
-        None => Err(HttpError::not_found("路径不存在", "请求的资源不存在")),
+        None => Err(HttpError::not_found(
+            Message::PathNotFound,
+            Message::ResourceNotFound,
+        )),
+    }
+}
+
+async fn handle_alias_request(
+    rule: &AliasRule,
+    req: &HttpRequest,
+) -> Result<HttpResponse, HttpError> {
+    // 别名本身可能不受保护，但它指向的目录可能受保护
+    auth::authorize(req, &rule.directory).await?;
+    let resolved = match alias::resolve(rule).await {
+        Ok(Some(resolved)) => resolved,
+        Ok(None) => {
+            return Err(HttpError::not_found(
+                Message::FileNotFound,
+                Message::AliasNoMatch,
+            ))
+        }
+        Err(e) => {
+            log::error!("Failed to resolve alias {}: {}", rule.path, e);
+            return Err(HttpError::from_storage(&e, Message::AliasResolveFailed));
+        }
+    };
+    log::debug!("Alias {} -> {}", rule.path, resolved.target);
+
+    match resolved.action {
+        AliasAction::Redirect => Ok(HttpResponse::Found()
+            .append_header((header::LOCATION, encode_path(&resolved.target)))
+            .append_header((header::CACHE_CONTROL, "no-cache"))
+            .finish()),
+        AliasAction::Stream => {
+            auth::authorize(req, &resolved.target).await?;
+            serve_file(&resolved.target, req).await
+        }
     }
 }
 
 async fn handle_directory_listing(
     path_str: &str,
-    full_path: &PathBuf,
+    full_path: &Path,
+    req: &HttpRequest,
 ) -> Result<HttpResponse, HttpError> {
-    let entries = match select_provider(path_str) {
-        Some((provider, path_in_provider)) => {
-            match provider.list_directory(&path_in_provider).await {
-                Ok(Some(entries)) => entries,
-                Ok(None) => return Err(HttpError::not_found("目录不存在", "请求的目录不存在")),
-                Err(e) => {
-                    if e.to_string().contains("Failed to connect to nginx") {
-                        log::error!("Nginx connection failed: {}", e);
-                        return Err(HttpError::internal_error(
-                            "服务器错误",
-                            "无法连接到存储服务",
-                        ));
-                    }
-                    log::error!("Failed to list directory: {}", e);
-                    return Err(HttpError::internal_error("服务器错误", "获取目录列表失败"));
-                }
-            }
+    let (provider, path_in_provider) = match select_provider(path_str) {
+        Some(v) => v,
+        None => {
+            return Err(HttpError::not_found(
+                Message::PathNotFound,
+                Message::ResourceNotFound,
+            ))
+        }
+    };
+    let entries = match provider.list_directory(&path_in_provider).await {
+        Ok(Some(entries)) => entries,
+        Ok(None) => {
+            return Err(HttpError::not_found(
+                Message::DirectoryNotFound,
+                Message::DirectoryNotFoundDesc,
+            ))
+        }
+        Err(e) => {
+            log::error!("Failed to list directory: {}", e);
+            return Err(HttpError::from_storage(&e, Message::ListDirectoryFailed));
         }
-        None => return Err(HttpError::not_found("路径不存在", "请求的资源不存在")),
     };
 
-    render::render_list(BASE_PATH, full_path.to_str().unwrap(), entries)
-        .map(|html| HttpResponse::Ok().content_type("text/html").body(html))
-        .map_err(|e| {
-            log::error!("渲染目录失败: {}", e);
-            HttpError::internal_error("服务器错误", "渲染目录时发生内部错误")
-        })
-}
+    let entries = auth::visible_entries(req, path_str, entries).await;
+    // 受保护的目录和可能隐藏部分目录项的列表因人而异，不能被共享缓存保存
+    let private = auth::listing_varies(path_str);
 
-fn validate_path(full_path: &PathBuf) -> Result<&str, HttpError> {
-    if full_path
-        .components()
-        .any(|c| c == std::path::Component::ParentDir)
-    {
-        log::warn!("检测到非法路径访问尝试: {:?}", full_path);
-        return Err(HttpError::forbidden("访问被拒绝", "请求的路径无效"));
+    let config = config::current();
+    let locale = Locale::from_request(req);
+    let (max_age, _) = config.cache.max_ages(path_str);
+    let banner = maintenance::banner(path_str, locale);
+    let validators = cache::Validators::for_listing(&entries, locale, banner.as_deref());
+    if validators.is_not_modified(req) {
+        return Ok(validators.not_modified(max_age, private));
     }
 
-    full_path.to_str().ok_or_else(|| {
-        log::warn!("检测到无效路径编码: {:?}", full_path);
-        HttpError::bad_request("无效请求", "请求路径无效")
+    let notes = render::load_notes(
+        provider.as_ref(),
+        &path_in_provider,
+        &entries,
+        &config.listing,
+    )
+    .await;
+
+    render::render_list(
+        BASE_PATH,
+        full_path.to_str().unwrap(),
+        entries,
+        notes,
+        banner,
+        locale,
+    )
+    .map(|html| {
+        let mut builder = HttpResponse::Ok();
+        validators.apply(&mut builder);
+        let cache_control = if private {
+            cache::private_cache_control(max_age)
+        } else {
+            cache::cache_control(max_age)
+        };
+        builder
+            .insert_header(cache_control)
+            .insert_header((header::VARY, "Accept-Language, Cookie"))
+            .content_type("text/html")
+            .body(html)
+    })
+    .map_err(|e| {
+        log::error!("渲染目录失败: {}", e);
+        HttpError::internal_error(Message::ServerError, Message::RenderDirectoryFailed)
     })
 }
 
-#[get("/pub{path:.*}")]
+/// URL路径中需要转义的字符（逐段转义，不包括`/`）
+const SEGMENT_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// 逐段转义路径，用于`Location`和上游的下载地址
+fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|s| utf8_percent_encode(s, SEGMENT_ENCODE_SET).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 检查请求路径并规范化：拒绝`..`，去掉`.`和空的路径段，保留结尾的`/`。
+/// 签名链接、访问控制、维护、别名和上传规则都按规范化后的路径匹配
+fn validate_path(full_path: &Path) -> Result<String, HttpError> {
+    let raw = full_path.to_str().ok_or_else(|| {
+        log::warn!("检测到无效路径编码: {:?}", full_path);
+        HttpError::bad_request(Message::InvalidRequest, Message::InvalidRequestPath)
+    })?;
+    if raw.split('/').any(|segment| segment == "..") {
+        log::warn!("检测到非法路径访问尝试: {:?}", full_path);
+        return Err(HttpError::forbidden(
+            Message::AccessDenied,
+            Message::InvalidPath,
+        ));
+    }
+    let segments: Vec<&str> = raw
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect();
+    let mut normalized = format!("/{}", segments.join("/"));
+    if !segments.is_empty() && (raw.ends_with('/') || raw.ends_with("/.")) {
+        normalized.push('/');
+    }
+    Ok(normalized)
+}
+
+#[route("/pub{path:.*}", method = "GET", method = "HEAD")]
 async fn autoindex(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
     let base_path = BASE_PATH.to_string();
     log::debug!("Base path: {:?}", base_path);
     log::debug!("Request path: {:?}", path);
     let mut req_path = path.into_inner();
     if !req_path.is_empty() && !req_path.starts_with('/') {
-        return HttpError::not_found("路径不存在", "请求的资源不存在").to_http_response();
+        return HttpError::not_found(Message::PathNotFound, Message::ResourceNotFound)
+            .to_http_response(&req);
     }
 
     if req_path.is_empty() {
@@ -141,112 +312,187 @@ async fn autoindex(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
         req_path = req_path.trim_start_matches('/').to_string();
     }
 
-    let full_path = PathBuf::from(format!("{}/{}", base_path, req_path));
-    log::debug!("Full path: {:?}", full_path);
-
-    let path_str = match validate_path(&full_path) {
+    let path_str = match validate_path(Path::new(&format!("{}/{}", base_path, req_path))) {
         Ok(s) => s,
-        Err(e) => return e.to_http_response(),
+        Err(e) => return e.to_http_response(&req),
     };
+    let path_str = path_str.as_str();
+    let full_path = PathBuf::from(path_str);
+    log::debug!("Full path: {:?}", full_path);
+
+    // 签名有效的链接不需要再提供访问凭据
+    match signed_link::verify(&req, path_str) {
+        Ok(true) => {}
+        Ok(false) => {
+            if let Err(e) = auth::authorize(&req, path_str).await {
+                return e.to_http_response(&req);
+            }
+        }
+        Err(e) => return e.to_http_response(&req),
+    }
+
+    let config = config::current();
+    if let Some(rule) = alias::find_rule(&config.aliases, path_str) {
+        return match handle_alias_request(rule, &req).await {
+            Ok(resp) => resp,
+            Err(e) => e.to_http_response(&req),
+        };
+    }
 
-    let config = CONFIG.get().expect("Config not initialized");
     if has_matching_extension(path_str, &config.download_rules.extensions) {
         match handle_download_request(path_str, &req).await {
             Ok(resp) => resp,
-            Err(e) => e.to_http_response(),
+            Err(e) => e.to_http_response(&req),
         }
     } else {
-        match handle_directory_listing(path_str, &full_path).await {
+        match handle_directory_listing(path_str, &full_path, &req).await {
             Ok(resp) => resp,
-            Err(e) => e.to_http_response(),
+            Err(e) => e.to_http_response(&req),
         }
     }
 }
 
-async fn named_file_to_response(
-    file: NamedFile,
-    range_header: Option<&str>,
-    req: &HttpRequest,
-) -> anyhow::Result<HttpResponse> {
-    let metadata = file.file().metadata().context("无法获取文件元数据")?;
-    let name = file
-        .path()
-        .file_name()
-        .ok_or(anyhow!("文件名无效"))?
-        .to_string_lossy()
-        .into_owned();
-    let mut response = file.into_response(req);
-
-    // 设置强制下载的headers
-    response.headers_mut().insert(
-        header::CONTENT_TYPE,
-        "application/octet-stream"
-            .parse()
-            .context("无效的CONTENT_TYPE header值")?,
-    );
-    response.headers_mut().insert(
-        header::CONTENT_DISPOSITION,
-        format!("attachment; filename=\"{}\"", name)
-            .parse()
-            .context("无效的CONTENT_DISPOSITION header值")?,
-    );
-
-    // 保持原有的内容长度和断点续传支持
-    response.headers_mut().insert(
-        header::CONTENT_LENGTH,
-        metadata
-            .len()
-            .to_string()
-            .parse()
-            .context("无效的CONTENT_LENGTH header值")?,
-    );
-    if let Some(range) = range_header {
-        response.headers_mut().insert(
-            header::RANGE,
-            range.to_string().parse().context("无效的RANGE header值")?,
-        );
+#[get("/")]
+async fn index(req: HttpRequest) -> HttpResponse {
+    let locale = Locale::from_request(&req);
+    match render::render_index(mirrors::statuses().await, locale) {
+        Ok(html) => HttpResponse::Ok().content_type("text/html").body(html),
+        Err(e) => {
+            log::error!("无法加载首页: {}", e);
+            HttpError::internal_error(Message::ServerError, Message::RenderIndexFailed)
+                .to_http_response(&req)
+        }
     }
-
-    Ok(response)
 }
 
-#[get("/")]
-async fn index() -> Result<NamedFile, actix_web::Error> {
-    NamedFile::open_async("templates/index.html")
-        .await
-        .map_err(|e| {
-            log::error!("无法加载首页: {}", e);
-            actix_web::error::ErrorInternalServerError("无法加载首页")
-        })
+/// 静态资源服务。主题目录中有`assets/`时优先从中查找，找不到再回退到内置资源
+fn assets_service() -> actix_files::Files {
+    let builtin = actix_files::Files::new("/assets", "templates/assets")
+        .show_files_listing()
+        .use_last_modified(true);
+    match render::theme::assets_dir() {
+        Some(dir) => {
+            log::info!("Serving theme assets from {}", dir.display());
+            actix_files::Files::new("/assets", dir)
+                .use_last_modified(true)
+                .default_handler(builtin)
+        }
+        None => builtin,
+    }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let config = config::load_config("config.toml")
+    let config = config::load_config(config::CONFIG_FILE)
         .await
         .expect("Failed to load config.toml");
-    CONFIG.set(config).expect("CONFIG already initialized");
+    config::set_current(config);
 
     let mut builder = env_logger::Builder::from_default_env();
     if std::env::var_os("RUST_LOG").is_none() {
         builder.filter_level(log::LevelFilter::Info);
     }
     builder.init();
-    HttpServer::new(|| {
+
+    // 子命令执行完后直接退出，不启动服务
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match args.first().map(String::as_str) {
+        // `mirror-proxy sign <路径>`：生成带签名的下载链接
+        Some("sign") => Some(
+            signed_link::run_cli(&args[1..], &config::current()).map(|url| println!("{}", url)),
+        ),
+        // `mirror-proxy retention [--dry-run]`：按保留规则清理旧文件
+        Some("retention") => Some(retention::run_cli(&args[1..]).await),
+        // `mirror-proxy sync [任务名...]`：立即执行同步任务
+        Some("sync") => Some(sync::run_cli(&args[1..]).await),
+        _ => None,
+    };
+    if let Some(result) = command {
+        if let Err(e) = result {
+            eprintln!("{:#}", e);
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
+
+    actix_web::rt::spawn(retention::run_scheduled());
+    actix_web::rt::spawn(sync::run_scheduled());
+    if let Some(listener) = rsync::listener().await? {
+        actix_web::rt::spawn(rsync::run(listener));
+    }
+    let server = HttpServer::new(|| {
         App::new()
-            .service(
-                actix_files::Files::new("/assets", "templates/assets")
-                    .show_files_listing()
-                    .use_last_modified(true),
-            )
+            .wrap(middleware::from_fn(compress::compress))
+            .wrap(middleware::from_fn(i18n::remember_locale))
+            .wrap(middleware::from_fn(request_id::assign_request_id))
+            // 访问日志放在最外层，以便记录请求ID响应头
+            .wrap(middleware::Logger::new(ACCESS_LOG_FORMAT))
+            .service(assets_service())
             .service(index)
             .service(autoindex)
-            .default_service(web::route().to(|| async {
-                HttpError::not_found("页面不存在", "您访问的页面不存在，请检查URL是否正确")
-                    .to_http_response()
+            .service(upload::upload_file)
+            .service(webdav::webdav)
+            .service(sync::status_page)
+            .service(sync::status_json)
+            .service(mirrors::tunasync_json)
+            .default_service(web::route().to(|req: HttpRequest| async move {
+                if req.method() != Method::GET && req.method() != Method::HEAD {
+                    return HttpError::method_not_allowed(
+                        Message::MethodNotAllowed,
+                        Message::MethodNotAllowedDesc,
+                    )
+                    .to_http_response(&req);
+                }
+                HttpError::not_found(Message::PageNotFound, Message::PageNotFoundDesc)
+                    .to_http_response(&req)
             }))
     })
     .bind("0.0.0.0:8080")?
-    .run()
-    .await
+    .run();
+
+    match admin::server()? {
+        Some(admin_server) => tokio::try_join!(server, admin_server).map(|_| ()),
+        None => server.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_path() {
+        let normalize = |path: &str| validate_path(Path::new(path)).ok();
+        assert_eq!(
+            normalize("/pub/dragonos/").as_deref(),
+            Some("/pub/dragonos/")
+        );
+        assert_eq!(
+            normalize("/pub/./prerelease/x.iso").as_deref(),
+            Some("/pub/prerelease/x.iso")
+        );
+        assert_eq!(
+            normalize("/pub/./prerelease/").as_deref(),
+            Some("/pub/prerelease/")
+        );
+        assert_eq!(
+            normalize("/pub/prerelease//internal/x").as_deref(),
+            Some("/pub/prerelease/internal/x")
+        );
+        assert_eq!(
+            normalize("/pub/prerelease/.").as_deref(),
+            Some("/pub/prerelease/")
+        );
+        assert_eq!(normalize("/pub//").as_deref(), Some("/pub/"));
+        assert!(normalize("/pub/a/../prerelease/x.iso").is_none());
+    }
+
+    #[test]
+    fn test_encode_path() {
+        assert_eq!(
+            encode_path("/pub/dragonos/dragonos 0.1.9#rc?.iso"),
+            "/pub/dragonos/dragonos%200.1.9%23rc%3F.iso"
+        );
+        assert_eq!(encode_path("/pub/100%/龙.iso"), "/pub/100%25/%E9%BE%99.iso");
+    }
 }
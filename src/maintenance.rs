@@ -0,0 +1,127 @@
+use std::{sync::RwLock, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config,
+    error::HttpError,
+    i18n::{Locale, Message},
+};
+
+lazy_static! {
+    /// 通过管理接口设置的维护窗口，为`None`时使用配置文件中的`[[maintenance]]`
+    static ref OVERRIDE: RwLock<Option<Vec<MaintenanceWindow>>> = RwLock::new(None);
+}
+
+/// 一个维护窗口：整个镜像站或某个路径（挂载点）处于维护状态
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    /// 处于维护状态的路径前缀，例如`/pub/dragonos`，为空时表示整个镜像站
+    #[serde(default)]
+    pub path: Option<String>,
+    /// 显示在目录列表顶部的说明，为空时显示默认提示
+    #[serde(default)]
+    pub message: Option<String>,
+    /// 下载被拒绝时建议客户端重试的间隔（秒）
+    #[serde(default)]
+    pub retry_after: Option<u64>,
+    #[serde(default)]
+    pub downloads: DownloadPolicy,
+}
+
+/// 维护期间下载请求的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadPolicy {
+    /// 下载照常进行，只在目录列表中显示横幅
+    #[default]
+    Continue,
+    /// 下载返回503
+    Block,
+}
+
+impl MaintenanceWindow {
+    fn covers(&self, full_path: &str) -> bool {
+        match &self.path {
+            None => true,
+            Some(prefix) => config::is_under(full_path, prefix),
+        }
+    }
+}
+
+/// 维护窗口的来源
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Config,
+    Admin,
+}
+
+/// 当前生效的维护窗口及其来源
+pub fn windows() -> (Source, Vec<MaintenanceWindow>) {
+    match OVERRIDE.read().unwrap().clone() {
+        Some(windows) => (Source::Admin, windows),
+        None => (Source::Config, config::current().maintenance.clone()),
+    }
+}
+
+/// 设置维护窗口，覆盖配置文件中的设置；`None`表示恢复使用配置文件
+pub fn set_override(windows: Option<Vec<MaintenanceWindow>>) {
+    log::info!("Maintenance windows set to {:?}", windows);
+    *OVERRIDE.write().unwrap() = windows;
+}
+
+/// 适用于该路径的维护窗口，路径前缀最长（最具体）的优先
+fn window_for(full_path: &str) -> Option<MaintenanceWindow> {
+    windows()
+        .1
+        .into_iter()
+        .filter(|w| w.covers(full_path))
+        .max_by_key(|w| w.path.as_ref().map(|p| p.len()).unwrap_or(0))
+}
+
+/// 目录列表顶部显示的维护横幅，不在维护中时返回`None`
+pub fn banner(full_path: &str, locale: Locale) -> Option<String> {
+    window_for(full_path).map(|w| {
+        w.message
+            .unwrap_or_else(|| locale.text(Message::MaintenanceBanner).to_string())
+    })
+}
+
+/// 维护窗口禁止下载时返回503错误
+pub fn check_download(full_path: &str) -> Result<(), HttpError> {
+    match window_for(full_path) {
+        Some(w) if w.downloads == DownloadPolicy::Block => Err(HttpError::service_unavailable(
+            Message::Maintenance,
+            Message::MaintenanceDesc,
+            w.retry_after.map(Duration::from_secs),
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_covers() {
+        let whole = MaintenanceWindow {
+            path: None,
+            message: None,
+            retry_after: None,
+            downloads: DownloadPolicy::Continue,
+        };
+        assert!(whole.covers("/pub/anything"));
+
+        let mount = MaintenanceWindow {
+            path: Some("/pub/dragonos/".to_string()),
+            ..whole
+        };
+        assert!(mount.covers("/pub/dragonos"));
+        assert!(mount.covers("/pub/dragonos/"));
+        assert!(mount.covers("/pub/dragonos/x.iso"));
+        assert!(!mount.covers("/pub/dragonos-old/x.iso"));
+        assert!(!mount.covers("/pub/other/"));
+    }
+}
@@ -0,0 +1,167 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{get, HttpResponse};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::{
+    auth,
+    storage::select_provider,
+    sync::{self, JobStatus, SyncState},
+    BASE_PATH,
+};
+
+/// 与tunasync的`/static/tunasync.json`相同格式的镜像状态
+#[derive(Debug, Serialize)]
+pub struct MirrorStatus {
+    pub name: String,
+    pub is_master: bool,
+    /// `success`、`syncing`、`failed`，从未同步过为`none`
+    pub status: &'static str,
+    pub last_update: String,
+    pub last_update_ts: u64,
+    pub last_started: String,
+    pub last_started_ts: u64,
+    pub last_ended: String,
+    pub last_ended_ts: u64,
+    pub next_schedule: String,
+    pub next_schedule_ts: u64,
+    pub upstream: String,
+    pub size: String,
+    /// 镜像在本站的目录，tunasync中没有这个字段
+    pub url: String,
+}
+
+/// 按tunasync的格式显示时间，未知的时间为`-`
+fn format_ts(ts: Option<u64>) -> String {
+    match ts {
+        Some(ts) => {
+            let time: DateTime<Local> = (UNIX_EPOCH + Duration::from_secs(ts)).into();
+            time.format("%Y-%m-%d %H:%M:%S %z").to_string()
+        }
+        None => "-".to_string(),
+    }
+}
+
+/// 与`du -h`类似的大小，例如`1.5G`
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["K", "M", "G", "T", "P"];
+    if size < 1024 {
+        return format!("{}B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+impl From<JobStatus> for MirrorStatus {
+    fn from(job: JobStatus) -> Self {
+        let record = job.record;
+        let status = match record.state {
+            SyncState::Pending => "none",
+            state => state.as_str(),
+        };
+        Self {
+            name: job.name,
+            is_master: true,
+            status,
+            last_update: format_ts(record.last_success),
+            last_update_ts: record.last_success.unwrap_or_default(),
+            last_started: format_ts(record.last_started),
+            last_started_ts: record.last_started.unwrap_or_default(),
+            last_ended: format_ts(record.last_finished),
+            last_ended_ts: record.last_finished.unwrap_or_default(),
+            next_schedule: format_ts(job.next_run),
+            next_schedule_ts: job.next_run.unwrap_or_default(),
+            upstream: job.upstream,
+            size: record
+                .last_report
+                .map_or_else(|| "unknown".to_string(), |r| format_size(r.size)),
+            url: format!("{}/", job.path.trim_end_matches('/')),
+        }
+    }
+}
+
+/// 不由同步任务维护的目录，以目录的修改时间作为更新时间
+fn static_mirror(name: String, modified: Option<SystemTime>) -> MirrorStatus {
+    let ts = modified
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+    MirrorStatus {
+        url: format!("{}/{}/", BASE_PATH, name),
+        name,
+        is_master: true,
+        status: "success",
+        last_update: format_ts(ts),
+        last_update_ts: ts.unwrap_or_default(),
+        last_started: format_ts(None),
+        last_started_ts: 0,
+        last_ended: format_ts(ts),
+        last_ended_ts: ts.unwrap_or_default(),
+        next_schedule: format_ts(None),
+        next_schedule_ts: 0,
+        upstream: String::new(),
+        size: "unknown".to_string(),
+    }
+}
+
+/// 所有镜像的状态：每个同步任务一项，挂载点下其余的顶层目录各一项（与任务同名时以任务为准），
+/// 受保护的目录不列出
+pub async fn statuses() -> Vec<MirrorStatus> {
+    let jobs = sync::statuses();
+    let mut mirrors = Vec::new();
+    let root = format!("{}/", BASE_PATH);
+    if let Some((provider, path)) = select_provider(&root) {
+        match provider.list_directory(&path).await {
+            Ok(Some(entries)) => {
+                for entry in entries {
+                    if entry.size.is_some() {
+                        continue;
+                    }
+                    let name = entry.name.trim_end_matches('/').to_string();
+                    let full_path = format!("{}/{}", BASE_PATH, name);
+                    if auth::is_protected(&format!("{}/", full_path))
+                        || jobs
+                            .iter()
+                            .any(|j| j.name == name || j.path.trim_end_matches('/') == full_path)
+                    {
+                        continue;
+                    }
+                    mirrors.push(static_mirror(name, entry.modified));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Failed to list mirrors under {}: {}", root, e),
+        }
+    }
+    mirrors.extend(
+        jobs.into_iter()
+            .filter(|j| !auth::is_protected(&format!("{}/", j.path.trim_end_matches('/'))))
+            .map(MirrorStatus::from),
+    );
+    mirrors.sort_by(|a, b| a.name.cmp(&b.name));
+    mirrors
+}
+
+#[get("/static/tunasync.json")]
+pub async fn tunasync_json() -> HttpResponse {
+    HttpResponse::Ok().json(statuses().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0B");
+        assert_eq!(format_size(1023), "1023B");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0G");
+        assert_eq!(format_ts(None), "-");
+    }
+}
@@ -1,27 +1,82 @@
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use askama::Template;
+use serde::Serialize;
 
-use crate::storage::StorageEntry;
+use crate::{
+    config::SiteConfig,
+    i18n::{Locale, UiStrings},
+    mirrors::MirrorStatus,
+    storage::StorageEntry,
+    sync::JobStatus,
+};
 
-#[derive(Template)]
+mod notes;
+pub mod theme;
+
+pub use notes::{load_notes, DirNotes};
+
+#[derive(Template, Serialize)]
 #[template(path = "error.html")]
 pub struct ErrorTemplate {
+    pub lang: &'static str,
+    pub ui: &'static UiStrings,
+    pub site: SiteConfig,
     pub title: String,
     pub status_code: String,
     pub message: String,
     pub error_code: String,
     pub description: String,
+    pub request_id: String,
     pub home_url: String,
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
+#[template(path = "index.html")]
+struct IndexTemplate {
+    lang: &'static str,
+    ui: &'static UiStrings,
+    site: SiteConfig,
+    mirrors: Vec<MirrorStatus>,
+}
+
+#[derive(Template, Serialize)]
 #[template(path = "autoindex.html")]
 struct AutoIndexTemplate {
+    lang: &'static str,
+    ui: &'static UiStrings,
+    site: SiteConfig,
     path: String,
     entries: Vec<IndexDirEntry>,
+    header: Option<String>,
+    readme: Option<String>,
+    /// 维护横幅
+    maintenance: Option<String>,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "sync.html")]
+struct SyncTemplate {
+    lang: &'static str,
+    ui: &'static UiStrings,
+    site: SiteConfig,
+    jobs: Vec<SyncJobRow>,
 }
 
+/// 同步状态页面中的一行，时间已经格式化
+#[derive(Serialize)]
+struct SyncJobRow {
+    name: String,
+    upstream: String,
+    path: String,
+    state: String,
+    error: String,
+    last_success: String,
+    last_failure: String,
+    next_run: String,
+}
+
+#[derive(Serialize)]
 pub struct IndexDirEntry {
     pub name: String,
     pub url: String,
@@ -45,7 +100,10 @@ impl From<StorageEntry> for IndexDirEntry {
         Self {
             name: entry.name,
             url: entry.url,
-            modified: format_time(entry.modified),
+            modified: entry
+                .modified
+                .map(format_time)
+                .unwrap_or_else(|| "-".to_owned()),
             size: format_size(entry.size),
         }
     }
@@ -74,6 +132,9 @@ pub fn render_list(
     base_path: &str,
     req_path: &str,
     src_entries: Vec<StorageEntry>,
+    notes: DirNotes,
+    maintenance: Option<String>,
+    locale: Locale,
 ) -> anyhow::Result<String> {
     let base_path = base_path.trim_end_matches('/');
     let mut entries = Vec::new();
@@ -85,9 +146,76 @@ pub fn render_list(
     });
 
     let template = AutoIndexTemplate {
+        lang: locale.tag(),
+        ui: locale.ui(),
+        site: site_config(),
         path: req_path.to_string(),
         entries,
+        header: notes.header,
+        readme: notes.readme,
+        maintenance,
     };
 
-    template.render().map_err(|e| anyhow::anyhow!(e))
+    theme::render("autoindex.html", &template)
+}
+
+pub fn render_index(mirrors: Vec<MirrorStatus>, locale: Locale) -> anyhow::Result<String> {
+    let template = IndexTemplate {
+        lang: locale.tag(),
+        ui: locale.ui(),
+        site: site_config(),
+        mirrors,
+    };
+    theme::render("index.html", &template)
+}
+
+pub fn render_sync_status(jobs: Vec<JobStatus>, locale: Locale) -> anyhow::Result<String> {
+    let ui = locale.ui();
+    let format_unix = |time: Option<u64>| {
+        time.map(|t| format_time(SystemTime::UNIX_EPOCH + Duration::from_secs(t)))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let jobs = jobs
+        .into_iter()
+        .map(|job| SyncJobRow {
+            state: job.record.state.as_str().to_string(),
+            error: job.record.last_error.unwrap_or_default(),
+            last_success: format_unix(job.record.last_success),
+            last_failure: format_unix(job.record.last_failure),
+            next_run: job
+                .next_run
+                .map_or_else(|| ui.manual_only.to_string(), |t| format_unix(Some(t))),
+            path: job.path.trim_end_matches('/').to_string(),
+            name: job.name,
+            upstream: job.upstream,
+        })
+        .collect();
+    let template = SyncTemplate {
+        lang: locale.tag(),
+        ui,
+        site: site_config(),
+        jobs,
+    };
+    theme::render("sync.html", &template)
+}
+
+pub fn site_config() -> SiteConfig {
+    crate::config::current().site.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_is_translated() {
+        crate::config::init_test_config();
+        let html = render_index(Vec::new(), Locale::En).unwrap();
+        assert!(html.contains("<h2>Sponsor DragonOS</h2>"));
+        assert!(!html.contains("赞助"));
+        assert!(!html.contains("资源中心"));
+
+        let html = render_index(Vec::new(), Locale::ZhCn).unwrap();
+        assert!(html.contains("<h2>赞助DragonOS</h2>"));
+    }
 }
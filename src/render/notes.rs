@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use pulldown_cmark::{html, Options, Parser};
+
+use crate::{
+    config::ListingConfig,
+    storage::{StorageEntry, StorageProvider},
+};
+
+/// 目录说明，类似Apache的`HeaderName`/`ReadmeName`，已转换为安全的HTML
+#[derive(Debug, Default)]
+pub struct DirNotes {
+    /// 渲染在文件列表上方
+    pub header: Option<String>,
+    /// 渲染在文件列表下方
+    pub readme: Option<String>,
+}
+
+/// 在已列出的目录项中查找说明文件，并通过存储提供者读取和渲染
+pub async fn load_notes(
+    provider: &dyn StorageProvider,
+    path_in_provider: &str,
+    entries: &[StorageEntry],
+    config: &ListingConfig,
+) -> DirNotes {
+    DirNotes {
+        header: load_first(
+            provider,
+            path_in_provider,
+            entries,
+            &config.header_files,
+            config,
+        )
+        .await,
+        readme: load_first(
+            provider,
+            path_in_provider,
+            entries,
+            &config.readme_files,
+            config,
+        )
+        .await,
+    }
+}
+
+async fn load_first(
+    provider: &dyn StorageProvider,
+    path_in_provider: &str,
+    entries: &[StorageEntry],
+    candidates: &[String],
+    config: &ListingConfig,
+) -> Option<String> {
+    let name = candidates
+        .iter()
+        .find(|c| entries.iter().any(|e| e.name == **c && e.size.is_some()))?;
+    let path = format!("{}/{}", path_in_provider.trim_end_matches('/'), name);
+
+    match provider.read_file(&path, config.max_note_size).await {
+        Ok(Some(content)) => Some(to_safe_html(name, &String::from_utf8_lossy(&content))),
+        Ok(None) => None,
+        Err(e) => {
+            log::warn!("Failed to load directory note {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// 根据文件后缀将说明文件转换为经过清理的HTML
+fn to_safe_html(name: &str, content: &str) -> String {
+    let ext = Path::new(name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    match ext.as_deref() {
+        Some("md") | Some("markdown") => {
+            let parser = Parser::new_ext(
+                content,
+                Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+            );
+            let mut out = String::new();
+            html::push_html(&mut out, parser);
+            ammonia::clean(&out)
+        }
+        Some("html") | Some("htm") => ammonia::clean(content),
+        _ => format!("<pre>{}</pre>", escape_html(content)),
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_is_sanitized() {
+        let html = to_safe_html(
+            "README.md",
+            "# Title\n\n<script>alert(1)</script>\n\n[link](javascript:alert(1))",
+        );
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("javascript:"));
+    }
+
+    #[test]
+    fn test_plain_text_is_escaped() {
+        let html = to_safe_html(".message", "a <b> & c");
+        assert_eq!(html, "<pre>a &lt;b&gt; &amp; c</pre>");
+    }
+}
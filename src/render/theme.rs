@@ -0,0 +1,307 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use askama::Template;
+use minijinja::{path_loader, Environment, ErrorKind};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::config::Config;
+
+lazy_static! {
+    static ref THEME: RwLock<Arc<Theme>> =
+        RwLock::new(Arc::new(Theme::from_config(&crate::config::current())));
+    /// askama的`{% if let Some(x) = expr %}`
+    static ref IF_LET_SOME: Regex =
+        Regex::new(r"\{%\s*if let Some\((\w+)\) = ([\w.]+)\s*%\}").unwrap();
+    /// askama的`!expr.is_empty()`
+    static ref NOT_EMPTY: Regex = Regex::new(r"!([\w.]+)\.is_empty\(\)").unwrap();
+}
+
+/// 内置模板的源码，主题模板可以`include`或`extends`它们
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "autoindex.html",
+        include_str!("../../templates/autoindex.html"),
+    ),
+    ("error.html", include_str!("../../templates/error.html")),
+    ("footer.html", include_str!("../../templates/footer.html")),
+    ("index.html", include_str!("../../templates/index.html")),
+    ("sync.html", include_str!("../../templates/sync.html")),
+];
+
+/// 返回内置模板的minijinja版本。内置模板为askama编写，其中askama专用的语法在这里改写
+fn builtin_source(name: &str) -> Option<String> {
+    let (_, source) = BUILTIN_TEMPLATES.iter().find(|(n, _)| *n == name)?;
+    let source = IF_LET_SOME.replace_all(source, "{% if $2 is not none %}{% set $1 = $2 %}");
+    Some(NOT_EMPTY.replace_all(&source, "$1").into_owned())
+}
+
+/// 运行时加载的主题，主题目录中不存在的模板会回退到编译进程序的内置模板
+pub struct Theme {
+    dir: Option<PathBuf>,
+    env: Option<Environment<'static>>,
+}
+
+impl Theme {
+    fn new(dir: Option<PathBuf>) -> Self {
+        let env = dir.as_ref().map(|dir| {
+            log::info!("Loading theme templates from {}", dir.display());
+            let mut env = Environment::new();
+            let themed = path_loader(dir);
+            env.set_loader(move |name| match themed(name)? {
+                Some(source) => Ok(Some(source)),
+                None => Ok(builtin_source(name)),
+            });
+            env
+        });
+        Self { dir, env }
+    }
+
+    fn from_config(config: &Config) -> Self {
+        Self::new(config.theme.dir.as_deref().map(PathBuf::from))
+    }
+
+    fn render<T: Template + Serialize>(&self, name: &str, template: &T) -> anyhow::Result<String> {
+        // 主题没有覆盖的模板直接使用编译好的askama版本
+        let overridden = self
+            .dir
+            .as_ref()
+            .is_some_and(|dir| dir.join(name).is_file());
+        if let Some(env) = self.env.as_ref().filter(|_| overridden) {
+            match env.get_template(name) {
+                Ok(tpl) => {
+                    return tpl.render(template).map_err(|e| {
+                        anyhow::anyhow!("Failed to render theme template {}: {}", name, e)
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::TemplateNotFound => {}
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Failed to load theme template {}: {}",
+                        name,
+                        e
+                    ));
+                }
+            }
+        }
+        template.render().map_err(|e| anyhow::anyhow!(e))
+    }
+}
+
+/// 使用主题模板（若存在）或内置模板渲染
+pub fn render<T: Template + Serialize>(name: &str, template: &T) -> anyhow::Result<String> {
+    let theme = THEME.read().unwrap().clone();
+    theme.render(name, template)
+}
+
+/// 按新的配置重新加载主题模板。静态资源目录在启动时确定，修改后需要重启
+pub fn reload(config: &Config) {
+    *THEME.write().unwrap() = Arc::new(Theme::from_config(config));
+}
+
+/// 主题中的静态资源目录，不存在时返回`None`
+pub fn assets_dir() -> Option<PathBuf> {
+    let config = crate::config::current();
+    let dir = Path::new(config.theme.dir.as_deref()?).join("assets");
+    dir.is_dir().then_some(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Template, Serialize)]
+    #[template(source = "builtin {{ name }}", ext = "txt")]
+    struct Greeting {
+        name: String,
+    }
+
+    #[test]
+    fn test_theme_overrides_and_falls_back() {
+        let dir = std::env::temp_dir().join(format!("mirror-proxy-theme-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("greeting.txt"), "theme {{ name }}").unwrap();
+
+        let theme = Theme::new(Some(dir.clone()));
+        let tpl = Greeting {
+            name: "dragon".to_string(),
+        };
+        assert_eq!(theme.render("greeting.txt", &tpl).unwrap(), "theme dragon");
+        assert_eq!(theme.render("missing.txt", &tpl).unwrap(), "builtin dragon");
+        assert_eq!(
+            Theme::new(None).render("greeting.txt", &tpl).unwrap(),
+            "builtin dragon"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_partial_override_uses_builtin_templates() {
+        let dir =
+            std::env::temp_dir().join(format!("mirror-proxy-theme-partial-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("sync.html"),
+            "<h1>{{ ui.sync_status }}</h1>{% include \"footer.html\" %}",
+        )
+        .unwrap();
+        let theme = Theme::new(Some(dir.clone()));
+
+        let env = theme.env.as_ref().unwrap();
+        let locale = crate::i18n::Locale::ZhCn;
+        let site = crate::config::SiteConfig {
+            logo: Some("/logo.png".to_string()),
+            copyright: "Theme Copyright".to_string(),
+            ..Default::default()
+        };
+        let html = theme
+            .render(
+                "sync.html",
+                &super::super::SyncTemplate {
+                    lang: locale.tag(),
+                    ui: locale.ui(),
+                    site: site.clone(),
+                    jobs: Vec::new(),
+                },
+            )
+            .unwrap();
+        assert!(html.starts_with(&format!("<h1>{}</h1>", locale.ui().sync_status)));
+        assert!(html.contains("Theme Copyright"));
+
+        // 内置模板中askama专用的语法改写后与原来的含义相同
+        let autoindex = super::super::AutoIndexTemplate {
+            lang: locale.tag(),
+            ui: locale.ui(),
+            site,
+            path: "/pub/".to_string(),
+            entries: Vec::new(),
+            header: Some("<p>header</p>".to_string()),
+            readme: None,
+            maintenance: None,
+        };
+        let html = env
+            .get_template("autoindex.html")
+            .unwrap()
+            .render(&autoindex)
+            .unwrap();
+        assert!(html.contains("class=\"site-logo\""));
+        assert!(html.contains("<p>header</p>"));
+        assert!(!html.contains("dir-readme"));
+        assert!(!html.contains("maintenance-banner"));
+        assert!(html.contains("Theme Copyright"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// askama的输出和minijinja使用的上下文
+    fn rendered<T: Template + Serialize>(tpl: T) -> (String, minijinja::Value) {
+        (
+            tpl.render().unwrap(),
+            minijinja::Value::from_serialize(&tpl),
+        )
+    }
+
+    /// 内置模板的示例数据，每个模板都有可选内容全部存在和全部缺少的两份
+    fn samples(name: &str) -> Vec<(String, minijinja::Value)> {
+        use super::super::*;
+        use crate::i18n::Locale;
+
+        let mut samples = Vec::new();
+        for (locale, full) in [(Locale::ZhCn, true), (Locale::En, false)] {
+            let site = crate::config::SiteConfig {
+                logo: full.then(|| "/logo.png".to_string()),
+                ..Default::default()
+            };
+            let (lang, ui) = (locale.tag(), locale.ui());
+            samples.push(match name {
+                "autoindex.html" | "footer.html" => {
+                    let tpl = AutoIndexTemplate {
+                        lang,
+                        ui,
+                        site: site.clone(),
+                        path: "/pub/dragonos/".to_string(),
+                        entries: vec![IndexDirEntry::parent_entry()],
+                        header: full.then(|| "<p>header</p>".to_string()),
+                        readme: full.then(|| "<p>readme</p>".to_string()),
+                        maintenance: full.then(|| "maintenance".to_string()),
+                    };
+                    rendered(tpl)
+                }
+                "error.html" => {
+                    let tpl = ErrorTemplate {
+                        lang,
+                        ui,
+                        site: site.clone(),
+                        title: "404".to_string(),
+                        status_code: "404".to_string(),
+                        message: "Not Found".to_string(),
+                        error_code: "NOT_FOUND".to_string(),
+                        description: "missing".to_string(),
+                        request_id: "abc".to_string(),
+                        home_url: "/".to_string(),
+                    };
+                    rendered(tpl)
+                }
+                "index.html" => {
+                    let mirrors = crate::mirrors::MirrorStatus {
+                        name: "dragonos".to_string(),
+                        is_master: true,
+                        status: "success",
+                        last_update: "-".to_string(),
+                        last_update_ts: 0,
+                        last_started: "-".to_string(),
+                        last_started_ts: 0,
+                        last_ended: "-".to_string(),
+                        last_ended_ts: 0,
+                        next_schedule: "-".to_string(),
+                        next_schedule_ts: 0,
+                        upstream: "rsync://example.org/dragonos/".to_string(),
+                        size: "1 GB".to_string(),
+                        url: "/pub/dragonos/".to_string(),
+                    };
+                    let tpl = IndexTemplate {
+                        lang,
+                        ui,
+                        site: site.clone(),
+                        mirrors: if full { vec![mirrors] } else { Vec::new() },
+                    };
+                    rendered(tpl)
+                }
+                "sync.html" => {
+                    let tpl = SyncTemplate {
+                        lang,
+                        ui,
+                        site: site.clone(),
+                        jobs: Vec::new(),
+                    };
+                    rendered(tpl)
+                }
+                _ => panic!("add sample data for the built-in template {}", name),
+            });
+        }
+        samples
+    }
+
+    #[test]
+    fn test_builtin_templates_render_in_minijinja() {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(minijinja::UndefinedBehavior::Strict);
+        env.set_loader(|name| Ok(builtin_source(name)));
+        for (name, _) in BUILTIN_TEMPLATES {
+            let tpl = env.get_template(name).unwrap();
+            for (askama, ctx) in samples(name) {
+                let html = tpl
+                    .render(&ctx)
+                    .unwrap_or_else(|e| panic!("{}: {:#}", name, e));
+                // minijinja还会转义`/`，除此之外应当与askama的输出相同
+                if *name != "footer.html" {
+                    assert_eq!(html.replace("&#x2f;", "/"), askama, "{}", name);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    cmp::Ordering,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Local};
+
+use crate::{
+    alias::{PatternMatcher, Version},
+    config::{self, AliasSort, RetentionRule},
+    storage::{self, select_provider, StorageEntry},
+};
+
+/// 未启用定时清理时，后台任务检查配置是否变化的间隔
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 一次清理中被删除（或在试运行中将被删除）的项
+#[derive(Debug)]
+pub struct Removal {
+    /// 完整请求路径
+    pub path: String,
+    pub modified: Option<SystemTime>,
+}
+
+impl std::fmt::Display for Removal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.modified {
+            Some(modified) => {
+                let modified: DateTime<Local> = modified.into();
+                write!(
+                    f,
+                    "{} (modified {})",
+                    self.path,
+                    modified.format("%Y-%m-%d %H:%M:%S")
+                )
+            }
+            None => write!(f, "{}", self.path),
+        }
+    }
+}
+
+/// 根据规则从目录项中选出应当删除的项，不访问存储
+fn plan(
+    rule: &RetentionRule,
+    entries: Vec<StorageEntry>,
+    now: SystemTime,
+) -> anyhow::Result<Vec<StorageEntry>> {
+    if rule.keep_last.is_none() && rule.keep_days.is_none() {
+        log::warn!(
+            "Retention rule for {} has neither keep_last nor keep_days, nothing will be deleted",
+            rule.path
+        );
+        return Ok(Vec::new());
+    }
+    let matcher = PatternMatcher::new(&rule.pattern)?;
+    let keep = rule
+        .keep
+        .iter()
+        .map(|p| PatternMatcher::new(p))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut candidates: Vec<(Version, StorageEntry)> = entries
+        .into_iter()
+        .filter_map(|e| {
+            let name = e.name.trim_end_matches('/');
+            let version = matcher.captures(name)?;
+            if keep.iter().any(|k| k.captures(name).is_some()) {
+                return None;
+            }
+            Some((Version::parse(&version), e))
+        })
+        .collect();
+    // 最新的排在前面
+    candidates.sort_by(|(va, a), (vb, b)| {
+        let ord: Ordering = match rule.sort {
+            AliasSort::Semver => va.cmp(vb).then_with(|| a.modified.cmp(&b.modified)),
+            AliasSort::Date => a.modified.cmp(&b.modified).then_with(|| va.cmp(vb)),
+        };
+        ord.reverse()
+    });
+
+    let max_age = rule.keep_days.map(|d| Duration::from_secs(d * 86400));
+    Ok(candidates
+        .into_iter()
+        .enumerate()
+        .filter(|(i, (_, e))| {
+            let recent = rule.keep_last.is_some_and(|n| *i < n);
+            let young = max_age.is_some_and(|max| {
+                // 修改时间未知时按新文件处理，不会因为`keep_days`被删除
+                e.modified
+                    .and_then(|t| now.duration_since(t).ok())
+                    .is_none_or(|age| age < max)
+            });
+            !recent && !young
+        })
+        .map(|(_, (_, e))| e)
+        .collect())
+}
+
+/// 对一条规则执行清理，`dry_run`为`true`时只返回将被删除的项
+async fn apply_rule(rule: &RetentionRule, dry_run: bool) -> anyhow::Result<Vec<Removal>> {
+    let directory = rule.path.trim_end_matches('/');
+    let (provider, path_in_provider) = select_provider(directory)
+        .ok_or_else(|| anyhow!("{} is not served by any storage", directory))?;
+    if !dry_run && !provider.capabilities().delete {
+        return Err(anyhow!(
+            "Storage of {} does not support deleting",
+            directory
+        ));
+    }
+    // 绕过缓存，以存储中的实际内容为准
+    storage::purge(directory);
+    let entries = match provider.list_directory(&path_in_provider).await? {
+        Some(entries) => entries,
+        None => return Err(anyhow!("Directory {} does not exist", directory)),
+    };
+
+    let mut removals = Vec::new();
+    for entry in plan(rule, entries, SystemTime::now())? {
+        let name = entry.name.trim_end_matches('/');
+        let removal = Removal {
+            path: format!("{}/{}", directory, name),
+            modified: entry.modified,
+        };
+        if !dry_run {
+            let path = format!("{}/{}", path_in_provider.trim_end_matches('/'), name);
+            match provider.delete(&path, true).await {
+                Ok(_) => log::info!("Retention: deleted {}", removal),
+                Err(e) => {
+                    log::error!("Retention: failed to delete {}: {}", removal.path, e);
+                    continue;
+                }
+            }
+        }
+        removals.push(removal);
+    }
+    Ok(removals)
+}
+
+/// 按配置中的所有规则执行清理，单条规则失败不影响其他规则
+pub async fn apply(dry_run: bool) -> Vec<Removal> {
+    let config = config::current();
+    let mut removals = Vec::new();
+    for rule in &config.retention.rules {
+        match apply_rule(rule, dry_run).await {
+            Ok(r) => removals.extend(r),
+            Err(e) => log::error!("Retention rule for {} failed: {:#}", rule.path, e),
+        }
+    }
+    removals
+}
+
+/// 后台定时清理任务，每次等待前重新读取配置，以便重新加载配置后生效
+pub async fn run_scheduled() {
+    loop {
+        let interval = config::current().retention.interval;
+        if interval == 0 {
+            tokio::time::sleep(IDLE_CHECK_INTERVAL).await;
+            continue;
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        if config::current().retention.rules.is_empty() {
+            continue;
+        }
+        let removals = apply(false).await;
+        log::info!("Retention run finished, {} entries deleted", removals.len());
+    }
+}
+
+/// 命令行`retention`子命令：`mirror-proxy retention [--dry-run]`
+pub async fn run_cli(args: &[String]) -> anyhow::Result<()> {
+    let dry_run = match args {
+        [] => false,
+        [flag] if flag == "--dry-run" => true,
+        _ => return Err(anyhow!("Usage: mirror-proxy retention [--dry-run]")),
+    };
+    for removal in apply(dry_run).await {
+        if dry_run {
+            println!("would delete {}", removal);
+        } else {
+            println!("deleted {}", removal);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, age_days: u64) -> StorageEntry {
+        StorageEntry {
+            name: name.to_string(),
+            url: String::new(),
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs((100 - age_days) * 86400)),
+            size: None,
+            approximate_size: false,
+        }
+    }
+
+    fn names(entries: Vec<StorageEntry>) -> Vec<String> {
+        entries.into_iter().map(|e| e.name).collect()
+    }
+
+    #[test]
+    fn test_plan() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(100 * 86400);
+        let entries = vec![
+            entry("2025-01-01/", 40),
+            entry("2025-02-01/", 30),
+            entry("2025-03-01/", 20),
+            entry("2025-04-01/", 10),
+            entry("2025-05-01/", 1),
+            entry("latest", 50),
+            entry("README.md", 60),
+        ];
+        let mut rule: RetentionRule = toml::from_str(
+            r#"
+            path = "/pub/nightly"
+            pattern = "2025-*"
+            keep_last = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            names(plan(&rule, entries.clone(), now).unwrap()),
+            ["2025-03-01/", "2025-02-01/", "2025-01-01/"]
+        );
+
+        rule.keep_days = Some(25);
+        assert_eq!(
+            names(plan(&rule, entries.clone(), now).unwrap()),
+            ["2025-02-01/", "2025-01-01/"]
+        );
+
+        rule.pattern = "*".to_string();
+        rule.keep = vec!["latest".to_string(), "*.md".to_string()];
+        assert_eq!(
+            names(plan(&rule, entries.clone(), now).unwrap()),
+            ["2025-02-01/", "2025-01-01/"]
+        );
+
+        rule.keep_last = None;
+        rule.keep_days = None;
+        assert!(plan(&rule, entries, now).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_plan_by_version() {
+        let rule: RetentionRule = toml::from_str(
+            r#"
+            path = "/pub/dragonos"
+            pattern = "dragonos-{version}.iso"
+            sort = "semver"
+            keep_last = 1
+            "#,
+        )
+        .unwrap();
+        let entries = vec![
+            entry("dragonos-0.1.10.iso", 30),
+            entry("dragonos-0.1.9.iso", 1),
+        ];
+        assert_eq!(
+            names(plan(&rule, entries, SystemTime::now()).unwrap()),
+            ["dragonos-0.1.9.iso"]
+        );
+    }
+}
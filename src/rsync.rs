@@ -0,0 +1,1554 @@
+//! 只读的rsync服务（rsync daemon协议）。
+//!
+//! 使用协议版本29：这是最后一个不需要增量递归、变长整数和校验算法协商的版本，
+//! 新版本的客户端会自动降级到该版本。只支持从服务端拉取（`--sender`），
+//! 支持递归、符号链接、属主/属组、硬链接、`--checksum`和基于块校验的增量传输，
+//! 不支持压缩（`-z`）、ACL和扩展属性。
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    net::SocketAddr,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use globset::{GlobBuilder, GlobMatcher};
+use md4::{Digest, Md4};
+use tokio::net::TcpListener;
+
+use crate::{
+    auth,
+    config::{self, has_matching_extension, RsyncModule},
+    maintenance,
+    storage::{local::is_upload_temp, select_provider},
+};
+
+const PROTOCOL_VERSION: i32 = 29;
+/// 最低支持的协议版本，更早的版本文件列表和校验格式不同
+const MIN_PROTOCOL_VERSION: i32 = 27;
+
+/// 字面数据每段的最大长度
+const CHUNK_SIZE: usize = 32 * 1024;
+/// 客户端发送的握手行的最大长度
+const MAX_LINE_LENGTH: usize = 4096;
+const MAX_ARGS: usize = 256;
+/// 块校验数量上限，防止客户端让服务端分配过多内存
+const MAX_BLOCK_COUNT: i32 = 1 << 24;
+const MAX_BLOCK_LENGTH: i32 = 1 << 29;
+const MD4_LENGTH: usize = 16;
+
+/// 多路复用消息头中的标签偏移
+const MPLEX_BASE: u32 = 7;
+const MSG_DATA: u8 = 0;
+const MSG_ERROR: u8 = 3;
+const MAX_PACKET: usize = 0xFF_FFFF;
+
+const NDX_DONE: i32 = -1;
+/// 文件列表中的错误标志（`IOERR_GENERAL`）
+const IO_ERROR_GENERAL: i32 = 1;
+
+// 文件列表项的标志位
+const XMIT_TOP_DIR: u16 = 1 << 0;
+const XMIT_SAME_MODE: u16 = 1 << 1;
+const XMIT_EXTENDED_FLAGS: u16 = 1 << 2;
+const XMIT_SAME_UID: u16 = 1 << 3;
+const XMIT_SAME_GID: u16 = 1 << 4;
+const XMIT_SAME_NAME: u16 = 1 << 5;
+const XMIT_LONG_NAME: u16 = 1 << 6;
+const XMIT_SAME_TIME: u16 = 1 << 7;
+const XMIT_HAS_IDEV_DATA: u16 = 1 << 9;
+const XMIT_SAME_DEV: u16 = 1 << 10;
+
+// 客户端请求文件时附带的标志位
+const ITEM_BASIS_TYPE_FOLLOWS: u16 = 1 << 11;
+const ITEM_XNAME_FOLLOWS: u16 = 1 << 12;
+const ITEM_TRANSFER: u16 = 1 << 15;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// 绑定rsync监听地址，配置中没有`[rsync]`时返回`None`
+pub async fn listener() -> io::Result<Option<TcpListener>> {
+    let config = config::current();
+    let rsync = match &config.rsync {
+        Some(rsync) => rsync,
+        None => return Ok(None),
+    };
+    if select_provider(crate::BASE_PATH).is_none_or(|(p, _)| !p.is_local()) {
+        log::error!("rsync requires the local storage backend, not starting it");
+        return Ok(None);
+    }
+    let listener = TcpListener::bind(&rsync.listen).await?;
+    log::info!("rsync daemon listening on {}", rsync.listen);
+    Ok(Some(listener))
+}
+
+/// 连接计数，连接结束时自动减一
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, AtomicOrdering::SeqCst);
+    }
+}
+
+/// 接受连接，每个连接在阻塞线程池中处理
+pub async fn run(listener: TcpListener) {
+    let active = Arc::new(AtomicUsize::new(0));
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::warn!("rsync: failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let config = config::current();
+        let (max_connections, timeout) = match &config.rsync {
+            Some(rsync) => (rsync.max_connections, rsync.timeout),
+            None => continue,
+        };
+        let guard = ConnectionGuard(active.clone());
+        let over_limit = active.fetch_add(1, AtomicOrdering::SeqCst) >= max_connections;
+        let stream = match stream.into_std().and_then(|s| {
+            s.set_nonblocking(false)?;
+            if timeout > 0 {
+                s.set_read_timeout(Some(Duration::from_secs(timeout)))?;
+                s.set_write_timeout(Some(Duration::from_secs(timeout)))?;
+            }
+            Ok(s)
+        }) {
+            Ok(s) => s,
+            Err(e) => {
+                log::warn!("rsync: failed to set up connection from {}: {}", peer, e);
+                continue;
+            }
+        };
+        tokio::task::spawn_blocking(move || {
+            let _guard = guard;
+            let result = stream.try_clone().and_then(|writer| {
+                let mut conn = Connection::new(BufReader::new(stream), BufWriter::new(writer));
+                if over_limit {
+                    conn.write_line(&format!(
+                        "@ERROR: max connections ({}) reached -- try again later",
+                        max_connections
+                    ))?;
+                    return conn.flush();
+                }
+                handle_connection(&mut conn, peer)
+            });
+            if let Err(e) = result {
+                log::debug!("rsync: connection from {} ended: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// rsync连接，负责基本类型的编解码和服务端输出的多路复用
+struct Connection<R, W: Write> {
+    reader: R,
+    writer: W,
+    /// 握手完成后服务端的输出都包装为多路复用的消息
+    multiplex: bool,
+    buffer: Vec<u8>,
+    bytes_read: u64,
+    bytes_written: u64,
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader,
+            writer,
+            multiplex: false,
+            buffer: Vec::new(),
+            bytes_read: 0,
+            bytes_written: 0,
+        }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.reader.read_exact(buf)?;
+        self.bytes_read += buf.len() as u64;
+        Ok(())
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let mut buf = [0; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn read_i32(&mut self) -> io::Result<i32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(i32::from_le_bytes(buf))
+    }
+
+    fn read_bytes(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; len];
+        self.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// 读取握手阶段以`\n`结尾的一行
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = Vec::new();
+        (&mut self.reader)
+            .take(MAX_LINE_LENGTH as u64 + 1)
+            .read_until(b'\n', &mut line)?;
+        if line.last() != Some(&b'\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "line too long or connection closed",
+            ));
+        }
+        self.bytes_read += line.len() as u64;
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    /// 握手阶段的文本行，不经过多路复用
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.bytes_written += data.len() as u64;
+        if !self.multiplex {
+            return self.writer.write_all(data);
+        }
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.flush_data()?;
+        }
+        Ok(())
+    }
+
+    fn write_u8(&mut self, value: u8) -> io::Result<()> {
+        self.write_all(&[value])
+    }
+
+    fn write_u16(&mut self, value: u16) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_i32(&mut self, value: i32) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    /// 64位整数，能用32位表示时只写32位
+    fn write_longint(&mut self, value: i64) -> io::Result<()> {
+        if (0..=i32::MAX as i64).contains(&value) {
+            return self.write_i32(value as i32);
+        }
+        self.write_i32(-1)?;
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_packet(&mut self, code: u8, data: &[u8]) -> io::Result<()> {
+        let header = ((MPLEX_BASE + code as u32) << 24) | data.len() as u32;
+        self.writer.write_all(&header.to_le_bytes())?;
+        self.writer.write_all(data)
+    }
+
+    fn flush_data(&mut self) -> io::Result<()> {
+        let buffer = std::mem::take(&mut self.buffer);
+        for chunk in buffer.chunks(MAX_PACKET) {
+            self.write_packet(MSG_DATA, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// 发送给客户端显示的错误信息，握手完成前没有消息通道，只记录日志
+    fn send_error(&mut self, message: &str) -> io::Result<()> {
+        log::debug!("rsync: {}", message);
+        if !self.multiplex {
+            return Ok(());
+        }
+        self.flush_data()?;
+        self.write_packet(MSG_ERROR, format!("rsync: {}\n", message).as_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_data()?;
+        self.writer.flush()
+    }
+}
+
+/// 客户端通过参数传递的选项中与发送端有关的部分
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    recursive: bool,
+    dirs: bool,
+    links: bool,
+    owner: bool,
+    group: bool,
+    hard_links: bool,
+    checksum: bool,
+    numeric_ids: bool,
+}
+
+/// 解析`--server --sender ...`参数，返回选项和请求的路径
+fn parse_args(args: &[String]) -> Result<(Options, Vec<String>), String> {
+    let mut options = Options::default();
+    let mut sender = false;
+    let mut paths = Vec::new();
+    let mut only_paths = false;
+    for arg in args {
+        if only_paths || !arg.starts_with('-') {
+            paths.push(arg.clone());
+            continue;
+        }
+        match arg.split_once('=').map_or(arg.as_str(), |(name, _)| name) {
+            "--" => only_paths = true,
+            "--server" => {}
+            "--sender" => sender = true,
+            "--numeric-ids" => options.numeric_ids = true,
+            "--compress" | "--files-from" | "--acls" | "--xattrs" | "--relative" => {
+                return Err(format!("option {} is not supported by this server", arg));
+            }
+            long if long.starts_with("--") => {}
+            short => {
+                for c in short.chars().skip(1) {
+                    match c {
+                        'r' => options.recursive = true,
+                        'd' => options.dirs = true,
+                        'l' => options.links = true,
+                        'o' => options.owner = true,
+                        'g' => options.group = true,
+                        'H' => options.hard_links = true,
+                        'c' => options.checksum = true,
+                        'z' | 'A' | 'X' | 'R' => {
+                            return Err(format!("option -{} is not supported by this server", c));
+                        }
+                        // `-e.xxx`之后是客户端的能力标志
+                        'e' => break,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    if !sender {
+        return Err("module is read only".to_string());
+    }
+    // 第一个路径是服务端的工作目录`.`
+    if !paths.is_empty() {
+        paths.remove(0);
+    }
+    Ok((options, paths))
+}
+
+/// 客户端发送的过滤规则，只支持`+`和`-`
+struct FilterRule {
+    include: bool,
+    matcher: GlobMatcher,
+    dir_only: bool,
+    /// 规则中含有`/`时匹配整个路径，否则只匹配文件名
+    whole_path: bool,
+}
+
+#[derive(Default)]
+struct Filters(Vec<FilterRule>);
+
+impl Filters {
+    fn add(&mut self, rule: &str) -> Result<(), String> {
+        let (include, pattern) = match rule.split_once(' ') {
+            Some(("-", pattern)) => (false, pattern),
+            Some(("+", pattern)) => (true, pattern),
+            _ if rule == "!" => {
+                self.0.clear();
+                return Ok(());
+            }
+            _ => return Err(format!("unsupported filter rule {:?}", rule)),
+        };
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let (pattern, whole_path) = match pattern.strip_prefix('/') {
+            Some(anchored) => (anchored.to_string(), true),
+            None if pattern.contains('/') => (format!("**/{}", pattern), true),
+            None => (pattern.to_string(), false),
+        };
+        let matcher = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("invalid filter pattern {:?}: {}", pattern, e))?
+            .compile_matcher();
+        self.0.push(FilterRule {
+            include,
+            matcher,
+            dir_only,
+            whole_path,
+        });
+        Ok(())
+    }
+
+    /// `name`是相对于传输根目录的路径，第一条匹配的规则生效
+    fn excluded(&self, name: &str, is_dir: bool) -> bool {
+        let base = name.rsplit('/').next().unwrap_or(name);
+        self.0
+            .iter()
+            .find(|r| {
+                (!r.dir_only || is_dir)
+                    && r.matcher.is_match(if r.whole_path { name } else { base })
+            })
+            .is_some_and(|r| !r.include)
+    }
+}
+
+/// 文件列表中的一项
+#[derive(Debug)]
+struct FileEntry {
+    /// 发送给客户端的名称，相对于传输根目录
+    name: Vec<u8>,
+    path: PathBuf,
+    mode: u32,
+    size: u64,
+    mtime: i64,
+    uid: u32,
+    gid: u32,
+    dev: u64,
+    ino: u64,
+    nlink: u64,
+    link_target: Option<Vec<u8>>,
+    top_dir: bool,
+}
+
+impl FileEntry {
+    fn new(name: Vec<u8>, path: PathBuf, metadata: &fs::Metadata) -> Self {
+        Self {
+            name,
+            path,
+            mode: metadata.mode(),
+            size: metadata.size(),
+            mtime: metadata.mtime(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            nlink: metadata.nlink(),
+            link_target: None,
+            top_dir: false,
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+}
+
+/// 与rsync（协议29及以上）相同的文件列表顺序：同一目录中文件在子目录之前，
+/// 目录名按带结尾`/`比较，目录紧接着它的内容，`.`最先。
+/// 客户端会按同样的规则重新排序，请求文件时使用排序后的序号，顺序不一致会传错文件
+fn compare_entries(a: &FileEntry, b: &FileEntry) -> Ordering {
+    let components = |e: &FileEntry| -> Vec<Vec<u8>> {
+        if e.name == b"." {
+            return Vec::new();
+        }
+        e.name.split(|&c| c == b'/').map(|c| c.to_vec()).collect()
+    };
+    let (ac, bc) = (components(a), components(b));
+    for i in 0.. {
+        let (x, y) = match (ac.get(i), bc.get(i)) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => (x, y),
+        };
+        let x_dir = i + 1 < ac.len() || a.is_dir();
+        let y_dir = i + 1 < bc.len() || b.is_dir();
+        let ord = match (x_dir, y_dir) {
+            (false, true) => Ordering::Less,
+            (true, false) => Ordering::Greater,
+            (true, true) => [x.as_slice(), b"/"]
+                .concat()
+                .cmp(&[y.as_slice(), b"/"].concat()),
+            (false, false) => x.cmp(y),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    unreachable!()
+}
+
+/// 规范化客户端请求的相对路径：拒绝`..`，去掉`.`和空的路径段，使访问规则按真实路径匹配。
+/// 结尾的`/`（或`/.`）表示发送目录的内容，予以保留
+fn normalize_rel(rel: &str) -> Option<String> {
+    if rel.split('/').any(|c| c == "..") {
+        return None;
+    }
+    let segments: Vec<&str> = rel
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+    let mut normalized = segments.join("/");
+    if !segments.is_empty() && (rel.ends_with('/') || rel.ends_with("/.")) {
+        normalized.push('/');
+    }
+    Some(normalized)
+}
+
+/// 遍历模块目录生成文件列表，应用与HTTP一致的访问规则
+struct FileListBuilder<'a> {
+    module: &'a RsyncModule,
+    root: PathBuf,
+    options: &'a Options,
+    filters: &'a Filters,
+    extensions: &'a std::collections::HashSet<String>,
+    entries: Vec<FileEntry>,
+    io_error: bool,
+    errors: Vec<String>,
+}
+
+impl FileListBuilder<'_> {
+    /// 是否对客户端可见。`rel`是相对于模块根目录的路径，`name`是列表中的名称
+    fn visible(&self, rel: &str, name: &str, metadata: &fs::Metadata) -> bool {
+        let file_name = rel.rsplit('/').next().unwrap_or(rel);
+        if is_upload_temp(file_name) {
+            return false;
+        }
+        let full_path = format!("{}/{}", self.module.path.trim_end_matches('/'), rel);
+        if auth::is_protected(&full_path) {
+            return false;
+        }
+        if metadata.is_file() && !has_matching_extension(&full_path, self.extensions) {
+            return false;
+        }
+        !self.filters.excluded(name, metadata.is_dir())
+    }
+
+    /// 生成一项，符号链接只在客户端要求保留链接且目标位于模块内时发送
+    fn entry(
+        &mut self,
+        name: Vec<u8>,
+        path: PathBuf,
+        metadata: &fs::Metadata,
+    ) -> Option<FileEntry> {
+        let mut entry = FileEntry::new(name, path, metadata);
+        if metadata.file_type().is_symlink() {
+            if !self.options.links {
+                return None;
+            }
+            let inside = entry
+                .path
+                .canonicalize()
+                .is_ok_and(|target| target.starts_with(&self.root));
+            if !inside {
+                return None;
+            }
+            entry.link_target = Some(
+                fs::read_link(&entry.path)
+                    .ok()?
+                    .as_os_str()
+                    .as_bytes()
+                    .to_vec(),
+            );
+        } else if !metadata.is_dir() && !metadata.is_file() {
+            return None;
+        }
+        Some(entry)
+    }
+
+    fn error(&mut self, message: String) {
+        self.io_error = true;
+        self.errors.push(message);
+    }
+
+    /// 按rsync的规则处理一个请求路径：以`/`结尾的目录发送其内容，否则发送目录本身
+    fn add_path(&mut self, rel: &str) {
+        let trailing_slash = rel.is_empty() || rel.ends_with('/');
+        let rel = rel.trim_matches('/');
+        let path = self.root.join(rel);
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(m) if rel.is_empty() || self.visible(rel, rel, &m) => m,
+            _ => {
+                self.error(format!(
+                    "link_stat \"{}\" (in {}) failed: No such file or directory (2)",
+                    rel, self.module.name
+                ));
+                return;
+            }
+        };
+        let (parent, base) = rel.rsplit_once('/').unwrap_or(("", rel));
+
+        if !metadata.is_dir() {
+            if let Some(entry) = self.entry(base.as_bytes().to_vec(), path, &metadata) {
+                self.entries.push(entry);
+            }
+            return;
+        }
+        if trailing_slash {
+            let mut entry = FileEntry::new(b".".to_vec(), path.clone(), &metadata);
+            entry.top_dir = true;
+            self.entries.push(entry);
+            if self.options.recursive || self.options.dirs {
+                self.walk(&path, rel, "");
+            }
+        } else if self.options.recursive || self.options.dirs {
+            let mut entry = FileEntry::new(base.as_bytes().to_vec(), path.clone(), &metadata);
+            entry.top_dir = true;
+            self.entries.push(entry);
+            if self.options.recursive {
+                self.walk(&path, rel, base);
+            }
+        } else {
+            log::debug!("rsync: skipping directory {}/{}", parent, base);
+        }
+    }
+
+    /// 列出目录内容，`rel`是目录相对于模块根目录的路径，`prefix`是目录在列表中的名称
+    fn walk(&mut self, dir: &Path, rel: &str, prefix: &str) {
+        let read_dir = match fs::read_dir(dir) {
+            Ok(r) => r,
+            Err(e) => {
+                self.error(format!("opendir \"{}\" failed: {}", rel, e));
+                return;
+            }
+        };
+        for child in read_dir {
+            let child = match child {
+                Ok(c) => c,
+                Err(e) => {
+                    self.error(format!("readdir \"{}\" failed: {}", rel, e));
+                    continue;
+                }
+            };
+            let file_name = child.file_name();
+            let file_name = match file_name.to_str() {
+                Some(n) => n.to_string(),
+                None => {
+                    log::debug!("rsync: skipping non UTF-8 name in {}", rel);
+                    continue;
+                }
+            };
+            let child_rel = join(rel, &file_name);
+            let name = join(prefix, &file_name);
+            let metadata = match child.metadata() {
+                Ok(m) => m,
+                Err(e) => {
+                    self.error(format!("stat \"{}\" failed: {}", child_rel, e));
+                    continue;
+                }
+            };
+            if !self.visible(&child_rel, &name, &metadata) {
+                continue;
+            }
+            let path = child.path();
+            if let Some(entry) = self.entry(name.clone().into_bytes(), path.clone(), &metadata) {
+                let is_dir = entry.is_dir();
+                self.entries.push(entry);
+                if is_dir && self.options.recursive {
+                    self.walk(&path, &child_rel, &name);
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> (Vec<FileEntry>, bool, Vec<String>) {
+        self.entries.sort_by(compare_entries);
+        self.entries.dedup_by(|a, b| a.name == b.name);
+        (self.entries, self.io_error, self.errors)
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+/// 完整文件的MD4，用于`--checksum`
+fn file_checksum(path: &Path) -> io::Result<[u8; MD4_LENGTH]> {
+    let mut file = File::open(path)?;
+    let mut hasher = Md4::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+fn send_file_list<R: BufRead, W: Write>(
+    conn: &mut Connection<R, W>,
+    entries: &[FileEntry],
+    options: &Options,
+    io_error: bool,
+) -> io::Result<()> {
+    let mut last_name: &[u8] = &[];
+    let (mut last_mode, mut last_mtime, mut last_uid, mut last_gid) = (0, 0, 0, 0);
+    let mut last_dev = None;
+    for (i, entry) in entries.iter().enumerate() {
+        let mut flags = 0;
+        if entry.top_dir && entry.is_dir() {
+            flags |= XMIT_TOP_DIR;
+        }
+        if entry.mode == last_mode {
+            flags |= XMIT_SAME_MODE;
+        }
+        if entry.mtime == last_mtime {
+            flags |= XMIT_SAME_TIME;
+        }
+        if !options.owner || (i > 0 && entry.uid == last_uid) {
+            flags |= XMIT_SAME_UID;
+        }
+        if !options.group || (i > 0 && entry.gid == last_gid) {
+            flags |= XMIT_SAME_GID;
+        }
+        let hard_linked = options.hard_links && entry.is_file() && entry.nlink > 1;
+        if hard_linked {
+            flags |= XMIT_HAS_IDEV_DATA;
+            if last_dev == Some(entry.dev) {
+                flags |= XMIT_SAME_DEV;
+            }
+        }
+        let common = last_name
+            .iter()
+            .zip(&entry.name)
+            .take(255)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = &entry.name[common..];
+        if common > 0 {
+            flags |= XMIT_SAME_NAME;
+        }
+        if suffix.len() > 255 {
+            flags |= XMIT_LONG_NAME;
+        }
+        // 标志为0表示列表结束，普通文件借用`XMIT_TOP_DIR`占位
+        if flags == 0 && !entry.is_dir() {
+            flags |= XMIT_TOP_DIR;
+        }
+        if flags & 0xFF00 != 0 || flags == 0 {
+            conn.write_u16(flags | XMIT_EXTENDED_FLAGS)?;
+        } else {
+            conn.write_u8(flags as u8)?;
+        }
+        if flags & XMIT_SAME_NAME != 0 {
+            conn.write_u8(common as u8)?;
+        }
+        if flags & XMIT_LONG_NAME != 0 {
+            conn.write_i32(suffix.len() as i32)?;
+        } else {
+            conn.write_u8(suffix.len() as u8)?;
+        }
+        conn.write_all(suffix)?;
+        conn.write_longint(entry.size as i64)?;
+        if flags & XMIT_SAME_TIME == 0 {
+            conn.write_i32(entry.mtime as i32)?;
+        }
+        if flags & XMIT_SAME_MODE == 0 {
+            conn.write_i32(entry.mode as i32)?;
+        }
+        if options.owner && flags & XMIT_SAME_UID == 0 {
+            conn.write_i32(entry.uid as i32)?;
+        }
+        if options.group && flags & XMIT_SAME_GID == 0 {
+            conn.write_i32(entry.gid as i32)?;
+        }
+        if let (true, Some(target)) = (options.links, &entry.link_target) {
+            conn.write_i32(target.len() as i32)?;
+            conn.write_all(target)?;
+        }
+        if hard_linked {
+            if flags & XMIT_SAME_DEV == 0 {
+                conn.write_longint(entry.dev as i64)?;
+            }
+            conn.write_longint(entry.ino as i64)?;
+            last_dev = Some(entry.dev);
+        }
+        if options.checksum && entry.is_file() {
+            let sum = file_checksum(&entry.path).unwrap_or_default();
+            conn.write_all(&sum)?;
+        }
+        last_name = &entry.name;
+        last_mode = entry.mode;
+        last_mtime = entry.mtime;
+        last_uid = entry.uid;
+        last_gid = entry.gid;
+    }
+    conn.write_u8(0)?;
+    // 用户名和组名列表，不发送名称，客户端按数字ID处理
+    if options.owner && !options.numeric_ids {
+        conn.write_i32(0)?;
+    }
+    if options.group && !options.numeric_ids {
+        conn.write_i32(0)?;
+    }
+    conn.write_i32(if io_error { IO_ERROR_GENERAL } else { 0 })
+}
+
+/// 客户端已有文件的块校验
+struct BlockSums {
+    count: i32,
+    block_length: i32,
+    sum2_length: i32,
+    remainder: i32,
+    sum1: Vec<u32>,
+    sum2: Vec<Vec<u8>>,
+}
+
+impl BlockSums {
+    fn read<R: BufRead, W: Write>(conn: &mut Connection<R, W>) -> io::Result<Self> {
+        let count = conn.read_i32()?;
+        let block_length = conn.read_i32()?;
+        let sum2_length = conn.read_i32()?;
+        let remainder = conn.read_i32()?;
+        if !(0..=MAX_BLOCK_COUNT).contains(&count)
+            || !(0..=MAX_BLOCK_LENGTH).contains(&block_length)
+            || !(0..=MD4_LENGTH as i32).contains(&sum2_length)
+            || !(0..=block_length).contains(&remainder)
+            || (count > 0 && block_length == 0)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid checksum header",
+            ));
+        }
+        let mut sums = Self {
+            count,
+            block_length,
+            sum2_length,
+            remainder,
+            sum1: Vec::with_capacity(count as usize),
+            sum2: Vec::with_capacity(count as usize),
+        };
+        for _ in 0..count {
+            sums.sum1.push(conn.read_i32()? as u32);
+            sums.sum2.push(conn.read_bytes(sum2_length as usize)?);
+        }
+        Ok(sums)
+    }
+
+    fn write<R: BufRead, W: Write>(&self, conn: &mut Connection<R, W>) -> io::Result<()> {
+        conn.write_i32(self.count)?;
+        conn.write_i32(self.block_length)?;
+        conn.write_i32(self.sum2_length)?;
+        conn.write_i32(self.remainder)
+    }
+
+    fn block_len(&self, i: usize) -> usize {
+        if i + 1 == self.count as usize && self.remainder != 0 {
+            self.remainder as usize
+        } else {
+            self.block_length as usize
+        }
+    }
+}
+
+/// 弱校验（rsync的`get_checksum1`），字节按有符号数计算
+fn checksum1(data: &[u8]) -> (u32, u32) {
+    let mut s1: u32 = 0;
+    let mut s2: u32 = 0;
+    for &b in data {
+        s1 = s1.wrapping_add(b as i8 as u32);
+        s2 = s2.wrapping_add(s1);
+    }
+    (s1 & 0xFFFF, s2 & 0xFFFF)
+}
+
+fn combine(s1: u32, s2: u32) -> u32 {
+    (s1 & 0xFFFF) | (s2 << 16)
+}
+
+/// 强校验：块内容之后附加校验种子的MD4
+fn checksum2(data: &[u8], seed: i32) -> [u8; MD4_LENGTH] {
+    let mut hasher = Md4::new();
+    hasher.update(data);
+    if seed != 0 {
+        hasher.update(seed.to_le_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// 按顺序读取文件，同时计算整个文件的校验，只保留尚未发送的部分
+struct Window<F> {
+    file: F,
+    size: u64,
+    start: u64,
+    data: Vec<u8>,
+    hasher: Md4,
+}
+
+impl<F: Read> Window<F> {
+    fn new(file: F, size: u64, seed: i32) -> Self {
+        let mut hasher = Md4::new();
+        hasher.update(seed.to_le_bytes());
+        Self {
+            file,
+            size,
+            start: 0,
+            data: Vec::new(),
+            hasher,
+        }
+    }
+
+    /// 确保`end`之前的内容已经读入，文件在传输中被截断时返回错误
+    fn fill(&mut self, end: u64) -> io::Result<()> {
+        let end = end.min(self.size);
+        while self.start + (self.data.len() as u64) < end {
+            let loaded = self.start + self.data.len() as u64;
+            // 超出文件大小的部分（文件在传输中变大）不读取
+            let want = (end - loaded)
+                .max(CHUNK_SIZE as u64)
+                .min(self.size - loaded) as usize;
+            let old_len = self.data.len();
+            self.data.resize(old_len + want, 0);
+            let n = self.file.read(&mut self.data[old_len..])?;
+            self.data.truncate(old_len + n);
+            if n == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "file shrank during transfer",
+                ));
+            }
+            self.hasher.update(&self.data[old_len..]);
+        }
+        Ok(())
+    }
+
+    fn slice(&self, from: u64, to: u64) -> &[u8] {
+        &self.data[(from - self.start) as usize..(to - self.start) as usize]
+    }
+
+    fn byte(&self, at: u64) -> u8 {
+        self.data[(at - self.start) as usize]
+    }
+
+    /// 丢弃`before`之前已经不再需要的内容
+    fn discard(&mut self, before: u64) {
+        if before - self.start >= 4 * CHUNK_SIZE as u64 {
+            self.data.drain(..(before - self.start) as usize);
+            self.start = before;
+        }
+    }
+}
+
+fn send_literal<R: BufRead, W: Write>(conn: &mut Connection<R, W>, data: &[u8]) -> io::Result<()> {
+    for chunk in data.chunks(CHUNK_SIZE) {
+        conn.write_i32(chunk.len() as i32)?;
+        conn.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+/// 发送文件的增量数据：与客户端已有块相同的部分只发送块序号，其余发送原始内容，
+/// 最后附上整个文件的校验
+fn send_delta<R: BufRead, W: Write, F: Read>(
+    conn: &mut Connection<R, W>,
+    file: F,
+    size: u64,
+    sums: &BlockSums,
+    seed: i32,
+) -> io::Result<()> {
+    let mut window = Window::new(file, size, seed);
+    let mut last_match = 0u64;
+
+    if sums.count > 0 {
+        let mut table: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, &sum) in sums.sum1.iter().enumerate() {
+            table.entry(sum).or_default().push(i);
+        }
+        let block_length = sums.block_length as u64;
+        let last_len = sums.block_len(sums.count as usize - 1) as u64;
+        let mut offset = 0u64;
+        let mut rolling: Option<(u32, u32, u64)> = None;
+        while offset + last_len <= size {
+            let (s1, s2, k) = match rolling {
+                Some(r) => r,
+                None => {
+                    let k = block_length.min(size - offset);
+                    window.fill(offset + k + 1)?;
+                    let (s1, s2) = checksum1(window.slice(offset, offset + k));
+                    (s1, s2, k)
+                }
+            };
+            let matched = table.get(&combine(s1, s2)).and_then(|candidates| {
+                let mut strong = None;
+                candidates.iter().copied().find(|&i| {
+                    if sums.block_len(i) as u64 != k {
+                        return false;
+                    }
+                    let sum = strong
+                        .get_or_insert_with(|| checksum2(window.slice(offset, offset + k), seed));
+                    sum[..sums.sum2_length as usize] == sums.sum2[i][..]
+                })
+            });
+            if let Some(i) = matched {
+                send_literal(conn, window.slice(last_match, offset))?;
+                conn.write_i32(-(i as i32 + 1))?;
+                offset += k;
+                last_match = offset;
+                window.discard(last_match);
+                rolling = None;
+                continue;
+            }
+            if offset - last_match >= CHUNK_SIZE as u64 {
+                send_literal(conn, window.slice(last_match, offset))?;
+                last_match = offset;
+                window.discard(last_match);
+            }
+            // 窗口后移一个字节
+            window.fill(offset + k + 2)?;
+            let out = window.byte(offset) as i8 as u32;
+            let mut s1 = s1.wrapping_sub(out);
+            let mut s2 = s2.wrapping_sub((k as u32).wrapping_mul(out));
+            let mut k = k;
+            if offset + k < size {
+                s1 = s1.wrapping_add(window.byte(offset + k) as i8 as u32);
+                s2 = s2.wrapping_add(s1);
+            } else {
+                k -= 1;
+            }
+            rolling = Some((s1 & 0xFFFF, s2 & 0xFFFF, k));
+            offset += 1;
+        }
+    }
+
+    while last_match < size {
+        let end = (last_match + CHUNK_SIZE as u64).min(size);
+        window.fill(end)?;
+        send_literal(conn, window.slice(last_match, end))?;
+        last_match = end;
+        window.discard(last_match);
+    }
+    conn.write_i32(0)?;
+    let digest: [u8; MD4_LENGTH] = window.hasher.finalize().into();
+    conn.write_all(&digest)
+}
+
+/// 客户端请求文件时发送的序号和属性，原样返回给客户端
+fn write_ndx_and_attrs<R: BufRead, W: Write>(
+    conn: &mut Connection<R, W>,
+    ndx: i32,
+    flags: u16,
+    basis_type: Option<u8>,
+    xname: Option<&[u8]>,
+) -> io::Result<()> {
+    conn.write_i32(ndx)?;
+    conn.write_u16(flags)?;
+    if let Some(basis_type) = basis_type {
+        conn.write_u8(basis_type)?;
+    }
+    if let Some(xname) = xname {
+        conn.write_all(xname)?;
+    }
+    Ok(())
+}
+
+/// 响应客户端的文件请求，直到客户端完成所有阶段
+fn send_files<R: BufRead, W: Write>(
+    conn: &mut Connection<R, W>,
+    entries: &[FileEntry],
+    seed: i32,
+) -> io::Result<()> {
+    let mut phase = 0;
+    loop {
+        // 等待客户端的下一个请求之前先把已有的输出发出去
+        conn.flush()?;
+        let ndx = conn.read_i32()?;
+        if ndx == NDX_DONE {
+            phase += 1;
+            // 协议29有两个阶段：正常传输和校验失败后的重传
+            if phase > 2 {
+                break;
+            }
+            conn.write_i32(NDX_DONE)?;
+            continue;
+        }
+        let flags = conn.read_u16()?;
+        let basis_type = if flags & ITEM_BASIS_TYPE_FOLLOWS != 0 {
+            Some(conn.read_u8()?)
+        } else {
+            None
+        };
+        let xname = if flags & ITEM_XNAME_FOLLOWS != 0 {
+            let mut raw = vec![conn.read_u8()?];
+            let mut len = raw[0] as usize;
+            if len & 0x80 != 0 {
+                raw.push(conn.read_u8()?);
+                len = (len & 0x7F) << 8 | raw[1] as usize;
+            }
+            raw.extend(conn.read_bytes(len)?);
+            Some(raw)
+        } else {
+            None
+        };
+        let entry = usize::try_from(ndx)
+            .ok()
+            .and_then(|i| entries.get(i))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid file index"))?;
+
+        if flags & ITEM_TRANSFER == 0 {
+            write_ndx_and_attrs(conn, ndx, flags, basis_type, xname.as_deref())?;
+            continue;
+        }
+        let sums = BlockSums::read(conn)?;
+        let file = match File::open(&entry.path) {
+            Ok(f) if entry.is_file() => f,
+            Ok(_) => continue,
+            Err(e) => {
+                conn.send_error(&format!(
+                    "send_files failed to open \"{}\": {}",
+                    String::from_utf8_lossy(&entry.name),
+                    e
+                ))?;
+                continue;
+            }
+        };
+        write_ndx_and_attrs(conn, ndx, flags, basis_type, xname.as_deref())?;
+        sums.write(conn)?;
+        send_delta(conn, BufReader::new(file), entry.size, &sums, seed)?;
+    }
+    conn.write_i32(NDX_DONE)
+}
+
+fn find_module(name: &str) -> Option<RsyncModule> {
+    let config = config::current();
+    config
+        .rsync
+        .as_ref()?
+        .modules
+        .iter()
+        .find(|m| m.name == name)
+        .cloned()
+}
+
+/// 模块根目录在本地文件系统中的位置，模块受认证保护或不在本地存储中时返回`None`
+fn module_root(module: &RsyncModule) -> Option<PathBuf> {
+    if auth::is_protected(&module.path) {
+        return None;
+    }
+    let (provider, path_in_provider) = select_provider(module.path.trim_end_matches('/'))?;
+    provider
+        .local_path(&path_in_provider)
+        .ok()
+        .flatten()
+        .filter(|p| p.is_dir())
+}
+
+fn handle_connection<R: BufRead, W: Write>(
+    conn: &mut Connection<R, W>,
+    peer: SocketAddr,
+) -> io::Result<()> {
+    conn.write_line(&format!("@RSYNCD: {}.0", PROTOCOL_VERSION))?;
+    conn.flush()?;
+    let greeting = conn.read_line()?;
+    let client_version = greeting
+        .strip_prefix("@RSYNCD: ")
+        .and_then(|v| v.split(['.', ' ']).next())
+        .and_then(|v| v.parse::<i32>().ok());
+    match client_version {
+        Some(v) if v >= MIN_PROTOCOL_VERSION => {}
+        _ => {
+            conn.write_line("@ERROR: protocol version mismatch -- is your shell clean?")?;
+            return conn.flush();
+        }
+    }
+
+    let config = config::current();
+    let rsync = match &config.rsync {
+        Some(rsync) => rsync,
+        None => return Ok(()),
+    };
+    if let Some(motd) = &rsync.motd {
+        for line in motd.lines() {
+            conn.write_line(line)?;
+        }
+        conn.write_line("")?;
+    }
+
+    let name = conn.read_line()?;
+    if name.is_empty() || name == "#list" {
+        log::info!("rsync: module list request from {}", peer);
+        for module in &rsync.modules {
+            if !auth::is_protected(&module.path) {
+                conn.write_line(&format!("{:<15}\t{}", module.name, module.comment))?;
+            }
+        }
+        conn.write_line("@RSYNCD: EXIT")?;
+        return conn.flush();
+    }
+
+    let module = find_module(&name);
+    let root = module.as_ref().and_then(module_root);
+    let (module, root) = match (module, root) {
+        (Some(module), Some(root)) => (module, root),
+        _ => {
+            conn.write_line(&format!("@ERROR: Unknown module '{}'", name))?;
+            return conn.flush();
+        }
+    };
+    if maintenance::check_download(&module.path).is_err() {
+        conn.write_line(&format!(
+            "@ERROR: module '{}' is under maintenance -- try again later",
+            name
+        ))?;
+        return conn.flush();
+    }
+    conn.write_line("@RSYNCD: OK")?;
+    conn.flush()?;
+
+    let mut args = Vec::new();
+    loop {
+        let arg = conn.read_line()?;
+        if arg.is_empty() {
+            break;
+        }
+        if args.len() >= MAX_ARGS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "too many arguments",
+            ));
+        }
+        args.push(arg);
+    }
+    log::info!("rsync: {} requested module {} with {:?}", peer, name, args);
+
+    // 校验种子，之后服务端的输出都经过多路复用
+    let seed = (SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i32)
+        ^ ((std::process::id() as i32) << 6);
+    conn.write_i32(seed)?;
+    conn.flush()?;
+    conn.multiplex = true;
+
+    let (options, paths) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            conn.send_error(&e)?;
+            return conn.flush();
+        }
+    };
+    let mut relative_paths = Vec::new();
+    for path in &paths {
+        let rel = match path.strip_prefix(module.name.as_str()) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => rest.trim_start_matches('/'),
+            _ => {
+                conn.send_error(&format!("path {} is not in module {}", path, module.name))?;
+                return conn.flush();
+            }
+        };
+        match normalize_rel(rel) {
+            Some(rel) => relative_paths.push(rel),
+            None => {
+                conn.send_error(&format!("invalid path {}", path))?;
+                return conn.flush();
+            }
+        }
+    }
+
+    let mut filters = Filters::default();
+    loop {
+        let len = conn.read_i32()?;
+        if len <= 0 {
+            break;
+        }
+        if len as usize > MAX_LINE_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "filter rule too long",
+            ));
+        }
+        let rule = String::from_utf8_lossy(&conn.read_bytes(len as usize)?).into_owned();
+        if let Err(e) = filters.add(&rule) {
+            conn.send_error(&e)?;
+            return conn.flush();
+        }
+    }
+
+    let build_start = Instant::now();
+    let mut builder = FileListBuilder {
+        module: &module,
+        root,
+        options: &options,
+        filters: &filters,
+        extensions: &config.download_rules.extensions,
+        entries: Vec::new(),
+        io_error: false,
+        errors: Vec::new(),
+    };
+    for rel in &relative_paths {
+        builder.add_path(rel);
+    }
+    let (entries, io_error, errors) = builder.finish();
+    let build_time = build_start.elapsed();
+    for e in errors {
+        conn.send_error(&e)?;
+    }
+    let transfer_start = Instant::now();
+    send_file_list(conn, &entries, &options, io_error)?;
+    conn.flush()?;
+    let transfer_time = transfer_start.elapsed();
+
+    send_files(conn, &entries, seed)?;
+
+    // 统计信息：读取和写入的字节数、文件总大小、生成和发送文件列表的时间（毫秒）
+    let total_size: u64 = entries.iter().map(|e| e.size).sum();
+    let (read, written) = (conn.bytes_read, conn.bytes_written);
+    conn.write_longint(read as i64)?;
+    conn.write_longint(written as i64)?;
+    conn.write_longint(total_size as i64)?;
+    conn.write_longint(build_time.as_millis() as i64)?;
+    conn.write_longint(transfer_time.as_millis() as i64)?;
+    conn.flush()?;
+    // 客户端最后发送一个`NDX_DONE`表示结束
+    let _ = conn.read_i32();
+    log::info!(
+        "rsync: sent {} bytes to {} from module {} ({} files)",
+        written,
+        peer,
+        module.name,
+        entries.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn entry(name: &str, dir: bool) -> FileEntry {
+        FileEntry {
+            name: name.as_bytes().to_vec(),
+            path: PathBuf::new(),
+            mode: if dir {
+                S_IFDIR | 0o755
+            } else {
+                S_IFREG | 0o644
+            },
+            size: 0,
+            mtime: 0,
+            uid: 0,
+            gid: 0,
+            dev: 0,
+            ino: 0,
+            nlink: 1,
+            link_target: None,
+            top_dir: false,
+        }
+    }
+
+    #[test]
+    fn test_sort_order() {
+        let mut entries = [
+            entry("b.iso", false),
+            entry("a", true),
+            entry("a/z.txt", false),
+            entry("a-b", true),
+            entry(".", true),
+            entry("a/sub", true),
+            entry("a/sub/x", false),
+            entry("zz", false),
+        ];
+        entries.sort_by(compare_entries);
+        let names: Vec<_> = entries
+            .iter()
+            .map(|e| String::from_utf8_lossy(&e.name).into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            [".", "b.iso", "zz", "a-b", "a", "a/z.txt", "a/sub", "a/sub/x"]
+        );
+    }
+
+    #[test]
+    fn test_parse_args_and_filters() {
+        let args: Vec<String> = [
+            "--server",
+            "--sender",
+            "-vlogDtpre.iLsfxC",
+            ".",
+            "dragonos/",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let (options, paths) = parse_args(&args).unwrap();
+        assert!(options.recursive && options.links && options.owner && options.group);
+        assert!(!options.checksum && !options.hard_links);
+        assert_eq!(paths, ["dragonos/"]);
+        assert!(parse_args(&args[..1]).is_err());
+        assert!(parse_args(&["--server".into(), "--sender".into(), "-az".into()]).is_err());
+
+        let mut filters = Filters::default();
+        filters.add("+ keep.iso").unwrap();
+        filters.add("- *.iso").unwrap();
+        filters.add("- /nightly/").unwrap();
+        assert!(filters.excluded("a/b.iso", false));
+        assert!(!filters.excluded("a/keep.iso", false));
+        assert!(filters.excluded("nightly", true));
+        assert!(!filters.excluded("nightly", false));
+        assert!(!filters.excluded("a/nightly", true));
+        assert!(filters.add("merge .rsync-filter").is_err());
+    }
+
+    /// 按客户端的方式用已有文件和增量数据重建文件
+    fn apply_delta(basis: &[u8], block_length: usize, stream: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut cursor = Cursor::new(stream);
+        let mut output = Vec::new();
+        loop {
+            let mut buf = [0; 4];
+            cursor.read_exact(&mut buf).unwrap();
+            let token = i32::from_le_bytes(buf);
+            if token == 0 {
+                break;
+            }
+            if token > 0 {
+                let mut data = vec![0; token as usize];
+                cursor.read_exact(&mut data).unwrap();
+                output.extend(data);
+            } else {
+                let start = (-(token + 1)) as usize * block_length;
+                let end = (start + block_length).min(basis.len());
+                output.extend(&basis[start..end]);
+            }
+        }
+        let mut digest = Vec::new();
+        cursor.read_to_end(&mut digest).unwrap();
+        (output, digest)
+    }
+
+    #[test]
+    fn test_delta_transfer() {
+        let original: Vec<u8> = (0..200_000u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut basis = original.clone();
+        basis.splice(1000..1000, [0x80, 0xFF, 1, 2, 3]);
+        basis.truncate(150_003);
+        let seed = 12345;
+        let block_length = 700;
+
+        let blocks: Vec<&[u8]> = basis.chunks(block_length).collect();
+        let sums = BlockSums {
+            count: blocks.len() as i32,
+            block_length: block_length as i32,
+            sum2_length: 4,
+            remainder: (basis.len() % block_length) as i32,
+            sum1: blocks
+                .iter()
+                .map(|b| {
+                    let (s1, s2) = checksum1(b);
+                    combine(s1, s2)
+                })
+                .collect(),
+            sum2: blocks
+                .iter()
+                .map(|b| checksum2(b, seed)[..4].to_vec())
+                .collect(),
+        };
+        let mut conn = Connection::new(Cursor::new(Vec::new()), Vec::new());
+        send_delta(
+            &mut conn,
+            Cursor::new(&original),
+            original.len() as u64,
+            &sums,
+            seed,
+        )
+        .unwrap();
+        let literal = conn.bytes_written;
+        let (output, digest) = apply_delta(&basis, block_length, &conn.writer);
+        assert!(output == original);
+        let mut hasher = Md4::new();
+        hasher.update(seed.to_le_bytes());
+        hasher.update(&original);
+        assert_eq!(digest, hasher.finalize().to_vec());
+        // 大部分内容与已有文件相同，只需要发送很少的原始数据
+        assert!(literal < 60_000, "sent {} bytes", literal);
+
+        let empty = BlockSums {
+            count: 0,
+            block_length: 0,
+            sum2_length: 0,
+            remainder: 0,
+            sum1: Vec::new(),
+            sum2: Vec::new(),
+        };
+        let mut conn = Connection::new(Cursor::new(Vec::new()), Vec::new());
+        send_delta(
+            &mut conn,
+            Cursor::new(&original),
+            original.len() as u64,
+            &empty,
+            seed,
+        )
+        .unwrap();
+        let (output, _) = apply_delta(&[], 1, &conn.writer);
+        assert!(output == original);
+    }
+
+    #[test]
+    fn test_protected_path_with_dot_segments() {
+        crate::config::init_test_config();
+        assert_eq!(normalize_rel("./prerelease").as_deref(), Some("prerelease"));
+        assert_eq!(
+            normalize_rel(".//prerelease/.").as_deref(),
+            Some("prerelease/")
+        );
+        assert_eq!(normalize_rel("").as_deref(), Some(""));
+        assert!(normalize_rel("a/../prerelease").is_none());
+
+        let root = std::env::temp_dir().join(format!("mirror-proxy-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("prerelease")).unwrap();
+        fs::write(root.join("prerelease/x.iso"), b"secret").unwrap();
+        fs::write(root.join("public.iso"), b"public").unwrap();
+        let module = RsyncModule {
+            name: "pub".to_string(),
+            path: "/pub".to_string(),
+            comment: String::new(),
+        };
+        let options = Options {
+            recursive: true,
+            ..Default::default()
+        };
+        let filters = Filters::default();
+        let extensions = ["iso".to_string()].into_iter().collect();
+        let mut builder = FileListBuilder {
+            module: &module,
+            root: root.clone(),
+            options: &options,
+            filters: &filters,
+            extensions: &extensions,
+            entries: Vec::new(),
+            io_error: false,
+            errors: Vec::new(),
+        };
+        for path in ["./prerelease/", "/prerelease", ""] {
+            builder.add_path(&normalize_rel(path).unwrap());
+        }
+        let (entries, io_error, _) = builder.finish();
+        let names: Vec<_> = entries
+            .iter()
+            .map(|e| String::from_utf8_lossy(&e.name).into_owned())
+            .collect();
+        assert_eq!(names, [".", "public.iso"]);
+        assert!(io_error);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,472 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    io::SeekFrom,
+    path::Path,
+    time::SystemTime,
+};
+
+use actix_files::NamedFile;
+use actix_web::{
+    body::SizedStream,
+    http::{
+        header::{
+            self, ContentDisposition, ContentEncoding, DispositionParam, DispositionType,
+            EntityTag, Header, HttpDate, IfMatch, IfModifiedSince, IfNoneMatch, IfUnmodifiedSince,
+        },
+        Method,
+    },
+    web::Bytes,
+    HttpRequest, HttpResponse,
+};
+use futures_util::stream;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{
+    downloads::{self, DownloadGuard},
+    error::HttpError,
+    i18n::Message,
+};
+
+/// 每次从文件读取的最大字节数
+const CHUNK_SIZE: u64 = 64 * 1024;
+/// 一个请求中最多接受的范围数，超过时忽略Range返回完整文件
+const MAX_RANGES: usize = 32;
+const BOUNDARY: &str = "MIRROR_PROXY_BYTERANGES";
+
+/// 将本地文件作为下载响应返回，支持HEAD、单/多范围请求、If-Range和条件请求。
+///
+/// `inline_extensions`中的后缀使用`inline`，其他文件使用`attachment`。
+/// `encoding`不为空时`file`是预压缩文件，内容类型和文件名按去掉压缩后缀的原文件确定。
+pub async fn file_response(
+    file: NamedFile,
+    req: &HttpRequest,
+    inline_extensions: &HashSet<String>,
+    encoding: Option<ContentEncoding>,
+) -> Result<HttpResponse, HttpError> {
+    let path = match encoding {
+        Some(_) => file.path().with_extension(""),
+        None => file.path().to_path_buf(),
+    };
+    let len = file.metadata().len();
+    let modified = file.modified();
+    let etag = modified.map(|m| file_etag(m, len, encoding));
+    let last_modified = modified.map(HttpDate::from);
+    let mut content_type = mime_guess::from_path(&path)
+        .first_or_octet_stream()
+        .to_string();
+    if content_type.starts_with("text/") {
+        content_type.push_str("; charset=utf-8");
+    }
+
+    if precondition_failed(req, etag.as_ref(), modified) {
+        return Err(HttpError::precondition_failed(
+            Message::PreconditionFailed,
+            Message::PreconditionFailedDesc,
+        ));
+    }
+    let is_not_modified = not_modified(req, etag.as_ref(), modified);
+    let mut builder = if is_not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    if let Some(etag) = &etag {
+        builder.insert_header(header::ETag(etag.clone()));
+    }
+    if let Some(last_modified) = last_modified {
+        builder.insert_header(header::LastModified(last_modified));
+    }
+    builder.insert_header((header::ACCEPT_RANGES, "bytes"));
+    if let Some(encoding) = encoding {
+        builder
+            .insert_header(encoding)
+            .insert_header((header::VARY, "Accept-Encoding"));
+    }
+    if is_not_modified {
+        return Ok(builder.finish());
+    }
+
+    builder
+        .insert_header(content_disposition(&path, inline_extensions))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"));
+
+    let ranges = if range_applies(req, etag.as_ref(), modified) {
+        req.headers()
+            .get(header::RANGE)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| parse_range(h, len))
+            .unwrap_or(RangeRequest::Full)
+    } else {
+        RangeRequest::Full
+    };
+
+    let mut parts = VecDeque::new();
+    match ranges {
+        RangeRequest::Unsatisfiable => {
+            let mut resp = HttpError::range_not_satisfiable(
+                Message::RangeNotSatisfiable,
+                Message::RangeNotSatisfiableDesc,
+            )
+            .to_http_response(req);
+            if let Ok(value) = format!("bytes */{}", len).parse() {
+                resp.headers_mut().insert(header::CONTENT_RANGE, value);
+            }
+            return Ok(resp);
+        }
+        RangeRequest::Full => {
+            builder.content_type(content_type.as_str());
+            parts.push_back(Part::File { start: 0, len });
+        }
+        RangeRequest::Ranges(ranges) if ranges.len() == 1 => {
+            let (start, range_len) = ranges[0];
+            builder
+                .status(actix_web::http::StatusCode::PARTIAL_CONTENT)
+                .content_type(content_type.as_str())
+                .insert_header((
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, start + range_len - 1, len),
+                ));
+            parts.push_back(Part::File {
+                start,
+                len: range_len,
+            });
+        }
+        RangeRequest::Ranges(ranges) => {
+            builder
+                .status(actix_web::http::StatusCode::PARTIAL_CONTENT)
+                .content_type(format!("multipart/byteranges; boundary={}", BOUNDARY));
+            for (start, range_len) in ranges {
+                parts.push_back(Part::Bytes(Bytes::from(format!(
+                    "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                    BOUNDARY,
+                    content_type,
+                    start,
+                    start + range_len - 1,
+                    len
+                ))));
+                parts.push_back(Part::File {
+                    start,
+                    len: range_len,
+                });
+            }
+            parts.push_back(Part::Bytes(Bytes::from(format!(
+                "\r\n--{}--\r\n",
+                BOUNDARY
+            ))));
+        }
+    }
+
+    let body_len = parts.iter().map(Part::len).sum();
+    if req.method() == Method::HEAD {
+        // HEAD响应不发送响应体，但Content-Length需要与GET一致
+        let empty = stream::empty::<Result<Bytes, std::io::Error>>();
+        return Ok(builder.body(SizedStream::new(body_len, empty)));
+    }
+
+    let std_file = file.file().try_clone().map_err(|e| {
+        log::error!("Failed to clone file handle {}: {}", path.display(), e);
+        HttpError::internal_error(Message::ServerError, Message::FileProcessingFailed)
+    })?;
+    let client = req
+        .connection_info()
+        .realip_remote_addr()
+        .unwrap_or("-")
+        .to_string();
+    let download = downloads::start(req.path(), &client, body_len);
+    let body = body_stream(tokio::fs::File::from_std(std_file), parts, download);
+    Ok(builder.body(SizedStream::new(body_len, body)))
+}
+
+/// 响应体的组成部分：文件中的一段或固定的字节（多范围响应的分隔头）
+enum Part {
+    File { start: u64, len: u64 },
+    Bytes(Bytes),
+}
+
+impl Part {
+    fn len(&self) -> u64 {
+        match self {
+            Part::File { len, .. } => *len,
+            Part::Bytes(b) => b.len() as u64,
+        }
+    }
+}
+
+fn body_stream(
+    file: tokio::fs::File,
+    parts: VecDeque<Part>,
+    download: DownloadGuard,
+) -> impl futures_util::Stream<Item = Result<Bytes, std::io::Error>> {
+    let state = (file, parts, download);
+    stream::try_unfold(state, |(mut file, mut parts, download)| async move {
+        let chunk = match parts.pop_front() {
+            None => return Ok(None),
+            Some(Part::Bytes(b)) => b,
+            Some(Part::File { start, len }) => {
+                let n = len.min(CHUNK_SIZE);
+                file.seek(SeekFrom::Start(start)).await?;
+                let mut buf = vec![0u8; n as usize];
+                file.read_exact(&mut buf).await?;
+                if len > n {
+                    parts.push_front(Part::File {
+                        start: start + n,
+                        len: len - n,
+                    });
+                }
+                Bytes::from(buf)
+            }
+        };
+        download.add_sent(chunk.len() as u64);
+        Ok(Some((chunk, (file, parts, download))))
+    })
+}
+
+fn file_etag(modified: SystemTime, len: u64, encoding: Option<ContentEncoding>) -> EntityTag {
+    let mtime = modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    match encoding {
+        Some(encoding) => {
+            EntityTag::new_strong(format!("{:x}-{:x}-{}", mtime, len, encoding.as_str()))
+        }
+        None => EntityTag::new_strong(format!("{:x}-{:x}", mtime, len)),
+    }
+}
+
+fn content_disposition(path: &Path, inline_extensions: &HashSet<String>) -> ContentDisposition {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let inline = path
+        .extension()
+        .map(|e| inline_extensions.contains(e.to_string_lossy().as_ref()))
+        .unwrap_or(false);
+
+    let mut parameters = Vec::new();
+    if name.is_ascii() {
+        parameters.push(DispositionParam::Filename(name));
+    } else {
+        parameters.push(DispositionParam::FilenameExt(header::ExtendedValue {
+            charset: header::Charset::Ext("UTF-8".to_string()),
+            language_tag: None,
+            value: name.into_bytes(),
+        }));
+    }
+    ContentDisposition {
+        disposition: if inline {
+            DispositionType::Inline
+        } else {
+            DispositionType::Attachment
+        },
+        parameters,
+    }
+}
+
+fn secs(t: SystemTime) -> u64 {
+    t.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// `If-Match`不匹配或`If-Unmodified-Since`之后被修改过
+fn precondition_failed(
+    req: &HttpRequest,
+    etag: Option<&EntityTag>,
+    modified: Option<SystemTime>,
+) -> bool {
+    if req.headers().contains_key(header::IF_MATCH) {
+        return match IfMatch::parse(req) {
+            Ok(IfMatch::Any) => false,
+            Ok(IfMatch::Items(tags)) => !etag.is_some_and(|e| tags.iter().any(|t| t.strong_eq(e))),
+            Err(_) => false,
+        };
+    }
+    match (IfUnmodifiedSince::parse(req), modified) {
+        (Ok(IfUnmodifiedSince(since)), Some(m)) => secs(m) > secs(since.into()),
+        _ => false,
+    }
+}
+
+fn not_modified(req: &HttpRequest, etag: Option<&EntityTag>, modified: Option<SystemTime>) -> bool {
+    if req.headers().contains_key(header::IF_NONE_MATCH) {
+        return match IfNoneMatch::parse(req) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(tags)) => etag.is_some_and(|e| tags.iter().any(|t| t.weak_eq(e))),
+            Err(_) => false,
+        };
+    }
+    match (IfModifiedSince::parse(req), modified) {
+        (Ok(IfModifiedSince(since)), Some(m)) => secs(m) <= secs(since.into()),
+        _ => false,
+    }
+}
+
+/// 没有`If-Range`，或`If-Range`与当前文件的强ETag/最后修改时间一致时，Range才生效
+fn range_applies(
+    req: &HttpRequest,
+    etag: Option<&EntityTag>,
+    modified: Option<SystemTime>,
+) -> bool {
+    let value = match req
+        .headers()
+        .get(header::IF_RANGE)
+        .and_then(|h| h.to_str().ok())
+    {
+        Some(v) => v.trim(),
+        None => return true,
+    };
+    if value.starts_with('"') || value.starts_with("W/") {
+        match (value.parse::<EntityTag>(), etag) {
+            (Ok(tag), Some(etag)) => tag.strong_eq(etag),
+            _ => false,
+        }
+    } else {
+        match (value.parse::<HttpDate>(), modified) {
+            (Ok(date), Some(m)) => secs(date.into()) == secs(m),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum RangeRequest {
+    /// 没有有效的Range，返回完整文件
+    Full,
+    /// 所有范围都超出了文件大小
+    Unsatisfiable,
+    /// (起始位置, 长度)，已排序并合并重叠的范围
+    Ranges(Vec<(u64, u64)>),
+}
+
+/// 解析`Range: bytes=...`。语法错误时按RFC 9110忽略Range
+fn parse_range(header: &str, len: u64) -> RangeRequest {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(spec) => spec,
+        None => return RangeRequest::Full,
+    };
+    // 没有任何范围的`bytes=`语法无效，按RFC 9110忽略
+    if spec.split(',').all(|item| item.trim().is_empty()) {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges = Vec::new();
+    for item in spec.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        let (start, end) = match item.split_once('-') {
+            Some(v) => v,
+            None => return RangeRequest::Full,
+        };
+        let (start, end) = (start.trim(), end.trim());
+        let range = if start.is_empty() {
+            // 后缀范围：最后N个字节
+            let n: u64 = match end.parse() {
+                Ok(n) => n,
+                Err(_) => return RangeRequest::Full,
+            };
+            if n == 0 || len == 0 {
+                continue;
+            }
+            let n = n.min(len);
+            (len - n, len - 1)
+        } else {
+            let start: u64 = match start.parse() {
+                Ok(s) => s,
+                Err(_) => return RangeRequest::Full,
+            };
+            let end: u64 = if end.is_empty() {
+                u64::MAX
+            } else {
+                match end.parse() {
+                    Ok(e) => e,
+                    Err(_) => return RangeRequest::Full,
+                }
+            };
+            if end < start {
+                return RangeRequest::Full;
+            }
+            if start >= len {
+                continue;
+            }
+            (start, end.min(len - 1))
+        };
+        ranges.push(range);
+    }
+
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    if ranges.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    ranges.sort();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    RangeRequest::Ranges(merged.into_iter().map(|(s, e)| (s, e - s + 1)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            parse_range("bytes=0-9", 100),
+            RangeRequest::Ranges(vec![(0, 10)])
+        );
+        assert_eq!(
+            parse_range("bytes=90-", 100),
+            RangeRequest::Ranges(vec![(90, 10)])
+        );
+        assert_eq!(
+            parse_range("bytes=-10", 100),
+            RangeRequest::Ranges(vec![(90, 10)])
+        );
+        assert_eq!(
+            parse_range("bytes=-500", 100),
+            RangeRequest::Ranges(vec![(0, 100)])
+        );
+        assert_eq!(
+            parse_range("bytes=95-200", 100),
+            RangeRequest::Ranges(vec![(95, 5)])
+        );
+        assert_eq!(
+            parse_range("bytes=0-4, 10-14", 100),
+            RangeRequest::Ranges(vec![(0, 5), (10, 5)])
+        );
+        // 重叠和相邻的范围会被合并
+        assert_eq!(
+            parse_range("bytes=10-20, 0-4, 5-12", 100),
+            RangeRequest::Ranges(vec![(0, 21)])
+        );
+        assert_eq!(parse_range("bytes=100-", 100), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=5-1", 100), RangeRequest::Full);
+        assert_eq!(parse_range("items=0-1", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=a-b", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=,", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=-0", 100), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        let inline: HashSet<String> = ["txt".to_string()].into_iter().collect();
+        let cd = content_disposition(Path::new("/a/readme.txt"), &inline);
+        assert_eq!(cd.to_string(), "inline; filename=\"readme.txt\"");
+        let cd = content_disposition(Path::new("/a/dragonos.iso"), &inline);
+        assert_eq!(cd.to_string(), "attachment; filename=\"dragonos.iso\"");
+        let cd = content_disposition(Path::new("/a/说明.iso"), &inline);
+        assert!(cd.to_string().starts_with("attachment; filename*=UTF-8''"));
+    }
+}
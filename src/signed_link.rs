@@ -0,0 +1,223 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::HttpRequest;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use sha2::Sha256;
+
+use crate::{
+    auth::constant_time_eq,
+    config::{self, SignedLinkConfig},
+    error::HttpError,
+    i18n::Message,
+};
+
+const EXPIRES_PARAM: &str = "expires";
+const IP_PARAM: &str = "ip";
+const SIGNATURE_PARAM: &str = "sig";
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// 计算签名：HMAC-SHA256(路径 \n 过期时间 \n 客户端IP)，使用无填充的URL安全Base64编码
+fn signature(secret: &str, path: &str, expires: u64, ip: Option<&str>) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(path.as_bytes());
+    mac.update(b"\n");
+    mac.update(expires.to_string().as_bytes());
+    mac.update(b"\n");
+    mac.update(ip.unwrap_or_default().as_bytes());
+    URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+/// 生成带签名的链接。`path`是完整的请求路径（例如`/pub/private/build.iso`），`ip`不为空时只允许该IP使用
+pub fn sign(
+    config: &SignedLinkConfig,
+    base_url: &str,
+    path: &str,
+    ttl: u64,
+    ip: Option<&str>,
+) -> anyhow::Result<String> {
+    let expires = now() + ttl;
+    let mut url = Url::parse(base_url)?.join(path.trim_start_matches('/'))?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair(EXPIRES_PARAM, &expires.to_string());
+        if let Some(ip) = ip {
+            query.append_pair(IP_PARAM, ip);
+        }
+        query.append_pair(
+            SIGNATURE_PARAM,
+            &signature(&config.secret, path, expires, ip),
+        );
+    }
+    Ok(url.to_string())
+}
+
+/// 客户端地址。只有在镜像站位于可信的反向代理之后时才使用`X-Forwarded-For`，否则客户端可以伪造它
+pub fn client_ip(req: &HttpRequest, trust_forwarded_for: bool) -> Option<String> {
+    if trust_forwarded_for {
+        req.connection_info()
+            .realip_remote_addr()
+            .map(|a| a.to_string())
+    } else {
+        req.peer_addr().map(|a| a.ip().to_string())
+    }
+}
+
+/// 检查请求中的签名。没有签名时返回`Ok(false)`，签名有效时返回`Ok(true)`，
+/// 签名无效、过期或客户端IP不符时返回403
+pub fn verify(req: &HttpRequest, full_path: &str) -> Result<bool, HttpError> {
+    let query: Vec<(String, String)> = url::form_urlencoded::parse(req.query_string().as_bytes())
+        .into_owned()
+        .collect();
+    let param = |name: &str| {
+        query
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    };
+    let sig = match param(SIGNATURE_PARAM) {
+        Some(sig) => sig,
+        None => return Ok(false),
+    };
+
+    let config = config::current();
+    let signed_links = match &config.signed_links {
+        Some(c) => c,
+        None => return Ok(false),
+    };
+    let invalid = || HttpError::forbidden(Message::AccessDenied, Message::SignedLinkInvalid);
+
+    let expires: u64 = param(EXPIRES_PARAM)
+        .and_then(|e| e.parse().ok())
+        .ok_or_else(invalid)?;
+    let ip = param(IP_PARAM);
+    let expected = signature(&signed_links.secret, full_path, expires, ip);
+    if !constant_time_eq(expected.as_bytes(), sig.as_bytes()) {
+        log::debug!("Invalid signature for {}", full_path);
+        return Err(invalid());
+    }
+    if expires < now() {
+        return Err(HttpError::forbidden(
+            Message::AccessDenied,
+            Message::SignedLinkExpired,
+        ));
+    }
+    if let Some(ip) = ip {
+        if client_ip(req, signed_links.trust_forwarded_for).as_deref() != Some(ip) {
+            log::debug!("Signed link for {} used from another address", full_path);
+            return Err(invalid());
+        }
+    }
+    Ok(true)
+}
+
+/// 命令行`sign`子命令：`mirror-proxy sign <路径> [--ttl 秒] [--ip 地址] [--base-url URL]`
+pub fn run_cli(args: &[String], config: &config::Config) -> anyhow::Result<String> {
+    let signed_links = config
+        .signed_links
+        .as_ref()
+        .ok_or_else(|| anyhow!("[signed_links] is not configured"))?;
+
+    let mut path = None;
+    let mut ttl = signed_links.default_ttl;
+    let mut ip = None;
+    let mut base_url = signed_links.base_url.clone();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| anyhow!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--ttl" => ttl = value()?.parse()?,
+            "--ip" => ip = Some(value()?),
+            "--base-url" => base_url = value()?,
+            _ if arg.starts_with("--") => return Err(anyhow!("Unknown option {}", arg)),
+            _ => path = Some(arg.clone()),
+        }
+    }
+    let path = path.ok_or_else(|| {
+        anyhow!("Usage: mirror-proxy sign <path> [--ttl SECONDS] [--ip ADDRESS] [--base-url URL]")
+    })?;
+    // 按与请求相同的规则规范化后签名，否则`//`、`/./`这样的路径签出的链接无法通过校验
+    let path = crate::validate_path(std::path::Path::new(&path))
+        .map_err(|_| anyhow!("Invalid path {}", path))?;
+    if !config::is_under(&path, crate::BASE_PATH) {
+        return Err(anyhow!("Path must start with {}", crate::BASE_PATH));
+    }
+    sign(signed_links, &base_url, &path, ttl, ip.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn config() -> SignedLinkConfig {
+        toml::from_str(r#"secret = "s3cret""#).unwrap()
+    }
+
+    #[test]
+    fn test_sign_url() {
+        let url = sign(
+            &config(),
+            "https://mirrors.dragonos.org.cn/",
+            "/pub/private/构建 1.iso",
+            60,
+            Some("10.0.0.1"),
+        )
+        .unwrap();
+        let url = Url::parse(&url).unwrap();
+        assert_eq!(url.path(), "/pub/private/%E6%9E%84%E5%BB%BA%201.iso");
+        let query: Vec<_> = url.query_pairs().map(|(k, _)| k.into_owned()).collect();
+        assert_eq!(query, ["expires", "ip", "sig"]);
+    }
+
+    #[test]
+    fn test_signature() {
+        let sig = signature("s3cret", "/pub/a.iso", 100, None);
+        assert_eq!(sig, signature("s3cret", "/pub/a.iso", 100, None));
+        assert_ne!(sig, signature("s3cret", "/pub/b.iso", 100, None));
+        assert_ne!(sig, signature("s3cret", "/pub/a.iso", 101, None));
+        assert_ne!(
+            sig,
+            signature("s3cret", "/pub/a.iso", 100, Some("10.0.0.1"))
+        );
+        assert_ne!(sig, signature("other", "/pub/a.iso", 100, None));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "1.2.3.4"))
+            .to_http_request();
+        assert_eq!(client_ip(&req, false).as_deref(), Some("10.0.0.1"));
+        assert_eq!(client_ip(&req, true).as_deref(), Some("1.2.3.4"));
+    }
+
+    #[test]
+    fn test_cli_signs_normalized_path() {
+        crate::config::init_test_config();
+        let config = config::current();
+        let args = ["/pub//private/./build.iso".to_string()];
+        let url = Url::parse(&run_cli(&args, &config).unwrap()).unwrap();
+        assert_eq!(url.path(), "/pub/private/build.iso");
+
+        let req = TestRequest::with_uri(&format!("{}?{}", url.path(), url.query().unwrap()))
+            .to_http_request();
+        assert!(verify(&req, "/pub/private/build.iso").unwrap());
+        assert!(run_cli(&["/pub/../etc/passwd".to_string()], &config).is_err());
+        assert!(run_cli(&["/public/a.iso".to_string()], &config).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! Caddy `file_server browse`，请求带`Accept: application/json`时返回json列表
+
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+
+use crate::{config::AutoindexFormat, storage::StorageEntry};
+
+use super::{exact_entry, ListingParser};
+
+pub struct CaddyJson;
+
+#[derive(Debug, Deserialize)]
+struct CaddyEntry {
+    /// 目录的名称以`/`结尾
+    name: String,
+    size: Option<usize>,
+    /// RFC 3339格式，带有源站的时区
+    mod_time: Option<String>,
+    #[serde(default)]
+    is_dir: bool,
+}
+
+impl ListingParser for CaddyJson {
+    fn format(&self) -> AutoindexFormat {
+        AutoindexFormat::Caddy
+    }
+
+    fn detect(&self, mime: &str, body: &str) -> bool {
+        mime.ends_with("json") && body.contains("\"mod_time\"") && body.contains("\"is_dir\"")
+    }
+
+    fn parse(
+        &self,
+        body: &str,
+        path: &str,
+        _timezone: FixedOffset,
+    ) -> anyhow::Result<Vec<StorageEntry>> {
+        let entries: Vec<CaddyEntry> = serde_json::from_str(body)
+            .map_err(|e| anyhow::anyhow!("Invalid Caddy browse json: {}", e))?;
+        Ok(entries
+            .into_iter()
+            .map(|e| {
+                let modified = e
+                    .mod_time
+                    .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                    .map(Into::into);
+                exact_entry(
+                    path,
+                    e.name.trim_end_matches('/'),
+                    e.is_dir,
+                    modified,
+                    e.size,
+                )
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use chrono::{Offset, Utc};
+
+    use super::*;
+    use crate::storage::autoindex::fixture;
+
+    #[test]
+    fn test_parse_caddy() {
+        let entries = CaddyJson
+            .parse(&fixture("caddy.json"), "/pub/", Utc.fix())
+            .unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["nightly/", "dragonos-v0.1.9.iso", "release notes.md"]
+        );
+        assert_eq!(entries[0].url, "/pub/nightly/");
+        assert_eq!(entries[0].size, None);
+        assert_eq!(entries[1].size, Some(123456789));
+        // 2026-10-19T12:28:00.5+08:00
+        assert_eq!(
+            entries[1].modified,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_millis(1792384080500))
+        );
+        assert_eq!(entries[2].url, "/pub/release%20notes.md");
+    }
+}
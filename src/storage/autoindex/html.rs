@@ -0,0 +1,335 @@
+//! html目录列表：nginx、Apache（FancyIndexing的表格和`<pre>`布局，以及简单列表）、
+//! lighttpd和Python `http.server`。它们都以链接列出目录项，区别在于日期和大小的位置和格式
+
+use std::{collections::HashSet, time::SystemTime};
+
+use chrono::{FixedOffset, NaiveDateTime, TimeZone};
+use percent_encoding::percent_decode_str;
+use scraper::{ElementRef, Html, Selector};
+
+use crate::{
+    config::AutoindexFormat,
+    storage::{utils::parse_file_size, StorageEntry},
+};
+
+use super::ListingParser;
+
+pub struct HtmlListing {
+    format: AutoindexFormat,
+    /// 响应中包含其中任意一个时认为是这种格式
+    markers: &'static [&'static str],
+    /// 日期和时间两列的格式
+    date_formats: &'static [&'static str],
+}
+
+/// `<pre>`中每行一项：`<a href="a.iso">a.iso</a>   19-Oct-2026 04:28   1234`
+pub const NGINX: HtmlListing = HtmlListing {
+    format: AutoindexFormat::Html,
+    markers: &["<h1>Index of "],
+    date_formats: &["%d-%b-%Y %H:%M"],
+};
+
+/// 表格或`<pre>`布局，日期为`2026-10-19 04:28`（2.2及更早为`19-Oct-2026 04:28`），大小为`1.2M`；
+/// 关闭FancyIndexing时只有链接
+pub const APACHE: HtmlListing = HtmlListing {
+    format: AutoindexFormat::Apache,
+    markers: &["?C=N;O=D", "<address>Apache", "Parent Directory</a>"],
+    date_formats: &["%Y-%m-%d %H:%M", "%d-%b-%Y %H:%M"],
+};
+
+/// 表格布局，日期为`2026-Oct-19 04:28:00`
+pub const LIGHTTPD: HtmlListing = HtmlListing {
+    format: AutoindexFormat::Lighttpd,
+    markers: &[
+        "<div class=\"foot\">lighttpd",
+        "summary=\"Directory Listing\"",
+    ],
+    date_formats: &["%Y-%b-%d %H:%M:%S"],
+};
+
+/// 只有链接，没有日期和大小
+pub const PYTHON: HtmlListing = HtmlListing {
+    format: AutoindexFormat::Python,
+    markers: &["<title>Directory listing for "],
+    date_formats: &[],
+};
+
+impl HtmlListing {
+    /// 解析链接后面的日期和大小，例如`   19-Oct-2026 04:28    1234`，目录的大小为`-`。
+    /// 返回的最后一项表示大小是否是带单位的近似值（例如`1.2M`）
+    fn parse_columns(
+        &self,
+        columns: &str,
+        timezone: FixedOffset,
+    ) -> (Option<SystemTime>, Option<usize>, bool) {
+        let parts: Vec<&str> = columns.split_whitespace().collect();
+        let date = parts.windows(2).enumerate().find_map(|(i, pair)| {
+            let text = pair.join(" ");
+            self.date_formats
+                .iter()
+                .find_map(|f| NaiveDateTime::parse_from_str(&text, f).ok())
+                .map(|local| (i, local))
+        });
+        match date {
+            Some((i, local)) => {
+                let modified = timezone
+                    .from_local_datetime(&local)
+                    .single()
+                    .map(SystemTime::from);
+                let size = parts.get(i + 2).copied();
+                (
+                    modified,
+                    size.and_then(parse_file_size),
+                    is_approximate(size),
+                )
+            }
+            // 没有日期时只有大小一列
+            None => {
+                let size = parts.first().copied();
+                (None, size.and_then(parse_file_size), is_approximate(size))
+            }
+        }
+    }
+}
+
+/// 大小是否带有`K`、`M`等单位，只有字节数（或以`B`结尾）时是精确值
+fn is_approximate(size: Option<&str>) -> bool {
+    size.and_then(|s| s.trim().chars().last())
+        .is_some_and(|c| c.is_ascii_alphabetic() && !c.eq_ignore_ascii_case(&'b'))
+}
+
+/// 链接所在行中后面的文字：表格布局取同一行后面的单元格，`<pre>`布局取链接之后到行尾的文字
+fn columns(link: ElementRef) -> String {
+    let cell = link
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .find(|e| e.value().name() == "td");
+    if let Some(cell) = cell {
+        return cell
+            .next_siblings()
+            .filter_map(ElementRef::wrap)
+            .map(|td| td.text().collect::<String>())
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+    link.next_sibling()
+        .and_then(|node| node.value().as_text().map(|t| t.to_string()))
+        .and_then(|t| t.split('\n').next().map(str::to_string))
+        .unwrap_or_default()
+}
+
+impl ListingParser for HtmlListing {
+    fn format(&self) -> AutoindexFormat {
+        self.format
+    }
+
+    fn detect(&self, mime: &str, body: &str) -> bool {
+        (mime.is_empty() || mime == "text/html") && self.markers.iter().any(|m| body.contains(m))
+    }
+
+    /// 以链接（`href`）而不是显示的文字为准：nginx和Apache都会截断过长的文件名。
+    /// 无法解析的日期和大小为`None`
+    fn parse(
+        &self,
+        html: &str,
+        path: &str,
+        timezone: FixedOffset,
+    ) -> anyhow::Result<Vec<StorageEntry>> {
+        let document = Html::parse_document(html);
+        let link_selector = Selector::parse("a[href]")
+            .map_err(|e| anyhow::anyhow!("Invalid selector: 'a[href]': {}", e))?;
+        let mut entries = Vec::new();
+        let mut seen = HashSet::new();
+
+        for element in document.select(&link_selector) {
+            let href = element.value().attr("href").unwrap_or_default();
+            let href = href.strip_prefix("./").unwrap_or(href);
+            // 跳过排序链接和指向其他位置的链接（包括Apache的上级目录），
+            // 同一项的图标和名称是两个相同的链接
+            if href.is_empty()
+                || href.starts_with('/')
+                || href.contains(['?', '#'])
+                || href.contains("://")
+                || !seen.insert(href)
+            {
+                continue;
+            }
+            let is_dir = href.ends_with('/');
+            let name = match percent_decode_str(href.trim_end_matches('/')).decode_utf8() {
+                Ok(name) if !matches!(&*name, "" | "." | "..") && !name.contains('/') => {
+                    name.into_owned()
+                }
+                _ => continue,
+            };
+            let (modified, size, approximate_size) =
+                self.parse_columns(&columns(element), timezone);
+
+            entries.push(StorageEntry {
+                name: if is_dir { format!("{}/", name) } else { name },
+                url: format!("{}/{}", path.trim_end_matches('/'), href),
+                modified,
+                size: if is_dir { None } else { size },
+                approximate_size: !is_dir && size.is_some() && approximate_size,
+            });
+        }
+        log::debug!("Parsed {:?} listing entries: {:?}", self.format, entries);
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{Offset, Utc};
+
+    use super::*;
+    use crate::storage::autoindex::{fixture, parse_timezone};
+
+    /// 2026-10-19 04:28:00 UTC
+    const MTIME: u64 = 1792384080;
+
+    fn at(secs: u64) -> Option<SystemTime> {
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    fn parse(listing: &HtmlListing, name: &str, path: &str, timezone: &str) -> Vec<StorageEntry> {
+        let timezone = parse_timezone(timezone).unwrap();
+        listing.parse(&fixture(name), path, timezone).unwrap()
+    }
+
+    fn names(entries: &[StorageEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_nginx_exact_size() {
+        let entries = parse(&NGINX, "nginx-exact-size.html", "/", "UTC");
+        assert_eq!(
+            names(&entries),
+            [
+                "dragonos/",
+                "dragonos-old/",
+                "构建产物/",
+                "dragonos-v0.1.9.iso",
+                "dragonos-v0.1.9.iso.sha256",
+                // 显示的文字被截断为`dragonos-v0.1.9-x86_64-qemu-nightly-20261019-de..>`
+                "dragonos-v0.1.9-x86_64-qemu-nightly-20261019-debug-symbols.tar.xz",
+                "R&D notes <draft>.txt",
+                "release notes.md",
+            ]
+        );
+        assert_eq!(entries[0].url, "/dragonos/");
+        assert_eq!(entries[0].size, None);
+        assert_eq!(entries[0].modified, at(MTIME));
+        assert_eq!(entries[1].modified, at(1735689600));
+        assert_eq!(entries[2].url, "/%E6%9E%84%E5%BB%BA%E4%BA%A7%E7%89%A9/");
+        // 名称互为前缀时不会取到其他行的日期和大小
+        assert_eq!(entries[3].size, Some(123456789));
+        assert_eq!(entries[3].modified, at(MTIME));
+        assert_eq!(entries[4].size, Some(86));
+        assert_eq!(entries[4].modified, at(MTIME + 60));
+        assert_eq!(entries[5].size, Some(5368709120));
+        assert_eq!(entries[6].url, "/R%26D%20notes%20%3Cdraft%3E.txt");
+        assert_eq!(entries[6].size, Some(12000));
+        assert_eq!(entries[7].size, Some(2048));
+    }
+
+    #[test]
+    fn test_nginx_human_size() {
+        let entries = parse(&NGINX, "nginx-human-size.html", "/pub", "UTC");
+        assert_eq!(entries.len(), 8);
+        assert_eq!(entries[0].size, None);
+        assert_eq!(entries[3].size, Some(118 * 1024 * 1024));
+        assert!(entries[3].approximate_size);
+        assert_eq!(entries[4].size, Some(86));
+        assert!(!entries[4].approximate_size);
+        assert_eq!(entries[5].size, Some(5 * 1024 * 1024 * 1024));
+        assert_eq!(entries[6].size, Some(12 * 1024));
+        assert_eq!(entries[7].url, "/pub/release%20notes.md");
+        assert_eq!(entries[7].modified, at(MTIME));
+    }
+
+    #[test]
+    fn test_nginx_localtime_and_missing_columns() {
+        // 源站开启了`autoindex_localtime`，12:28 +08:00即04:28 UTC
+        let entries = parse(&NGINX, "nginx-localtime.html", "/dragonos/", "+08:00");
+        assert_eq!(entries[0].name, "nightly/");
+        assert_eq!(entries[0].modified, at(MTIME));
+        assert_eq!(entries[1].modified, at(MTIME));
+        assert_eq!(entries[1].size, Some(1024));
+
+        let body = "<pre><a href=\"../\">../</a>\n<a href=\"a.iso\">a.iso</a>   1234\n\
+                    <a href=\"b.iso\">b.iso</a>   yesterday 1K\n\
+                    <a href=\"?C=N;O=D\">Name</a>\n<a href=\"https://dragonos.org/\">home</a></pre>";
+        let entries = NGINX.parse(body, "/", Utc.fix()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].modified, None);
+        assert_eq!(entries[0].size, Some(1234));
+        assert_eq!(entries[1].modified, None);
+        assert_eq!(entries[1].size, None);
+    }
+
+    #[test]
+    fn test_apache() {
+        for fixture in ["apache-fancy-table.html", "apache-fancy-pre.html"] {
+            let entries = parse(&APACHE, fixture, "/pub/", "UTC");
+            assert_eq!(
+                names(&entries),
+                [
+                    ".message",
+                    "dragonos-v0.1.9-x86_64-qemu-nightly-20261019-debug-symbols.tar.xz",
+                    "dragonos-v0.1.9.iso",
+                    "nightly/",
+                ],
+                "{}",
+                fixture
+            );
+            assert_eq!(entries[0].size, Some(120));
+            assert_eq!(entries[1].size, Some(5 * 1024 * 1024 * 1024));
+            assert_eq!(entries[2].size, Some((1.2 * 1024.0 * 1024.0) as usize));
+            assert_eq!(entries[2].modified, at(MTIME));
+            assert_eq!(entries[3].url, "/pub/nightly/");
+            assert_eq!(entries[3].size, None);
+            assert_eq!(entries[3].modified, at(MTIME + 60));
+        }
+
+        let entries = parse(&APACHE, "apache-plain.html", "/pub/", "UTC");
+        assert_eq!(names(&entries), ["dragonos-v0.1.9.iso", "nightly/"]);
+        assert_eq!(entries[0].modified, None);
+        assert_eq!(entries[0].size, None);
+    }
+
+    #[test]
+    fn test_lighttpd() {
+        let entries = parse(&LIGHTTPD, "lighttpd.html", "/pub/", "+08:00");
+        assert_eq!(
+            names(&entries),
+            ["nightly/", "dragonos-v0.1.9.iso", "release notes.md"]
+        );
+        assert_eq!(entries[0].size, None);
+        assert_eq!(entries[0].modified, at(MTIME));
+        assert_eq!(entries[1].size, Some((117.7 * 1024.0 * 1024.0) as usize));
+        assert_eq!(entries[1].modified, at(MTIME + 60));
+        assert_eq!(entries[2].url, "/pub/release%20notes.md");
+        assert_eq!(entries[2].size, Some(2048));
+    }
+
+    #[test]
+    fn test_python_http_server() {
+        let entries = parse(&PYTHON, "python-http-server.html", "/pub/", "UTC");
+        assert_eq!(
+            names(&entries),
+            [
+                ".message",
+                "dragonos-v0.1.9.iso",
+                "nightly/",
+                "release notes.md"
+            ]
+        );
+        assert!(entries
+            .iter()
+            .all(|e| e.modified.is_none() && e.size.is_none()));
+        assert_eq!(entries[3].url, "/pub/release%20notes.md");
+    }
+}
@@ -0,0 +1,171 @@
+//! 解析HTTP服务器生成的目录列表：nginx `autoindex`（html、json、xml）、Apache `mod_autoindex`、
+//! Caddy `browse`（json）、lighttpd `mod_dirlisting`和Python `http.server`
+
+use std::time::SystemTime;
+
+use chrono::{FixedOffset, Offset, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::config::AutoindexFormat;
+
+use super::StorageEntry;
+
+mod caddy;
+mod html;
+mod nginx;
+
+/// 链接中需要转义的字符，与nginx生成的html目录列表中的`href`一致
+const HREF_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// 一种目录列表格式的解析器
+pub trait ListingParser: Sync {
+    /// 对应配置中`autoindex_format`的取值
+    fn format(&self) -> AutoindexFormat;
+
+    /// 根据`Content-Type`（小写，不含参数）和响应内容判断是否是这种格式
+    fn detect(&self, mime: &str, body: &str) -> bool;
+
+    /// 解析目录`path`的列表，`path`用于拼接目录项的`url`。`timezone`用于解释不带时区的时间
+    fn parse(
+        &self,
+        body: &str,
+        path: &str,
+        timezone: FixedOffset,
+    ) -> anyhow::Result<Vec<StorageEntry>>;
+}
+
+/// 自动识别时按顺序尝试，特征更明确的格式在前，nginx html作为最后的回退
+static PARSERS: &[&dyn ListingParser] = &[
+    &caddy::CaddyJson,
+    &nginx::NginxJson,
+    &nginx::NginxXml,
+    &html::LIGHTTPD,
+    &html::APACHE,
+    &html::PYTHON,
+    &html::NGINX,
+];
+
+/// 根据配置和响应选择解析器，配置为`auto`且无法识别时按nginx html解析
+pub fn parser(
+    configured: AutoindexFormat,
+    content_type: Option<&str>,
+    body: &str,
+) -> &'static dyn ListingParser {
+    if configured != AutoindexFormat::Auto {
+        if let Some(parser) = PARSERS.iter().find(|p| p.format() == configured) {
+            return *parser;
+        }
+    }
+    let mime = content_type
+        .and_then(|c| c.split(';').next())
+        .map(|m| m.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    PARSERS
+        .iter()
+        .find(|p| p.detect(&mime, body))
+        .copied()
+        .unwrap_or(&html::NGINX)
+}
+
+/// 请求目录列表时是否需要`Accept: application/json`：Caddy只在请求json时才返回精确的信息
+pub fn wants_json(configured: AutoindexFormat) -> bool {
+    matches!(configured, AutoindexFormat::Auto | AutoindexFormat::Caddy)
+}
+
+/// 解析源站的时区设置：`UTC`或`+08:00`这样的固定偏移
+pub fn parse_timezone(timezone: &str) -> anyhow::Result<FixedOffset> {
+    let timezone = timezone.trim();
+    if timezone.eq_ignore_ascii_case("utc") || timezone.eq_ignore_ascii_case("z") {
+        return Ok(Utc.fix());
+    }
+    timezone
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid timezone '{}': {}", timezone, e))
+}
+
+/// json和xml格式给出的是原始名称，按html格式的习惯为目录加上`/`并转义链接
+fn exact_entry(
+    path: &str,
+    name: &str,
+    is_dir: bool,
+    modified: Option<SystemTime>,
+    size: Option<usize>,
+) -> StorageEntry {
+    let suffix = if is_dir { "/" } else { "" };
+    StorageEntry {
+        name: format!("{}{}", name, suffix),
+        url: format!(
+            "{}/{}{}",
+            path.trim_end_matches('/'),
+            utf8_percent_encode(name, HREF_ENCODE_SET),
+            suffix
+        ),
+        modified,
+        size: if is_dir { None } else { size },
+        approximate_size: false,
+    }
+}
+
+#[cfg(test)]
+fn fixture(name: &str) -> String {
+    let path = format!(
+        "{}/tests/fixtures/autoindex/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(content_type: &str, fixture_name: &str) -> AutoindexFormat {
+        parser(
+            AutoindexFormat::Auto,
+            Some(content_type),
+            &fixture(fixture_name),
+        )
+        .format()
+    }
+
+    #[test]
+    fn test_detect_format() {
+        use AutoindexFormat::*;
+        let html = "text/html; charset=utf-8";
+        assert_eq!(detect(html, "nginx-exact-size.html"), Html);
+        assert_eq!(detect(html, "apache-fancy-table.html"), Apache);
+        assert_eq!(detect(html, "apache-fancy-pre.html"), Apache);
+        assert_eq!(detect(html, "apache-plain.html"), Apache);
+        assert_eq!(detect(html, "lighttpd.html"), Lighttpd);
+        assert_eq!(detect(html, "python-http-server.html"), Python);
+        assert_eq!(detect("application/json", "caddy.json"), Caddy);
+        assert_eq!(
+            parser(
+                Auto,
+                Some("application/json"),
+                r#"[{"name":"a","type":"file"}]"#
+            )
+            .format(),
+            Json
+        );
+        assert_eq!(
+            parser(Auto, Some("text/xml"), "<list></list>").format(),
+            Xml
+        );
+        assert_eq!(parser(Auto, None, "").format(), Html);
+        assert_eq!(parser(Json, Some("text/html"), "").format(), Json);
+        assert!(parse_timezone("+0800").is_ok());
+        assert!(parse_timezone("Asia/Shanghai").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! nginx `autoindex_format json`和`xml`，给出完整的名称、精确的大小和UTC时间
+
+use chrono::{DateTime, FixedOffset};
+use scraper::{Html, Selector};
+use serde::Deserialize;
+
+use crate::{config::AutoindexFormat, storage::StorageEntry};
+
+use super::{exact_entry, ListingParser};
+
+pub struct NginxJson;
+
+#[derive(Debug, Deserialize)]
+struct JsonEntry {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    /// RFC 1123格式，例如`Mon, 19 Oct 2026 04:28:00 GMT`
+    mtime: String,
+    size: Option<usize>,
+}
+
+impl ListingParser for NginxJson {
+    fn format(&self) -> AutoindexFormat {
+        AutoindexFormat::Json
+    }
+
+    fn detect(&self, mime: &str, _body: &str) -> bool {
+        mime.ends_with("/json") || mime.ends_with("+json")
+    }
+
+    fn parse(
+        &self,
+        body: &str,
+        path: &str,
+        _timezone: FixedOffset,
+    ) -> anyhow::Result<Vec<StorageEntry>> {
+        let entries: Vec<JsonEntry> = serde_json::from_str(body)
+            .map_err(|e| anyhow::anyhow!("Invalid json autoindex: {}", e))?;
+        entries
+            .into_iter()
+            .map(|e| {
+                let modified = DateTime::parse_from_rfc2822(&e.mtime)
+                    .map_err(|err| anyhow::anyhow!("Invalid mtime '{}': {}", e.mtime, err))?;
+                Ok(exact_entry(
+                    path,
+                    &e.name,
+                    e.kind == "directory",
+                    Some(modified.into()),
+                    e.size,
+                ))
+            })
+            .collect()
+    }
+}
+
+pub struct NginxXml;
+
+impl ListingParser for NginxXml {
+    fn format(&self) -> AutoindexFormat {
+        AutoindexFormat::Xml
+    }
+
+    fn detect(&self, mime: &str, _body: &str) -> bool {
+        mime.ends_with("/xml") || mime.ends_with("+xml")
+    }
+
+    fn parse(
+        &self,
+        body: &str,
+        path: &str,
+        _timezone: FixedOffset,
+    ) -> anyhow::Result<Vec<StorageEntry>> {
+        // nginx的xml列表只有`<list>`中的`<directory>`、`<file>`等元素，用html解析器读取即可，
+        // 名称中的实体（例如`&amp;`）会被解码
+        let document = Html::parse_document(body);
+        let selector = Selector::parse("list > *")
+            .map_err(|e| anyhow::anyhow!("Invalid selector: 'list > *': {}", e))?;
+        let mut entries = Vec::new();
+        for element in document.select(&selector) {
+            let value = element.value();
+            let name = element.text().collect::<String>();
+            let mtime = value
+                .attr("mtime")
+                .ok_or_else(|| anyhow::anyhow!("Missing mtime for {} in xml autoindex", name))?;
+            let modified = DateTime::parse_from_rfc3339(mtime)
+                .map_err(|e| anyhow::anyhow!("Invalid mtime '{}': {}", mtime, e))?;
+            let size = value.attr("size").and_then(|s| s.parse().ok());
+            entries.push(exact_entry(
+                path,
+                &name,
+                value.name() == "directory",
+                Some(modified.into()),
+                size,
+            ));
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use chrono::{Offset, Utc};
+
+    use super::*;
+
+    /// 2026-10-19 04:28:00 UTC
+    const MTIME: u64 = 1792384080;
+
+    fn at(secs: u64) -> Option<SystemTime> {
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let body = r#"[
+{ "name":"sub dir", "type":"directory", "mtime":"Mon, 19 Oct 2026 04:28:00 GMT" },
+{ "name":"dragonos-v0.1.9-very-long-name-that-nginx-would-truncate.iso", "type":"file", "mtime":"Mon, 19 Oct 2026 04:28:00 GMT", "size":1234 },
+{ "name":"dragonos-v0.1.9.iso", "type":"file", "mtime":"Mon, 19 Oct 2026 04:29:00 GMT", "size":6 }
+]"#;
+        let entries = NginxJson.parse(body, "/dragonos/", Utc.fix()).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].name, "sub dir/");
+        assert_eq!(entries[0].url, "/dragonos/sub%20dir/");
+        assert_eq!(entries[0].size, None);
+        assert_eq!(entries[0].modified, at(MTIME));
+        assert_eq!(
+            entries[1].name,
+            "dragonos-v0.1.9-very-long-name-that-nginx-would-truncate.iso"
+        );
+        assert_eq!(entries[1].size, Some(1234));
+        assert_eq!(entries[2].size, Some(6));
+        assert_eq!(entries[2].modified, at(MTIME + 60));
+    }
+
+    #[test]
+    fn test_parse_xml() {
+        let body = r#"<?xml version="1.0"?>
+<list>
+<directory mtime="2026-10-19T04:28:00Z">sub</directory>
+<file mtime="2026-10-19T04:28:00Z" size="1234">a&amp;b.iso</file>
+<file mtime="2026-10-19T04:29:00Z" size="6">a.iso</file>
+</list>
+"#;
+        let entries = NginxXml.parse(body, "/", Utc.fix()).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].name, "sub/");
+        assert_eq!(entries[0].url, "/sub/");
+        assert_eq!(entries[1].name, "a&b.iso");
+        assert_eq!(entries[1].url, "/a&b.iso");
+        assert_eq!(entries[1].size, Some(1234));
+        assert_eq!(entries[2].name, "a.iso");
+        assert_eq!(entries[2].modified, at(MTIME + 60));
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use actix_files::NamedFile;
+use async_trait::async_trait;
+
+use super::{Capabilities, StorageEntry, StorageProvider, StorageResult, Upload};
+
+/// 每类缓存最多保存的条目数，超过后先清理过期条目，仍然超过则全部清空
+const MAX_ENTRIES: usize = 10_000;
+
+struct Cached<T> {
+    at: Instant,
+    value: T,
+}
+
+struct TtlCache<K, T> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, Cached<T>>>,
+}
+
+impl<K: std::hash::Hash + Eq, T: Clone> TtlCache<K, T> {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<T> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|c| c.at.elapsed() < self.ttl)
+            .map(|c| c.value.clone())
+    }
+
+    fn insert(&self, key: K, value: T) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, c| c.at.elapsed() < self.ttl);
+            if entries.len() >= MAX_ENTRIES {
+                entries.clear();
+            }
+        }
+        entries.insert(
+            key,
+            Cached {
+                at: Instant::now(),
+                value,
+            },
+        );
+    }
+
+    fn purge(&self, matches: impl Fn(&K) -> bool) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|k, _| !matches(k));
+        before - entries.len()
+    }
+}
+
+/// 为存储提供者缓存目录列表和小文件内容（目录说明等），`ttl`为0时不缓存。
+///
+/// 只缓存成功的结果，错误总是直接返回给调用者。
+pub struct CachedProvider {
+    inner: Arc<dyn StorageProvider>,
+    listings: TtlCache<String, Option<Vec<StorageEntry>>>,
+    files: TtlCache<(String, usize), Option<Vec<u8>>>,
+}
+
+impl CachedProvider {
+    pub fn new(inner: Arc<dyn StorageProvider>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            listings: TtlCache::new(ttl),
+            files: TtlCache::new(ttl),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        !self.listings.ttl.is_zero()
+    }
+
+    /// 清除存储路径以`prefix`开头的目录列表和文件缓存，返回清除的条目数
+    pub fn purge(&self, prefix: &str) -> usize {
+        self.listings.purge(|path| path.starts_with(prefix))
+            + self.files.purge(|(path, _)| path.starts_with(prefix))
+    }
+
+    /// 绕过缓存直接访问存储，用于健康检查
+    pub fn inner(&self) -> &Arc<dyn StorageProvider> {
+        &self.inner
+    }
+}
+
+/// 路径所在的目录，例如`/a/b/c.iso`所在的目录是`/a/b`
+fn parent_dir(path: &str) -> &str {
+    path.trim_end_matches('/')
+        .rsplit_once('/')
+        .map(|(dir, _)| dir)
+        .unwrap_or("")
+}
+
+#[async_trait]
+impl StorageProvider for CachedProvider {
+    async fn list_directory(
+        &self,
+        path_in_provider: &str,
+    ) -> StorageResult<Option<Vec<StorageEntry>>> {
+        if !self.enabled() {
+            return self.inner.list_directory(path_in_provider).await;
+        }
+        if let Some(entries) = self.listings.get(&path_in_provider.to_string()) {
+            return Ok(entries);
+        }
+        let entries = self.inner.list_directory(path_in_provider).await?;
+        self.listings
+            .insert(path_in_provider.to_string(), entries.clone());
+        Ok(entries)
+    }
+
+    fn path_in_provider(&self, full_path: &str) -> Option<String> {
+        self.inner.path_in_provider(full_path)
+    }
+
+    async fn get_download_url(
+        &self,
+        full_path: &str,
+        client_ip: Option<&str>,
+    ) -> StorageResult<Option<String>> {
+        self.inner.get_download_url(full_path, client_ip).await
+    }
+
+    fn is_local(&self) -> bool {
+        self.inner.is_local()
+    }
+
+    fn local_path(&self, path_in_provider: &str) -> StorageResult<Option<PathBuf>> {
+        self.inner.local_path(path_in_provider)
+    }
+
+    async fn stream_file(&self, path_in_provider: &str) -> StorageResult<Option<NamedFile>> {
+        self.inner.stream_file(path_in_provider).await
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    async fn put(&self, path_in_provider: &str) -> StorageResult<Box<dyn Upload>> {
+        self.inner.put(path_in_provider).await
+    }
+
+    async fn delete(&self, path_in_provider: &str, recursive: bool) -> StorageResult<bool> {
+        let deleted = self.inner.delete(path_in_provider, recursive).await?;
+        self.purge(parent_dir(path_in_provider));
+        Ok(deleted)
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> StorageResult<bool> {
+        let renamed = self.inner.rename(from, to).await?;
+        // 目标的上级目录可能是新建的，它们的列表也已过期
+        self.purge("");
+        Ok(renamed)
+    }
+
+    async fn mkdir(&self, path_in_provider: &str) -> StorageResult<()> {
+        self.inner.mkdir(path_in_provider).await?;
+        self.purge("");
+        Ok(())
+    }
+
+    async fn read_file(
+        &self,
+        path_in_provider: &str,
+        max_size: usize,
+    ) -> StorageResult<Option<Vec<u8>>> {
+        if !self.enabled() {
+            return self.inner.read_file(path_in_provider, max_size).await;
+        }
+        let key = (path_in_provider.to_string(), max_size);
+        if let Some(content) = self.files.get(&key) {
+            return Ok(content);
+        }
+        let content = self.inner.read_file(path_in_provider, max_size).await?;
+        self.files.insert(key, content.clone());
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::SystemTime;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct CountingProvider {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl StorageProvider for CountingProvider {
+        async fn list_directory(
+            &self,
+            path_in_provider: &str,
+        ) -> StorageResult<Option<Vec<StorageEntry>>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(vec![StorageEntry {
+                name: "a.iso".to_string(),
+                url: format!("{}/a.iso", path_in_provider),
+                modified: Some(SystemTime::UNIX_EPOCH),
+                size: Some(1),
+                approximate_size: false,
+            }]))
+        }
+
+        fn path_in_provider(&self, full_path: &str) -> Option<String> {
+            Some(full_path.to_string())
+        }
+
+        async fn get_download_url(
+            &self,
+            _full_path: &str,
+            _client_ip: Option<&str>,
+        ) -> StorageResult<Option<String>> {
+            Ok(None)
+        }
+
+        async fn read_file(
+            &self,
+            _path_in_provider: &str,
+            _max_size: usize,
+        ) -> StorageResult<Option<Vec<u8>>> {
+            Ok(None)
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_listing_cache_and_purge() {
+        let inner = Arc::new(CountingProvider::default());
+        let cached = CachedProvider::new(inner.clone(), Duration::from_secs(60));
+
+        cached.list_directory("/dragonos/").await.unwrap();
+        cached.list_directory("/dragonos/").await.unwrap();
+        cached.list_directory("/other/").await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+
+        assert_eq!(cached.purge("/dragonos"), 1);
+        cached.list_directory("/dragonos/").await.unwrap();
+        cached.list_directory("/other/").await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_web::test]
+    async fn test_zero_ttl_disables_cache() {
+        let inner = Arc::new(CountingProvider::default());
+        let cached = CachedProvider::new(inner.clone(), Duration::ZERO);
+        cached.list_directory("/").await.unwrap();
+        cached.list_directory("/").await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+}
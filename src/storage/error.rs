@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+/// 存储提供者返回的错误。资源不存在不属于错误，由`Ok(None)`表示。
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    /// 无法连接到上游存储
+    #[error("Failed to connect to upstream storage: {0}")]
+    Connect(String),
+    /// 上游存储响应超时
+    #[error("Upstream storage timed out: {0}")]
+    Timeout(String),
+    /// 上游存储暂时不可用（例如返回503）
+    #[error("Upstream storage is temporarily unavailable")]
+    Unavailable { retry_after: Option<Duration> },
+    /// 上游存储限制了请求频率（返回429）
+    #[error("Upstream storage is rate limiting requests")]
+    RateLimited { retry_after: Option<Duration> },
+    /// 请求的范围无法满足
+    #[error("Requested range not satisfiable")]
+    RangeNotSatisfiable,
+    /// 存储不支持该操作
+    #[error("Operation not supported by this storage")]
+    MethodNotAllowed,
+    /// 目标已存在，或者要删除的目录不为空
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    /// 上游存储返回了非预期的状态码或无法解析的内容
+    #[error("Bad response from upstream storage: {0}")]
+    BadUpstream(String),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+impl StorageError {
+    /// 将上游返回的非成功状态码转换为错误
+    pub fn from_response(resp: &reqwest::Response) -> Self {
+        let retry_after = crate::upstream::retry_after(resp.headers());
+        match resp.status() {
+            StatusCode::SERVICE_UNAVAILABLE => Self::Unavailable { retry_after },
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited { retry_after },
+            StatusCode::GATEWAY_TIMEOUT | StatusCode::REQUEST_TIMEOUT => {
+                Self::Timeout(format!("upstream returned {}", resp.status()))
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE => Self::RangeNotSatisfiable,
+            StatusCode::METHOD_NOT_ALLOWED => Self::MethodNotAllowed,
+            status => Self::BadUpstream(format!("upstream returned {}", status)),
+        }
+    }
+}
+
+impl From<reqwest::Error> for StorageError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_connect() {
+            Self::Connect(e.to_string())
+        } else if e.is_timeout() {
+            Self::Timeout(e.to_string())
+        } else if e.is_body() || e.is_decode() {
+            Self::BadUpstream(e.to_string())
+        } else {
+            Self::Other(e.into())
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, FixedOffset};
+use futures_util::{stream, StreamExt};
+use md5::{Digest, Md5};
+use percent_encoding::percent_decode_str;
+use reqwest::{
+    header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, LAST_MODIFIED},
+    RequestBuilder, Response, StatusCode, Url,
+};
+use url::Url as UrlParser;
+
+use crate::{
+    config::{AutoindexFormat, HttpIndexStorageConfig, SecureLinkConfig},
+    upstream::UpstreamClient,
+};
+
+use super::{autoindex, StorageEntry, StorageError, StorageProvider, StorageResult};
+
+/// 通过HTTP目录列表浏览源站，列表格式见[`autoindex`]
+pub struct HttpIndexStorageProvider {
+    base_url: String,
+    req_path_prefix: String,
+    public_url: String, // 用于对外返回的url_base
+    secure_link: Option<SecureLinkConfig>,
+    autoindex_format: AutoindexFormat,
+    /// 目录列表中不带时区的时间所用的时区
+    timezone: FixedOffset,
+    /// 每次列目录最多发出的HEAD请求数
+    head_requests: usize,
+    /// 列表中的近似大小（例如`118M`）也用HEAD请求换成精确值
+    exact_sizes: bool,
+    /// 所有对源站的请求共用的客户端
+    client: UpstreamClient,
+}
+
+/// 同时进行的HEAD请求数
+const HEAD_CONCURRENCY: usize = 8;
+
+impl HttpIndexStorageProvider {
+    pub fn new(config: &HttpIndexStorageConfig, req_path_prefix: String) -> anyhow::Result<Self> {
+        // 验证base_url格式
+        let url = UrlParser::parse(&config.base_url)
+            .map_err(|e| anyhow::anyhow!("Invalid http base_url: {}", e))?;
+
+        // 验证协议是http或https
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(anyhow::anyhow!(
+                "http base_url must use http or https protocol"
+            ));
+        }
+
+        let mut base_url = config.base_url.clone();
+        if !base_url.is_empty() && !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        let mut public_url = config.public_url.clone();
+        if !public_url.is_empty() && !public_url.ends_with('/') {
+            public_url.push('/');
+        }
+
+        Ok(Self {
+            base_url,
+            req_path_prefix,
+            public_url,
+            secure_link: config.secure_link.clone(),
+            autoindex_format: config.autoindex_format,
+            timezone: autoindex::parse_timezone(&config.timezone)?,
+            head_requests: config.head_requests,
+            exact_sizes: false,
+            client: UpstreamClient::new(&config.client)?,
+        })
+    }
+
+    /// 需要用大小判断文件是否变化时（例如同步），近似的大小也通过HEAD请求获取精确值
+    pub fn with_exact_sizes(mut self) -> Self {
+        self.exact_sizes = true;
+        self
+    }
+
+    pub fn client(&self) -> &UpstreamClient {
+        &self.client
+    }
+
+    /// 为重定向地址加上nginx `secure_link`模块校验的`md5`和`expires`参数
+    fn sign_secure_link(config: &SecureLinkConfig, url: &mut Url, client_ip: Option<&str>) {
+        let expires = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+            + config.ttl;
+        // nginx中的`$uri`是解码后的路径
+        let uri = percent_decode_str(url.path()).decode_utf8_lossy();
+        let input = config
+            .md5
+            .replace("$secure_link_expires", &expires.to_string())
+            .replace("$uri", &uri)
+            .replace("$remote_addr", client_ip.unwrap_or_default());
+        let md5 = URL_SAFE_NO_PAD.encode(Md5::digest(input.as_bytes()));
+        url.query_pairs_mut()
+            .append_pair("md5", &md5)
+            .append_pair("expires", &expires.to_string());
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path.trim_start_matches('/'))
+    }
+
+    /// 发送请求（失败时重试），404时返回`None`，其他非成功状态转换为对应的错误
+    async fn send(&self, request: RequestBuilder) -> StorageResult<Option<Response>> {
+        let resp = self.client.send(request).await?;
+
+        if resp.status().is_success() {
+            Ok(Some(resp))
+        } else if resp.status() == StatusCode::NOT_FOUND {
+            log::debug!("Origin returned 404 for {}", resp.url());
+            Ok(None)
+        } else {
+            Err(StorageError::from_response(&resp))
+        }
+    }
+
+    /// 获取目录列表，返回`Content-Type`和内容
+    async fn fetch_autoindex(&self, path: &str) -> StorageResult<Option<(Option<String>, String)>> {
+        let mut request = self.client.get(self.url(path));
+        if autoindex::wants_json(self.autoindex_format) {
+            // Caddy只在请求json时返回精确的大小和时间，其他服务器会忽略这个头
+            request = request.header(ACCEPT, "application/json, text/html;q=0.9");
+        }
+        let resp = match self.send(request).await? {
+            Some(resp) => resp,
+            None => return Ok(None),
+        };
+        let content_type = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        Ok(Some((content_type, resp.text().await?)))
+    }
+
+    /// 对列表中缺少大小或修改时间的文件发出HEAD请求，用`Content-Length`和`Last-Modified`补全，
+    /// 开启`exact_sizes`时近似的大小也用`Content-Length`替换
+    async fn fill_from_head(&self, entries: &mut [StorageEntry]) {
+        let missing: Vec<usize> = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| {
+                !e.name.ends_with('/')
+                    && (e.size.is_none()
+                        || e.modified.is_none()
+                        || (self.exact_sizes && e.approximate_size))
+            })
+            .map(|(i, _)| i)
+            .take(self.head_requests)
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let urls: Vec<(usize, String)> = missing
+            .into_iter()
+            .map(|i| (i, self.url(&entries[i].url)))
+            .collect();
+        let results: Vec<(usize, StorageResult<Option<Response>>)> = stream::iter(urls)
+            .map(|(i, url)| async move { (i, self.send(self.client.head(url)).await) })
+            .buffer_unordered(HEAD_CONCURRENCY)
+            .collect()
+            .await;
+        for (i, result) in results {
+            let resp = match result {
+                Ok(Some(resp)) => resp,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::debug!("HEAD {} failed: {}", entries[i].url, e);
+                    continue;
+                }
+            };
+            let entry = &mut entries[i];
+            let length = resp
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok());
+            if length.is_some() && (entry.size.is_none() || entry.approximate_size) {
+                entry.size = length;
+                entry.approximate_size = false;
+            }
+            if entry.modified.is_none() {
+                entry.modified = resp
+                    .headers()
+                    .get(LAST_MODIFIED)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+                    .map(Into::into);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StorageProvider for HttpIndexStorageProvider {
+    async fn list_directory(
+        &self,
+        path_in_provider: &str,
+    ) -> StorageResult<Option<Vec<StorageEntry>>> {
+        let (content_type, body) = match self.fetch_autoindex(path_in_provider).await? {
+            Some(listing) => listing,
+            None => return Ok(None),
+        };
+        let parser = autoindex::parser(self.autoindex_format, content_type.as_deref(), &body);
+        let mut entries = parser
+            .parse(&body, path_in_provider, self.timezone)
+            .map_err(|e| {
+                StorageError::BadUpstream(format!(
+                    "Failed to parse {:?} directory listing: {}",
+                    parser.format(),
+                    e
+                ))
+            })?;
+        self.fill_from_head(&mut entries).await;
+        Ok(Some(entries))
+    }
+
+    async fn read_file(
+        &self,
+        path_in_provider: &str,
+        max_size: usize,
+    ) -> StorageResult<Option<Vec<u8>>> {
+        let resp = match self
+            .send(self.client.get(self.url(path_in_provider)))
+            .await?
+        {
+            Some(resp) => resp,
+            None => return Ok(None),
+        };
+        if resp.content_length().unwrap_or(0) as usize > max_size {
+            return Err(anyhow::anyhow!(
+                "File {} is too large to read (limit {})",
+                path_in_provider,
+                max_size
+            )
+            .into());
+        }
+        let content = resp.bytes().await?;
+        if content.len() > max_size {
+            return Err(anyhow::anyhow!(
+                "File {} is too large to read (limit {})",
+                path_in_provider,
+                max_size
+            )
+            .into());
+        }
+        Ok(Some(content.to_vec()))
+    }
+
+    fn path_in_provider(&self, full_path: &str) -> Option<String> {
+        if full_path.starts_with(&self.req_path_prefix) {
+            Some(full_path[self.req_path_prefix.len()..].to_string())
+        } else {
+            None
+        }
+    }
+
+    async fn get_download_url(
+        &self,
+        full_path: &str,
+        client_ip: Option<&str>,
+    ) -> StorageResult<Option<String>> {
+        log::debug!("Getting download URL for {}", full_path);
+        let path_in_provider = match self.path_in_provider(full_path) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        // 使用public_url构建对外URL
+        let mut url = Url::parse(&self.public_url)
+            .map_err(|e| anyhow::anyhow!("Invalid URL: {}", e))?
+            .join(path_in_provider.strip_prefix("/").unwrap_or_default())
+            .map_err(|e| anyhow::anyhow!("Failed to join URLs: {}", e))?;
+        if let Some(secure_link) = &self.secure_link {
+            Self::sign_secure_link(secure_link, &mut url, client_ip);
+        }
+
+        log::debug!(
+            "Download URL for {} is {} (public_host: {})",
+            full_path,
+            url,
+            self.public_url
+        );
+
+        Ok(Some(url.to_string()))
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secure_link() {
+        let config = SecureLinkConfig {
+            md5: "$secure_link_expires$uri$remote_addr secret".to_string(),
+            ttl: 60,
+        };
+        let mut url = Url::parse("https://dl.example.com/dragonos/%E6%9E%84%E5%BB%BA.iso").unwrap();
+        HttpIndexStorageProvider::sign_secure_link(&config, &mut url, Some("10.0.0.1"));
+
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert_eq!(query[0].0, "md5");
+        assert_eq!(query[1].0, "expires");
+        let input = format!("{}/dragonos/构建.iso10.0.0.1 secret", query[1].1);
+        assert_eq!(
+            query[0].1,
+            URL_SAFE_NO_PAD.encode(Md5::digest(input.as_bytes()))
+        );
+    }
+}
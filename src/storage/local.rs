@@ -3,9 +3,13 @@ use std::path::{Path, PathBuf};
 use actix_files::NamedFile;
 use anyhow;
 use async_trait::async_trait;
-use tokio::fs;
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncWriteExt};
 
-use super::{StorageEntry, StorageProvider};
+use super::{StorageEntry, StorageError, StorageProvider, StorageResult, Upload};
+
+/// 上传中的临时文件后缀，这些文件不会出现在目录列表中
+const UPLOAD_TEMP_SUFFIX: &str = ".uploading";
 
 pub struct LocalStorageProvider {
     root_path: String,
@@ -13,14 +17,14 @@ pub struct LocalStorageProvider {
 }
 
 impl LocalStorageProvider {
-    pub fn new(root_path: String, req_path_prefix: String) -> Self {
-        let abs_root_path = Path::new(&root_path)
-            .canonicalize()
-            .expect("Failed to canonicalize root path");
-        Self {
+    pub fn new(root_path: String, req_path_prefix: String) -> anyhow::Result<Self> {
+        let abs_root_path = Path::new(&root_path).canonicalize().map_err(|e| {
+            anyhow::anyhow!("Failed to canonicalize root path {}: {}", root_path, e)
+        })?;
+        Ok(Self {
             root_path: abs_root_path.to_string_lossy().to_string(),
             req_path_prefix,
-        }
+        })
     }
 
     fn ent_path_in_provider(&self, path_in_provider: &str, ent: &tokio::fs::DirEntry) -> String {
@@ -57,25 +61,174 @@ impl LocalStorageProvider {
         let entry = StorageEntry {
             name: file_name,
             url: self.ent_path_in_provider(path_in_provider, ent),
-            modified,
+            modified: Some(modified),
             size,
+            approximate_size: false,
         };
 
         Ok(entry)
     }
 }
 
+/// 正在上传的文件。内容先写入目标目录中的临时文件，校验通过后再原子地移动到目标位置；
+/// 未提交就被丢弃时删除临时文件
+struct PendingUpload {
+    file: fs::File,
+    temp_path: PathBuf,
+    target: PathBuf,
+    hasher: Sha256,
+    size: u64,
+}
+
+#[async_trait]
+impl Upload for PendingUpload {
+    async fn write(&mut self, chunk: &[u8]) -> StorageResult<()> {
+        self.file
+            .write_all(chunk)
+            .await
+            .map_err(|e| anyhow!("Failed to write {}: {}", self.temp_path.display(), e))?;
+        self.hasher.update(chunk);
+        self.size += chunk.len() as u64;
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    async fn finish(&mut self) -> StorageResult<String> {
+        let synced = match self.file.flush().await {
+            Ok(()) => self.file.sync_all().await,
+            Err(e) => Err(e),
+        };
+        synced.map_err(|e| anyhow!("Failed to sync {}: {}", self.temp_path.display(), e))?;
+        Ok(hex::encode(self.hasher.clone().finalize()))
+    }
+
+    /// 把临时文件移动到目标位置
+    async fn commit(self: Box<Self>, overwrite: bool) -> StorageResult<bool> {
+        let (temp_path, target) = (self.temp_path.clone(), self.target.clone());
+        let committed = if overwrite {
+            fs::rename(&temp_path, &target).await.map(|_| true)
+        } else {
+            // 硬链接在目标存在时失败，避免检查和重命名之间的竞争
+            match fs::hard_link(&temp_path, &target).await {
+                Ok(()) => fs::remove_file(&temp_path).await.map(|_| true),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+                Err(e) => Err(e),
+            }
+        }
+        .map_err(|e| anyhow!("Failed to move upload to {}: {}", target.display(), e))?;
+        if committed {
+            sync_dir(&target).await;
+        }
+        Ok(committed)
+    }
+}
+
+impl Drop for PendingUpload {
+    fn drop(&mut self) {
+        // 提交后临时文件已不存在，删除失败可以忽略
+        let _ = std::fs::remove_file(&self.temp_path);
+    }
+}
+
+impl LocalStorageProvider {
+    /// 返回写操作的目标在本地文件系统中的路径，目标本身可以不存在。
+    /// 上级目录不存在时，`create_parent`为`true`则创建，否则返回`None`
+    async fn writable_path(
+        &self,
+        path_in_provider: &str,
+        create_parent: bool,
+    ) -> StorageResult<Option<PathBuf>> {
+        let relative = Path::new(path_in_provider.trim_matches('/'));
+        let name = match relative.file_name() {
+            Some(name) => name.to_os_string(),
+            None => return Err(anyhow!("Invalid path: {:?}", path_in_provider).into()),
+        };
+        if !relative
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+        {
+            return Err(anyhow!("Invalid path: {:?}", path_in_provider).into());
+        }
+        // 逐级检查上级目录：已存在的目录可能是指向根目录之外的符号链接，
+        // 必须先确认它位于根目录之内，再在其中创建缺少的目录
+        let mut dir = PathBuf::from(&self.root_path);
+        for component in relative.parent().unwrap_or(Path::new("")).components() {
+            let next = dir.join(component);
+            if !exists(&next).await {
+                if !create_parent {
+                    return Ok(None);
+                }
+                match fs::create_dir(&next).await {
+                    Ok(()) => {
+                        dir = next;
+                        continue;
+                    }
+                    // 并发创建时按已存在的目录处理
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                    Err(e) => {
+                        return Err(
+                            anyhow!("Failed to create directory {}: {}", next.display(), e).into(),
+                        )
+                    }
+                }
+            }
+            let canonical = next
+                .canonicalize()
+                .map_err(|e| anyhow!("Failed to canonicalize {}: {}", next.display(), e))?;
+            if !canonical.starts_with(&self.root_path) {
+                return Err(anyhow!("Path {} escapes the storage root", path_in_provider).into());
+            }
+            if !canonical.is_dir() {
+                if !create_parent {
+                    return Ok(None);
+                }
+                return Err(StorageError::Conflict(format!(
+                    "{} is not a directory",
+                    next.display()
+                )));
+            }
+            dir = canonical;
+        }
+        Ok(Some(dir.join(name)))
+    }
+}
+
+/// 是否是上传中的临时文件
+pub fn is_upload_temp(file_name: &str) -> bool {
+    file_name.starts_with('.') && file_name.ends_with(UPLOAD_TEMP_SUFFIX)
+}
+
+/// 同步目录，确保其中的创建、删除和重命名在断电后仍然有效
+async fn sync_dir(path: &Path) {
+    if let Some(dir) = path.parent() {
+        if let Ok(dir) = fs::File::open(dir).await {
+            let _ = dir.sync_all().await;
+        }
+    }
+}
+
+/// 路径是否存在（不跟随符号链接）
+async fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).await.is_ok()
+}
+
 impl LocalStorageProvider {
-    fn abs_path(&self, path_in_provider: &str) -> anyhow::Result<PathBuf> {
+    /// 返回文件在本地文件系统中的绝对路径，路径不存在时返回`None`
+    fn abs_path(&self, path_in_provider: &str) -> StorageResult<Option<PathBuf>> {
         let full_path = format!("{}/{}", self.root_path, path_in_provider);
-        let full_path = PathBuf::from(&full_path).canonicalize().map_err(|e| {
-            anyhow!(
+        match PathBuf::from(&full_path).canonicalize() {
+            Ok(full_path) => Ok(Some(full_path)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow!(
                 "Failed to canonicalize path {}, err: {}",
                 path_in_provider,
                 e
             )
-        })?;
-        Ok(full_path)
+            .into()),
+        }
     }
 }
 
@@ -85,8 +238,143 @@ impl StorageProvider for LocalStorageProvider {
         true
     }
 
-    async fn stream_file(&self, path_in_provider: &str) -> anyhow::Result<Option<NamedFile>> {
-        let file_path = self.abs_path(path_in_provider)?;
+    fn capabilities(&self) -> super::Capabilities {
+        super::Capabilities {
+            put: true,
+            delete: true,
+            rename: true,
+            mkdir: true,
+        }
+    }
+
+    fn local_path(&self, path_in_provider: &str) -> StorageResult<Option<PathBuf>> {
+        // 根目录中指向外部的符号链接不能通过这里访问
+        Ok(self
+            .abs_path(path_in_provider)?
+            .filter(|p| p.starts_with(&self.root_path)))
+    }
+
+    async fn put(&self, path_in_provider: &str) -> StorageResult<Box<dyn Upload>> {
+        let target = self
+            .writable_path(path_in_provider, true)
+            .await?
+            .ok_or_else(|| anyhow!("Failed to create parent of {}", path_in_provider))?;
+        let name = target
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if name.starts_with('.') {
+            return Err(anyhow!("Invalid file name: {:?}", path_in_provider).into());
+        }
+        if target.is_dir() {
+            return Err(StorageError::Conflict(format!(
+                "{} is a directory",
+                path_in_provider
+            )));
+        }
+
+        let temp_path = target.with_file_name(format!(
+            ".{}.{}{}",
+            name,
+            uuid::Uuid::new_v4().simple(),
+            UPLOAD_TEMP_SUFFIX
+        ));
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .await
+            .map_err(|e| anyhow!("Failed to create {}: {}", temp_path.display(), e))?;
+        Ok(Box::new(PendingUpload {
+            file,
+            temp_path,
+            target,
+            hasher: Sha256::new(),
+            size: 0,
+        }))
+    }
+
+    async fn delete(&self, path_in_provider: &str, recursive: bool) -> StorageResult<bool> {
+        let path = match self.writable_path(path_in_provider, false).await? {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+        let metadata = match fs::symlink_metadata(&path).await {
+            Ok(m) => m,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(anyhow!("Failed to stat {}: {}", path.display(), e).into()),
+        };
+        let result = if !metadata.is_dir() {
+            fs::remove_file(&path).await
+        } else if recursive {
+            fs::remove_dir_all(&path).await
+        } else {
+            fs::remove_dir(&path).await
+        };
+        match result {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::DirectoryNotEmpty => {
+                return Err(StorageError::Conflict(format!(
+                    "directory {} is not empty",
+                    path_in_provider
+                )))
+            }
+            Err(e) => return Err(anyhow!("Failed to delete {}: {}", path.display(), e).into()),
+        }
+        sync_dir(&path).await;
+        log::info!("Deleted {}", path.display());
+        Ok(true)
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> StorageResult<bool> {
+        let source = match self.writable_path(from, false).await? {
+            Some(path) if exists(&path).await => path,
+            _ => return Ok(false),
+        };
+        let target = self
+            .writable_path(to, true)
+            .await?
+            .ok_or_else(|| anyhow!("Failed to create parent of {}", to))?;
+        if exists(&target).await {
+            return Err(StorageError::Conflict(format!("{} already exists", to)));
+        }
+        fs::rename(&source, &target).await.map_err(|e| {
+            anyhow!(
+                "Failed to rename {} to {}: {}",
+                source.display(),
+                target.display(),
+                e
+            )
+        })?;
+        sync_dir(&source).await;
+        sync_dir(&target).await;
+        log::info!("Renamed {} to {}", source.display(), target.display());
+        Ok(true)
+    }
+
+    async fn mkdir(&self, path_in_provider: &str) -> StorageResult<()> {
+        let path = self
+            .writable_path(path_in_provider, true)
+            .await?
+            .ok_or_else(|| anyhow!("Failed to create parent of {}", path_in_provider))?;
+        match fs::create_dir(&path).await {
+            Ok(()) => {
+                sync_dir(&path).await;
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && path.is_dir() => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err(StorageError::Conflict(
+                format!("{} is not a directory", path_in_provider),
+            )),
+            Err(e) => Err(anyhow!("Failed to create directory {}: {}", path.display(), e).into()),
+        }
+    }
+
+    async fn stream_file(&self, path_in_provider: &str) -> StorageResult<Option<NamedFile>> {
+        let file_path = match self.local_path(path_in_provider)? {
+            Some(p) => p,
+            None => return Ok(None),
+        };
         match NamedFile::open_async(file_path).await {
             Ok(file) => Ok(Some(file)),
             Err(e) => {
@@ -96,6 +384,34 @@ impl StorageProvider for LocalStorageProvider {
         }
     }
 
+    async fn read_file(
+        &self,
+        path_in_provider: &str,
+        max_size: usize,
+    ) -> StorageResult<Option<Vec<u8>>> {
+        let file_path = match self.local_path(path_in_provider)? {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        let metadata = match fs::metadata(&file_path).await {
+            Ok(m) if m.is_file() => m,
+            _ => return Ok(None),
+        };
+        if metadata.len() as usize > max_size {
+            return Err(anyhow!(
+                "File {} is too large to read ({} bytes, limit {})",
+                path_in_provider,
+                metadata.len(),
+                max_size
+            )
+            .into());
+        }
+        let content = fs::read(&file_path)
+            .await
+            .map_err(|e| anyhow!("Failed to read file {}: {}", path_in_provider, e))?;
+        Ok(Some(content))
+    }
+
     fn path_in_provider(&self, full_path: &str) -> Option<String> {
         if full_path.starts_with(&self.req_path_prefix) {
             Some(full_path[self.req_path_prefix.len()..].to_string())
@@ -104,7 +420,11 @@ impl StorageProvider for LocalStorageProvider {
         }
     }
 
-    async fn get_download_url(&self, _full_path: &str) -> anyhow::Result<Option<String>> {
+    async fn get_download_url(
+        &self,
+        _full_path: &str,
+        _client_ip: Option<&str>,
+    ) -> StorageResult<Option<String>> {
         // should not impl for local storage
         Ok(None)
     }
@@ -112,14 +432,15 @@ impl StorageProvider for LocalStorageProvider {
     async fn list_directory(
         &self,
         path_in_provider: &str,
-    ) -> anyhow::Result<Option<Vec<super::StorageEntry>>> {
+    ) -> StorageResult<Option<Vec<super::StorageEntry>>> {
         let mut entries = Vec::new();
-        let full_path = self.abs_path(path_in_provider).map_err(|e| {
-            e.context(format!(
-                "list_directory: Failed to resolve path '{}'",
-                path_in_provider
-            ))
-        })?;
+        let full_path = match self.abs_path(path_in_provider)? {
+            Some(p) => p,
+            None => {
+                log::debug!("Path {} does not exist", path_in_provider);
+                return Ok(None);
+            }
+        };
         if !full_path.is_dir() {
             log::debug!("Path {} is not a directory", full_path.display());
             return Ok(None);
@@ -144,6 +465,11 @@ impl StorageProvider for LocalStorageProvider {
             .await
             .map_err(|e| anyhow::anyhow!("Failed to read directory entry: {}", e))?
         {
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            if is_upload_temp(&file_name) {
+                continue;
+            }
             match self.process_entry(path_in_provider, &entry).await {
                 Ok(ent) => entries.push(ent),
                 Err(e) => {
@@ -163,3 +489,111 @@ impl StorageProvider for LocalStorageProvider {
         Ok(Some(entries))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_write_operations() {
+        let root = std::env::temp_dir().join(format!("mirror-proxy-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let provider =
+            LocalStorageProvider::new(root.to_string_lossy().to_string(), "/pub".to_string())
+                .unwrap();
+
+        provider.mkdir("/a/b").await.unwrap();
+        let mut upload = provider.put("/a/b/x.iso").await.unwrap();
+        upload.write(b"hello").await.unwrap();
+        upload.finish().await.unwrap();
+        assert!(upload.commit(false).await.unwrap());
+        let mut upload = provider.put("/a/b/x.iso").await.unwrap();
+        upload.finish().await.unwrap();
+        assert!(!upload.commit(false).await.unwrap());
+        let names: Vec<_> = provider
+            .list_directory("/a/b")
+            .await
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, ["x.iso"]);
+
+        assert!(provider.rename("/a/b/x.iso", "/c/y.iso").await.unwrap());
+        assert!(!provider.rename("/a/b/x.iso", "/c/z.iso").await.unwrap());
+        assert_eq!(std::fs::read(root.join("c/y.iso")).unwrap(), b"hello");
+        assert!(matches!(
+            provider.rename("/a", "/c").await,
+            Err(StorageError::Conflict(_))
+        ));
+
+        assert!(matches!(
+            provider.delete("/c", false).await,
+            Err(StorageError::Conflict(_))
+        ));
+        assert!(provider.delete("/c", true).await.unwrap());
+        assert!(!provider.delete("/c", true).await.unwrap());
+        assert!(provider.delete("/a/../..", true).await.is_err());
+        assert!(provider.mkdir("/").await.is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_stream_file_symlink_outside_root() {
+        let base = std::env::temp_dir().join(format!("mirror-proxy-{}", uuid::Uuid::new_v4()));
+        let root = base.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(base.join("secret"), b"secret").unwrap();
+        std::fs::write(root.join("a.iso"), b"a").unwrap();
+        std::os::unix::fs::symlink(base.join("secret"), root.join("escape.iso")).unwrap();
+        std::os::unix::fs::symlink(root.join("a.iso"), root.join("link.iso")).unwrap();
+        let provider =
+            LocalStorageProvider::new(root.to_string_lossy().to_string(), "/pub".to_string())
+                .unwrap();
+
+        assert!(provider.stream_file("/a.iso").await.unwrap().is_some());
+        // 指向根目录内的符号链接可以访问
+        assert!(provider.stream_file("/link.iso").await.unwrap().is_some());
+        assert!(provider.stream_file("/escape.iso").await.unwrap().is_none());
+        assert!(provider.stream_file("/../secret").await.unwrap().is_none());
+        assert_eq!(
+            provider.read_file("/link.iso", 16).await.unwrap().unwrap(),
+            b"a"
+        );
+        assert!(provider
+            .read_file("/escape.iso", 16)
+            .await
+            .unwrap()
+            .is_none());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_write_through_symlink_outside_root() {
+        let base = std::env::temp_dir().join(format!("mirror-proxy-{}", uuid::Uuid::new_v4()));
+        let (root, outside) = (base.join("root"), base.join("outside"));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        let provider =
+            LocalStorageProvider::new(root.to_string_lossy().to_string(), "/pub".to_string())
+                .unwrap();
+
+        assert!(provider.put("/link/new/file.iso").await.is_err());
+        assert!(provider.mkdir("/link/new/dir").await.is_err());
+        std::fs::write(root.join("a.iso"), b"a").unwrap();
+        assert!(provider.rename("/a.iso", "/link/new/a.iso").await.is_err());
+        assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+
+        // 根目录之内的目录照常创建
+        let mut upload = provider.put("/x/y/file.iso").await.unwrap();
+        upload.finish().await.unwrap();
+        assert!(upload.commit(false).await.unwrap());
+        assert!(root.join("x/y/file.iso").is_file());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
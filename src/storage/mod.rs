@@ -1,38 +1,122 @@
-use std::{sync::Arc, time::SystemTime};
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
 
-use crate::{config::StorageBackend, BASE_PATH};
+use crate::{
+    config::{Config, StorageBackend},
+    BASE_PATH,
+};
 use actix_files::NamedFile;
 use async_trait::async_trait;
+use serde::Serialize;
 
+mod autoindex;
+mod cache;
+mod error;
+pub mod http_index;
 pub mod local;
-pub mod nginx;
 mod utils;
 
+pub use cache::CachedProvider;
+pub use error::{StorageError, StorageResult};
+
 lazy_static! {
-    static ref STORAGE_PROVIDER: Arc<dyn StorageProvider> = {
-        let config = crate::CONFIG.get().expect("Config not initialized");
-        match config.storage.backend {
-            StorageBackend::Nginx => {
-                let nginx_config = config
-                    .storage
-                    .nginx
-                    .as_ref()
-                    .expect("Nginx storage config not found");
-                Arc::new(
-                    nginx::NginxStorageProvider::new(
-                        nginx_config.base_url.clone(),
-                        BASE_PATH.to_string(),
-                        nginx_config.public_url.clone(),
-                    )
-                    .expect("Failed to create Nginx storage provider"),
-                )
-            }
-            StorageBackend::Local => Arc::new(local::LocalStorageProvider::new(
-                config.storage.local.as_ref().unwrap().root_path.clone(),
+    static ref STORAGE_PROVIDER: RwLock<Arc<CachedProvider>> = RwLock::new(
+        build_provider(&crate::config::current()).expect("Failed to create storage provider")
+    );
+}
+
+fn build_provider(config: &Config) -> anyhow::Result<Arc<CachedProvider>> {
+    let provider: Arc<dyn StorageProvider> = match config.storage.backend {
+        StorageBackend::HttpIndex => {
+            let http_config = config
+                .storage
+                .http
+                .as_ref()
+                .ok_or_else(|| anyhow!("HTTP storage config not found"))?;
+            Arc::new(http_index::HttpIndexStorageProvider::new(
+                http_config,
                 BASE_PATH.to_string(),
-            )),
+            )?)
+        }
+        StorageBackend::Local => {
+            let local_config = config
+                .storage
+                .local
+                .as_ref()
+                .ok_or_else(|| anyhow!("Local storage config not found"))?;
+            Arc::new(local::LocalStorageProvider::new(
+                local_config.root_path.clone(),
+                BASE_PATH.to_string(),
+            )?)
         }
     };
+    Ok(Arc::new(CachedProvider::new(
+        provider,
+        Duration::from_secs(config.cache.storage_ttl),
+    )))
+}
+
+fn current_provider() -> Arc<CachedProvider> {
+    STORAGE_PROVIDER.read().unwrap().clone()
+}
+
+/// 按新的配置重新创建存储提供者，旧提供者的缓存随之丢弃
+pub fn reload(config: &Config) -> anyhow::Result<()> {
+    let provider = build_provider(config)?;
+    *STORAGE_PROVIDER.write().unwrap() = provider;
+    Ok(())
+}
+
+/// 清除请求路径以`prefix`开头的目录列表和文件缓存，返回清除的条目数
+pub fn purge(prefix: &str) -> usize {
+    let provider = current_provider();
+    match provider.path_in_provider(prefix) {
+        Some(prefix) => provider.purge(&prefix),
+        // 前缀比挂载点更短（例如`/`）时清除全部缓存
+        None if BASE_PATH.starts_with(prefix) => provider.purge(""),
+        None => 0,
+    }
+}
+
+/// 当前存储支持的写操作
+pub fn capabilities() -> Capabilities {
+    current_provider().capabilities()
+}
+
+/// 存储提供者的健康状态
+#[derive(Debug, Serialize)]
+pub struct ProviderHealth {
+    pub mount: &'static str,
+    pub backend: StorageBackend,
+    pub healthy: bool,
+    pub latency_ms: u128,
+    pub error: Option<String>,
+}
+
+/// 绕过缓存列出根目录来检查存储提供者是否可用
+pub async fn health() -> Vec<ProviderHealth> {
+    let backend = crate::config::current().storage.backend;
+    let provider = current_provider();
+    let start = Instant::now();
+    let result = match provider.path_in_provider(&format!("{}/", BASE_PATH)) {
+        Some(root) => provider.inner().list_directory(&root).await,
+        None => Ok(None),
+    };
+    let error = match result {
+        Ok(Some(_)) => None,
+        Ok(None) => Some("root directory not found".to_string()),
+        Err(e) => Some(e.to_string()),
+    };
+    vec![ProviderHealth {
+        mount: BASE_PATH,
+        backend,
+        healthy: error.is_none(),
+        latency_ms: start.elapsed().as_millis(),
+        error,
+    }]
 }
 
 #[async_trait]
@@ -40,34 +124,102 @@ pub trait StorageProvider: Sync + Send {
     async fn list_directory(
         &self,
         path_in_provider: &str,
-    ) -> anyhow::Result<Option<Vec<StorageEntry>>>;
+    ) -> StorageResult<Option<Vec<StorageEntry>>>;
     /// 根据完整的请求路径，返回在存储提供者中的路径
     fn path_in_provider(&self, full_path: &str) -> Option<String>;
-    /// 获取文件的下载URL（适用于特定后缀的文件）
-    async fn get_download_url(&self, full_path: &str) -> anyhow::Result<Option<String>>;
+    /// 获取文件的下载URL（适用于特定后缀的文件）。`client_ip`用于生成绑定客户端地址的链接
+    async fn get_download_url(
+        &self,
+        full_path: &str,
+        client_ip: Option<&str>,
+    ) -> StorageResult<Option<String>>;
 
     /// 是否是本地存储
     fn is_local(&self) -> bool {
         false
     }
 
+    /// 返回存储中的路径在本地文件系统中的绝对路径，只有本地存储支持
+    fn local_path(&self, _path_in_provider: &str) -> StorageResult<Option<PathBuf>> {
+        Ok(None)
+    }
+
     /// 流式返回文件内容
     #[allow(unused)]
-    async fn stream_file(&self, path_in_provider: &str) -> anyhow::Result<Option<NamedFile>> {
+    async fn stream_file(&self, path_in_provider: &str) -> StorageResult<Option<NamedFile>> {
         Ok(None)
     }
+
+    /// 存储支持的写操作，只读的存储（例如HTTP目录列表）全部为`false`
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// 开始写入文件，内容在`Upload::commit`之后才可见
+    async fn put(&self, _path_in_provider: &str) -> StorageResult<Box<dyn Upload>> {
+        Err(StorageError::MethodNotAllowed)
+    }
+
+    /// 删除文件或目录，非空目录需要`recursive`。不存在时返回`Ok(false)`
+    async fn delete(&self, _path_in_provider: &str, _recursive: bool) -> StorageResult<bool> {
+        Err(StorageError::MethodNotAllowed)
+    }
+
+    /// 移动文件或目录，目标已存在时返回`Conflict`。源不存在时返回`Ok(false)`
+    async fn rename(&self, _from: &str, _to: &str) -> StorageResult<bool> {
+        Err(StorageError::MethodNotAllowed)
+    }
+
+    /// 创建目录（包括不存在的上级目录），目录已存在时什么也不做
+    async fn mkdir(&self, _path_in_provider: &str) -> StorageResult<()> {
+        Err(StorageError::MethodNotAllowed)
+    }
+
+    /// 读取小文件的全部内容（例如目录中的README），超过`max_size`字节时返回错误
+    async fn read_file(
+        &self,
+        path_in_provider: &str,
+        max_size: usize,
+    ) -> StorageResult<Option<Vec<u8>>>;
+}
+
+/// 正在写入的文件，由`StorageProvider::put`返回。未提交就被丢弃时不留下任何内容
+#[async_trait]
+pub trait Upload: Send {
+    async fn write(&mut self, chunk: &[u8]) -> StorageResult<()>;
+
+    /// 已写入的字节数
+    fn size(&self) -> u64;
+
+    /// 将内容刷写到存储，返回内容的SHA-256（十六进制）
+    async fn finish(&mut self) -> StorageResult<String>;
+
+    /// 使内容在目标位置可见。`overwrite`为`false`且目标已存在时返回`Ok(false)`
+    async fn commit(self: Box<Self>, overwrite: bool) -> StorageResult<bool>;
+}
+
+/// 存储支持的写操作
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Capabilities {
+    pub put: bool,
+    pub delete: bool,
+    pub rename: bool,
+    pub mkdir: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct StorageEntry {
     pub name: String,
     pub url: String,
-    pub modified: SystemTime,
+    /// 修改时间，源站的目录列表中没有或无法解析时为`None`
+    pub modified: Option<SystemTime>,
     pub size: Option<usize>,
+    /// `size`是目录列表中按单位取整的近似值（例如`118M`），不能用来判断文件是否变化
+    pub approximate_size: bool,
 }
 
 pub fn select_provider(full_path: &str) -> Option<(Arc<dyn StorageProvider>, String)> {
-    let provider = STORAGE_PROVIDER.clone();
+    let provider: Arc<dyn StorageProvider> = current_provider();
     let path_in_provider = provider.path_in_provider(full_path)?;
     Some((provider, path_in_provider))
 }
@@ -0,0 +1,937 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{get, HttpRequest, HttpResponse};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    config::{self, AutoindexFormat, HttpIndexStorageConfig, PathPattern, SyncJob},
+    error::HttpError,
+    i18n::{Locale, Message},
+    render,
+    storage::{
+        self, http_index::HttpIndexStorageProvider, local::is_upload_temp, select_provider,
+        StorageEntry, StorageProvider,
+    },
+};
+
+/// 后台任务检查是否有到期任务的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref RECORDS: Mutex<HashMap<String, JobRecord>> = Mutex::new(HashMap::new());
+    /// 进程启动时间，从未运行过的任务在启动后立即执行
+    static ref STARTED_AT: u64 = unix_now();
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncState {
+    /// 启动后还没有运行过
+    #[default]
+    Pending,
+    Syncing,
+    Success,
+    Failed,
+}
+
+impl SyncState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Syncing => "syncing",
+            Self::Success => "success",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// 任务的运行记录（只保存在内存中，重启后清空），时间均为Unix时间戳（秒）
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobRecord {
+    pub state: SyncState,
+    pub last_started: Option<u64>,
+    pub last_finished: Option<u64>,
+    pub last_success: Option<u64>,
+    pub last_failure: Option<u64>,
+    pub last_error: Option<String>,
+    /// 最近一次成功同步的结果
+    pub last_report: Option<SyncReport>,
+}
+
+/// 状态页面和JSON中的一个任务
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub upstream: String,
+    pub path: String,
+    pub interval: u64,
+    #[serde(flatten)]
+    pub record: JobRecord,
+    /// 下次计划执行的时间，只手动执行的任务为`None`
+    pub next_run: Option<u64>,
+}
+
+/// 一次同步的结果
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SyncReport {
+    pub downloaded: u64,
+    pub deleted: u64,
+    pub bytes: u64,
+    /// 同步完成后目录中文件的总大小
+    pub size: u64,
+}
+
+impl std::fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} files downloaded ({} bytes), {} deleted",
+            self.downloaded, self.bytes, self.deleted
+        )
+    }
+}
+
+fn unix_now() -> u64 {
+    to_unix(SystemTime::now())
+}
+
+fn to_unix(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn next_run(job: &SyncJob, record: &JobRecord) -> Option<u64> {
+    if job.interval == 0 {
+        return None;
+    }
+    Some(
+        record
+            .last_finished
+            .map_or(*STARTED_AT, |t| t + job.interval),
+    )
+}
+
+/// 配置中所有任务的当前状态
+pub fn statuses() -> Vec<JobStatus> {
+    let config = config::current();
+    let records = RECORDS.lock().unwrap();
+    config
+        .sync
+        .iter()
+        .map(|job| {
+            let record = records.get(&job.name).cloned().unwrap_or_default();
+            JobStatus {
+                name: job.name.clone(),
+                upstream: job.upstream.clone(),
+                path: job.path.clone(),
+                interval: job.interval,
+                next_run: next_run(job, &record),
+                record,
+            }
+        })
+        .collect()
+}
+
+/// 标记任务开始运行，任务已经在运行时返回`false`
+fn begin(name: &str) -> bool {
+    let mut records = RECORDS.lock().unwrap();
+    let record = records.entry(name.to_string()).or_default();
+    if record.state == SyncState::Syncing {
+        return false;
+    }
+    record.state = SyncState::Syncing;
+    record.last_started = Some(unix_now());
+    true
+}
+
+fn finish(name: &str, result: &anyhow::Result<SyncReport>) {
+    let now = unix_now();
+    let mut records = RECORDS.lock().unwrap();
+    let record = records.entry(name.to_string()).or_default();
+    record.last_finished = Some(now);
+    match result {
+        Ok(report) => {
+            record.state = SyncState::Success;
+            record.last_success = Some(now);
+            record.last_error = None;
+            record.last_report = Some(*report);
+        }
+        Err(e) => {
+            record.state = SyncState::Failed;
+            record.last_failure = Some(now);
+            record.last_error = Some(format!("{:#}", e));
+        }
+    }
+}
+
+pub fn is_running(name: &str) -> bool {
+    RECORDS
+        .lock()
+        .unwrap()
+        .get(name)
+        .is_some_and(|r| r.state == SyncState::Syncing)
+}
+
+/// 执行一次同步任务，同一任务不会同时运行多个
+pub async fn run(job: &SyncJob) -> anyhow::Result<SyncReport> {
+    if !begin(&job.name) {
+        return Err(anyhow!("Sync job {} is already running", job.name));
+    }
+    log::info!("Sync job {} started", job.name);
+    let result = if job.timeout > 0 {
+        tokio::time::timeout(Duration::from_secs(job.timeout), sync_job(job))
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("Timed out after {} seconds", job.timeout)))
+    } else {
+        sync_job(job).await
+    };
+    match &result {
+        Ok(report) => log::info!("Sync job {} finished: {}", job.name, report),
+        Err(e) => log::error!("Sync job {} failed: {:#}", job.name, e),
+    }
+    finish(&job.name, &result);
+    result
+}
+
+/// 后台定时同步，每次检查时重新读取配置，以便重新加载配置后生效
+pub async fn run_scheduled() {
+    loop {
+        let now = unix_now();
+        for status in statuses() {
+            if status.record.state == SyncState::Syncing || status.next_run.is_none_or(|t| t > now)
+            {
+                continue;
+            }
+            let config = config::current();
+            if let Some(job) = config.sync.iter().find(|j| j.name == status.name).cloned() {
+                actix_web::rt::spawn(async move {
+                    let _ = run(&job).await;
+                });
+            }
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+/// 命令行`sync`子命令：`mirror-proxy sync [任务名...]`，不指定时执行全部任务
+pub async fn run_cli(args: &[String]) -> anyhow::Result<()> {
+    let config = config::current();
+    for name in args {
+        if !config.sync.iter().any(|j| &j.name == name) {
+            return Err(anyhow!("Unknown sync job {}", name));
+        }
+    }
+    let mut failed = 0;
+    for job in config
+        .sync
+        .iter()
+        .filter(|j| args.is_empty() || args.contains(&j.name))
+    {
+        match run(job).await {
+            Ok(report) => println!("{}: {}", job.name, report),
+            Err(e) => {
+                println!("{}: failed: {:#}", job.name, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} sync jobs failed", failed));
+    }
+    Ok(())
+}
+
+/// 任务的`include`/`exclude`规则，匹配相对于任务目录的路径
+struct Filters {
+    include: Vec<PathPattern>,
+    exclude: Vec<PathPattern>,
+}
+
+impl Filters {
+    fn new(job: &SyncJob) -> anyhow::Result<Self> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| PathPattern::new(p))
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        Ok(Self {
+            include: compile(&job.include)?,
+            exclude: compile(&job.exclude)?,
+        })
+    }
+
+    /// 排除一个目录时也排除其中的所有内容
+    fn excluded(&self, rel: &str) -> bool {
+        rel.match_indices('/')
+            .map(|(i, _)| &rel[..i])
+            .chain(std::iter::once(rel))
+            .any(|path| self.exclude.iter().any(|p| p.matches(path)))
+    }
+
+    /// 文件是否需要同步
+    fn selected(&self, rel: &str) -> bool {
+        !self.excluded(rel)
+            && (self.include.is_empty() || self.include.iter().any(|p| p.matches(rel)))
+    }
+}
+
+async fn sync_job(job: &SyncJob) -> anyhow::Result<SyncReport> {
+    let (provider, dest) = select_provider(&job.path)
+        .ok_or_else(|| anyhow!("{} is not served by any storage", job.path))?;
+    if !provider.capabilities().put {
+        return Err(anyhow!("Storage of {} does not support writing", job.path));
+    }
+    if !dest.trim_matches('/').is_empty() {
+        provider.mkdir(&dest).await?;
+    }
+    let root = provider
+        .local_path(&dest)?
+        .ok_or_else(|| anyhow!("{} is not in local storage", job.path))?;
+    let filters = Arc::new(Filters::new(job)?);
+
+    let result = if job.upstream.starts_with("rsync://") {
+        sync_rsync(job, &root, filters).await
+    } else {
+        match http_upstream(job) {
+            Ok(upstream) => {
+                HttpSync {
+                    job,
+                    filters,
+                    provider,
+                    dest: dest.trim_end_matches('/').to_string(),
+                    upstream,
+                    report: SyncReport::default(),
+                }
+                .run(&root)
+                .await
+            }
+            Err(e) => Err(e),
+        }
+    };
+    // 同步中途失败时也可能已经写入了文件
+    storage::purge(&job.path);
+    let mut report = result?;
+    report.size = tokio::task::spawn_blocking(move || dir_size(&root)).await??;
+    Ok(report)
+}
+
+/// 按目录列表访问`http(s)://`上游，列目录和下载共用同一个客户端
+fn http_upstream(job: &SyncJob) -> anyhow::Result<HttpIndexStorageProvider> {
+    let upstream_url = format!("{}/", job.upstream.trim_end_matches('/'));
+    let config = HttpIndexStorageConfig {
+        base_url: upstream_url.clone(),
+        public_url: upstream_url,
+        secure_link: None,
+        autoindex_format: AutoindexFormat::Auto,
+        timezone: "UTC".to_string(),
+        // 同步时按大小和修改时间判断文件是否变化，列表中缺少时全部用HEAD请求补全
+        head_requests: usize::MAX,
+        client: job.client.clone(),
+    };
+    Ok(HttpIndexStorageProvider::new(&config, String::new())?.with_exact_sizes())
+}
+
+/// 目录中所有文件的总大小，不跟随符号链接
+fn dir_size(dir: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for child in fs::read_dir(dir)? {
+        let child = child?;
+        let file_type = child.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&child.path())?;
+        } else if file_type.is_file() {
+            size += child.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+/// 从HTTP目录列表同步
+struct HttpSync<'a> {
+    job: &'a SyncJob,
+    filters: Arc<Filters>,
+    provider: Arc<dyn StorageProvider>,
+    /// 任务目录在存储中的路径，不以`/`结尾
+    dest: String,
+    upstream: HttpIndexStorageProvider,
+    report: SyncReport,
+}
+
+/// 从autoindex的链接中取出名称，跳过排序链接、上级目录和绝对地址
+fn entry_name(entry: &StorageEntry) -> Option<(String, bool)> {
+    let href = entry
+        .url
+        .rsplit_once('/')
+        .map_or(entry.url.as_str(), |(dir, last)| {
+            if last.is_empty() {
+                dir.rsplit('/').next().unwrap_or(dir)
+            } else {
+                last
+            }
+        });
+    let is_dir = entry.url.ends_with('/');
+    // 以`/`开头或带协议的链接拼接后会出现`//`
+    if href.contains(['?', ':']) || entry.url.contains("//") {
+        return None;
+    }
+    let name = percent_decode_str(href).decode_utf8().ok()?.into_owned();
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') || is_upload_temp(&name)
+    {
+        return None;
+    }
+    Some((name, is_dir))
+}
+
+/// 本地文件与上游相同：上游提供的修改时间（精确到秒）和大小都与本地相同，两者至少有一个。
+/// 近似的大小（例如`118M`）不参与比较
+fn unchanged(local: &Path, entry: &StorageEntry) -> bool {
+    let size = entry.size.filter(|_| !entry.approximate_size);
+    match fs::metadata(local) {
+        Ok(m) if m.is_file() => {
+            let same_time = match (entry.modified, m.modified()) {
+                (Some(upstream), Ok(local)) => to_unix(upstream) == to_unix(local),
+                (Some(_), Err(_)) => false,
+                (None, _) => true,
+            };
+            (entry.modified.is_some() || size.is_some())
+                && same_time
+                && size.is_none_or(|size| size as u64 == m.len())
+        }
+        _ => false,
+    }
+}
+
+impl HttpSync<'_> {
+    async fn run(mut self, root: &Path) -> anyhow::Result<SyncReport> {
+        let base = Url::parse(&format!("{}/", self.job.upstream.trim_end_matches('/')))?;
+
+        // 上游存在的文件和目录，用于删除本地多余的文件
+        let mut seen = HashSet::new();
+        let mut failures = Vec::new();
+        let mut dirs = vec![String::new()];
+        let mut root_empty = false;
+        while let Some(dir) = dirs.pop() {
+            // 列目录失败时中止整个任务，避免把没有列出的文件当作已删除
+            let entries = self
+                .upstream
+                .list_directory(&format!("/{}", dir))
+                .await?
+                .ok_or_else(|| anyhow!("Upstream directory /{} not found", dir))?;
+            if dir.is_empty() {
+                root_empty = !entries.iter().any(|e| entry_name(e).is_some());
+            }
+            for entry in entries {
+                let (name, is_dir) = match entry_name(&entry) {
+                    Some(e) => e,
+                    None => continue,
+                };
+                let rel = format!("{}{}", dir, name);
+                if is_dir {
+                    if !self.filters.excluded(&rel) {
+                        self.provider
+                            .mkdir(&format!("{}/{}", self.dest, rel))
+                            .await?;
+                        dirs.push(format!("{}/", rel));
+                        seen.insert(rel);
+                    }
+                    continue;
+                }
+                if !self.filters.selected(&rel) {
+                    continue;
+                }
+                seen.insert(rel.clone());
+                let (local, listed) = (root.join(&rel), entry.clone());
+                if tokio::task::spawn_blocking(move || unchanged(&local, &listed)).await? {
+                    continue;
+                }
+                if let Err(e) = self.download(&base, root, &rel, entry.modified).await {
+                    log::warn!(
+                        "Sync job {}: failed to download {}: {:#}",
+                        self.job.name,
+                        rel,
+                        e
+                    );
+                    failures.push(format!("{}: {:#}", rel, e));
+                }
+            }
+        }
+        if let Some(first) = failures.first() {
+            return Err(anyhow!(
+                "{} files failed to download, first error: {}",
+                failures.len(),
+                first
+            ));
+        }
+        if self.job.delete {
+            self.delete_removed(root, seen, root_empty).await?;
+        }
+        Ok(self.report)
+    }
+
+    /// 下载到临时文件，长度正确后再替换本地文件，并设置与上游相同的修改时间
+    async fn download(
+        &mut self,
+        base: &Url,
+        root: &Path,
+        rel: &str,
+        modified: Option<SystemTime>,
+    ) -> anyhow::Result<()> {
+        let encoded = crate::encode_path(rel);
+        let client = self.upstream.client();
+        let mut resp = client
+            .send(client.get(base.join(&encoded)?))
+            .await?
+            .error_for_status()?;
+        let expected = resp.content_length();
+        let mut upload = self.provider.put(&format!("{}/{}", self.dest, rel)).await?;
+        while let Some(chunk) = resp.chunk().await? {
+            upload.write(&chunk).await?;
+        }
+        if expected.is_some_and(|len| len != upload.size()) {
+            return Err(anyhow!(
+                "Truncated download: expected {} bytes, got {}",
+                expected.unwrap_or_default(),
+                upload.size()
+            ));
+        }
+        upload.finish().await?;
+        let size = upload.size();
+        upload.commit(true).await?;
+        if let Some(modified) = modified {
+            let local = root.join(rel);
+            tokio::task::spawn_blocking(move || {
+                fs::File::options()
+                    .write(true)
+                    .open(local)
+                    .and_then(|f| f.set_modified(modified))
+            })
+            .await??;
+        }
+        log::debug!(
+            "Sync job {}: downloaded {} ({} bytes)",
+            self.job.name,
+            rel,
+            size
+        );
+        self.report.downloaded += 1;
+        self.report.bytes += size;
+        Ok(())
+    }
+
+    /// 删除上游已经不存在的文件和目录，被规则排除的本地文件保留。
+    /// 上游根目录为空或者要删除的文件过多时认为上游列表不完整，不删除任何文件
+    async fn delete_removed(
+        &mut self,
+        root: &Path,
+        seen: HashSet<String>,
+        root_empty: bool,
+    ) -> anyhow::Result<()> {
+        let (dir, filters) = (root.to_path_buf(), self.filters.clone());
+        let plan = tokio::task::spawn_blocking(move || {
+            let mut plan = Removal::default();
+            plan.scan(&dir, &dir, &filters, &seen, false).map(|_| plan)
+        })
+        .await??;
+        plan.check(root_empty, self.job.max_delete_ratio)?;
+        for rel in plan.paths {
+            self.provider
+                .delete(&format!("{}/{}", self.dest, rel), true)
+                .await?;
+            log::info!("Sync job {}: deleted {}", self.job.name, rel);
+            self.report.deleted += 1;
+        }
+        Ok(())
+    }
+}
+
+/// 本地需要删除的文件和目录
+#[derive(Debug, Default)]
+struct Removal {
+    /// 要删除的路径（相对于任务目录），删除目录时不再列出其中的内容
+    paths: Vec<String>,
+    /// 要删除的文件数，包括要删除的目录中的文件
+    files: u64,
+    /// 本地参与同步的文件总数
+    total: u64,
+}
+
+impl Removal {
+    /// 扫描本地目录，`removing`表示`dir`本身要被删除
+    fn scan(
+        &mut self,
+        root: &Path,
+        dir: &Path,
+        filters: &Filters,
+        seen: &HashSet<String>,
+        removing: bool,
+    ) -> std::io::Result<()> {
+        for child in fs::read_dir(dir)? {
+            let child = child?;
+            let path = child.path();
+            let rel = match path.strip_prefix(root).ok().and_then(|p| p.to_str()) {
+                Some(rel) => rel.to_string(),
+                None => continue,
+            };
+            let is_dir = child.file_type()?.is_dir();
+            if is_upload_temp(&child.file_name().to_string_lossy()) {
+                continue;
+            }
+            let kept = if is_dir {
+                filters.excluded(&rel)
+            } else {
+                !filters.selected(&rel)
+            };
+            if kept && !removing {
+                continue;
+            }
+            let remove = removing || !seen.contains(&rel);
+            if remove && !removing {
+                self.paths.push(rel);
+            }
+            if is_dir {
+                self.scan(root, &path, filters, seen, remove)?;
+            } else {
+                self.total += 1;
+                if remove {
+                    self.files += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check(&self, root_empty: bool, max_ratio: f64) -> anyhow::Result<()> {
+        if self.paths.is_empty() {
+            return Ok(());
+        }
+        if root_empty {
+            return Err(anyhow!(
+                "Upstream root listing is empty, refusing to delete {} local files",
+                self.files
+            ));
+        }
+        if self.files as f64 > self.total as f64 * max_ratio {
+            return Err(anyhow!(
+                "Refusing to delete {} of {} local files (max_delete_ratio = {}), \
+                 the upstream listing may be incomplete",
+                self.files,
+                self.total,
+                max_ratio
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// rsync的过滤参数：排除规则在前；有`include`时只包含匹配的文件和所有目录
+fn rsync_filter_args(job: &SyncJob) -> Vec<String> {
+    let mut args: Vec<String> = job
+        .exclude
+        .iter()
+        .map(|p| format!("--exclude={}", p))
+        .collect();
+    if !job.include.is_empty() {
+        args.push("--include=*/".to_string());
+        args.extend(job.include.iter().map(|p| format!("--include={}", p)));
+        args.push("--exclude=*".to_string());
+        args.push("--prune-empty-dirs".to_string());
+    }
+    args
+}
+
+/// `rsync --stats`输出中的数字，例如`Number of deleted files: 1,234`
+fn stats_value(output: &str, label: &str) -> u64 {
+    output
+        .lines()
+        .find_map(|l| l.trim().strip_prefix(label))
+        .and_then(|v| v.split_whitespace().next())
+        .and_then(|v| v.replace(',', "").parse().ok())
+        .unwrap_or_default()
+}
+
+fn rsync_command(job: &SyncJob) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("rsync");
+    command
+        .args(["-rlt", "--safe-links", "--delay-updates"])
+        .args(rsync_filter_args(job))
+        .args(&job.rsync_options)
+        .kill_on_drop(true);
+    if job.delete {
+        command.arg("--delete-delay");
+    }
+    command
+}
+
+async fn run_rsync(
+    mut command: tokio::process::Command,
+    job: &SyncJob,
+    root: &Path,
+) -> anyhow::Result<String> {
+    command
+        .arg(format!("{}/", job.upstream.trim_end_matches('/')))
+        .arg(format!("{}/", root.display()));
+    let output = command
+        .output()
+        .await
+        .map_err(|e| anyhow!("Failed to run rsync: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.lines().rev().find(|l| !l.trim().is_empty());
+        return Err(anyhow!(
+            "rsync exited with {}: {}",
+            output.status,
+            message.unwrap_or_default()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// 解析`--itemize-changes --out-format='%i %n'`的输出，返回将要删除的文件，
+/// 以及上游是否为空（没有任何要创建或更新的项目，并且本地的文件将全部被删除）
+fn itemized_removal(output: &str, total: u64) -> (Removal, bool) {
+    let mut plan = Removal {
+        total,
+        ..Default::default()
+    };
+    let mut transfers = false;
+    for line in output.lines() {
+        let Some((item, name)) = line.split_once(' ') else {
+            continue;
+        };
+        let name = name.trim_start();
+        if item == "*deleting" {
+            if !name.ends_with('/') {
+                plan.files += 1;
+            }
+            plan.paths.push(name.to_string());
+        } else if name != "./" {
+            transfers = true;
+        }
+    }
+    let upstream_empty = !transfers && plan.files == plan.total;
+    (plan, upstream_empty)
+}
+
+/// 调用系统中的rsync从`rsync://`上游同步。开启`delete`时先预演一次，
+/// 与HTTP上游相同，上游为空或者要删除的文件过多时不同步
+async fn sync_rsync(
+    job: &SyncJob,
+    root: &Path,
+    filters: Arc<Filters>,
+) -> anyhow::Result<SyncReport> {
+    if job.delete {
+        let dir = root.to_path_buf();
+        let total = tokio::task::spawn_blocking(move || {
+            let mut plan = Removal::default();
+            plan.scan(&dir, &dir, &filters, &HashSet::new(), false)
+                .map(|_| plan.total)
+        })
+        .await??;
+        let mut command = rsync_command(job);
+        command.args(["--dry-run", "--itemize-changes", "--out-format=%i %n"]);
+        let output = run_rsync(command, job, root).await?;
+        let (plan, upstream_empty) = itemized_removal(&output, total);
+        plan.check(upstream_empty, job.max_delete_ratio)?;
+    }
+
+    let mut command = rsync_command(job);
+    command.arg("--stats");
+    let stdout = run_rsync(command, job, root).await?;
+    Ok(SyncReport {
+        downloaded: stats_value(&stdout, "Number of regular files transferred:"),
+        deleted: stats_value(&stdout, "Number of deleted files:"),
+        bytes: stats_value(&stdout, "Total transferred file size:"),
+        ..Default::default()
+    })
+}
+
+/// 同步任务状态页面
+#[get("/sync/")]
+pub async fn status_page(req: HttpRequest) -> HttpResponse {
+    let locale = Locale::from_request(&req);
+    match render::render_sync_status(statuses(), locale) {
+        Ok(html) => HttpResponse::Ok().content_type("text/html").body(html),
+        Err(e) => {
+            log::error!("Failed to render sync status: {}", e);
+            HttpError::internal_error(Message::ServerError, Message::RenderIndexFailed)
+                .to_http_response(&req)
+        }
+    }
+}
+
+#[get("/sync/status.json")]
+pub async fn status_json() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "jobs": statuses() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(toml: &str) -> SyncJob {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_filters() {
+        let job = job(r#"
+            name = "dragonos"
+            upstream = "https://example.org/dragonos/"
+            path = "/pub/dragonos"
+            include = ["**/*.iso"]
+            exclude = ["nightly"]
+            "#);
+        let filters = Filters::new(&job).unwrap();
+        assert!(filters.selected("releases/dragonos-0.1.9.iso"));
+        assert!(filters.selected("a.iso"));
+        assert!(!filters.selected("README.md"));
+        assert!(filters.excluded("nightly"));
+        assert!(!filters.selected("nightly/a.iso"));
+        assert_eq!(
+            rsync_filter_args(&job),
+            [
+                "--exclude=nightly",
+                "--include=*/",
+                "--include=**/*.iso",
+                "--exclude=*",
+                "--prune-empty-dirs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_entry_name_and_stats() {
+        let entry = |url: &str| StorageEntry {
+            name: String::new(),
+            url: url.to_string(),
+            modified: Some(SystemTime::UNIX_EPOCH),
+            size: None,
+            approximate_size: false,
+        };
+        assert_eq!(
+            entry_name(&entry("/a/b%20c.iso")),
+            Some(("b c.iso".to_string(), false))
+        );
+        assert_eq!(
+            entry_name(&entry("/a/sub/")),
+            Some(("sub".to_string(), true))
+        );
+        assert_eq!(entry_name(&entry("/a/?C=N;O=D")), None);
+        assert_eq!(entry_name(&entry("/a/../")), None);
+        assert_eq!(entry_name(&entry("/a//b/c.iso")), None);
+
+        let output = "Number of files: 10 (reg: 8, dir: 2)\n\
+                      Number of deleted files: 3\n\
+                      Number of regular files transferred: 1,024\n\
+                      Total transferred file size: 123,456 bytes\n";
+        assert_eq!(
+            stats_value(output, "Number of regular files transferred:"),
+            1024
+        );
+        assert_eq!(stats_value(output, "Number of deleted files:"), 3);
+        assert_eq!(stats_value(output, "Total transferred file size:"), 123456);
+        assert_eq!(stats_value(output, "Missing:"), 0);
+    }
+
+    #[test]
+    fn test_unchanged_with_approximate_size() {
+        let path = std::env::temp_dir().join(format!("mirror-proxy-{}", uuid::Uuid::new_v4()));
+        fs::write(&path, vec![0u8; 1000]).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1792384080);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(modified))
+            .unwrap();
+        // 列表中显示为`1K`
+        let mut entry = StorageEntry {
+            name: "a.iso".to_string(),
+            url: "/a.iso".to_string(),
+            modified: Some(modified),
+            size: Some(1024),
+            approximate_size: true,
+        };
+        assert!(unchanged(&path, &entry));
+        entry.approximate_size = false;
+        assert!(!unchanged(&path, &entry));
+        entry.size = Some(1000);
+        assert!(unchanged(&path, &entry));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_delete_guard() {
+        let root = std::env::temp_dir().join(format!("mirror-proxy-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(root.join("nightly")).unwrap();
+        for name in ["a.iso", "b.iso", "c.iso", "sub/d.iso", "nightly/e.iso"] {
+            fs::write(root.join(name), b"x").unwrap();
+        }
+        let filters = Filters::new(&job(r#"
+            name = "dragonos"
+            upstream = "https://example.org/dragonos/"
+            path = "/pub/dragonos"
+            exclude = ["nightly"]
+            "#))
+        .unwrap();
+
+        let seen: HashSet<String> = ["a.iso", "b.iso", "c.iso"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut plan = Removal::default();
+        plan.scan(&root, &root, &filters, &seen, false).unwrap();
+        assert_eq!(plan.paths, ["sub"]);
+        assert_eq!((plan.files, plan.total), (1, 4));
+        assert!(plan.check(false, 0.5).is_ok());
+        // 上游根目录为空时不删除
+        assert!(plan.check(true, 1.0).is_err());
+
+        // 列表只解析出一部分时删除的比例过高
+        let seen: HashSet<String> = ["a.iso"].iter().map(|s| s.to_string()).collect();
+        let mut plan = Removal::default();
+        plan.scan(&root, &root, &filters, &seen, false).unwrap();
+        assert_eq!((plan.files, plan.total), (3, 4));
+        assert!(plan.check(false, 0.5).is_err());
+        assert!(plan.check(false, 1.0).is_ok());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_itemized_removal() {
+        let output = "\
+.d..t...... ./
+*deleting   old/a.iso
+*deleting   old/
+*deleting   b.iso
+>f+++++++++ c.iso
+";
+        let (plan, upstream_empty) = itemized_removal(output, 4);
+        assert_eq!(plan.paths, ["old/a.iso", "old/", "b.iso"]);
+        assert_eq!(plan.files, 2);
+        assert!(!upstream_empty);
+        assert!(plan.check(upstream_empty, 0.5).is_ok());
+        assert!(plan.check(upstream_empty, 0.4).is_err());
+
+        // 上游模块为空：只有删除，没有任何要传输的文件
+        let output = "*deleting   a.iso\n*deleting   b.iso\n";
+        let (plan, upstream_empty) = itemized_removal(output, 2);
+        assert!(upstream_empty);
+        assert!(plan.check(upstream_empty, 1.0).is_err());
+
+        // 只有未变化的文件时不会被当作空的上游
+        let (plan, upstream_empty) = itemized_removal("*deleting   a.iso\n", 2);
+        assert!(!upstream_empty);
+        assert!(plan.check(upstream_empty, 0.5).is_ok());
+    }
+}
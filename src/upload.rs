@@ -0,0 +1,228 @@
+use std::path::PathBuf;
+
+use actix_web::{http::header, route, web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use serde::Serialize;
+
+use crate::{
+    auth::constant_time_eq,
+    config::{self, has_matching_extension, is_under, UploadRule},
+    error::HttpError,
+    i18n::Message,
+    storage::{self, select_provider},
+    BASE_PATH,
+};
+
+/// 客户端提供的内容校验值（十六进制SHA-256）
+const CHECKSUM_HEADER: &str = "X-Checksum-Sha256";
+
+/// 上传成功后返回的结果
+#[derive(Debug, Serialize)]
+pub struct UploadResult {
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+/// 适用于该路径的上传规则，路径前缀最长（最具体）的优先
+fn rule_for<'a>(rules: &'a [UploadRule], full_path: &str) -> Option<&'a UploadRule> {
+    rules
+        .iter()
+        .filter(|r| is_under(full_path, &r.path))
+        .max_by_key(|r| r.path.len())
+}
+
+fn is_authorized(req: &HttpRequest, rule: &UploadRule) -> bool {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|v| v.trim().split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim());
+    match token {
+        Some(token) => rule
+            .tokens
+            .iter()
+            .any(|t| constant_time_eq(t.as_bytes(), token.as_bytes())),
+        None => false,
+    }
+}
+
+/// 解析`X-Checksum-Sha256`请求头，统一转换为小写
+pub fn expected_checksum(req: &HttpRequest) -> Result<Option<String>, HttpError> {
+    let value = match req.headers().get(CHECKSUM_HEADER) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    match value.to_str() {
+        Ok(v) if v.len() == 64 && v.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Ok(Some(v.to_ascii_lowercase()))
+        }
+        _ => Err(HttpError::bad_request(
+            Message::InvalidRequest,
+            Message::InvalidChecksum,
+        )),
+    }
+}
+
+/// 上传文件：`PUT`（或`POST`）请求体即文件内容，写入请求路径对应的位置
+#[route("/pub{path:.*}", method = "PUT", method = "POST")]
+pub async fn upload_file(
+    req: HttpRequest,
+    path: web::Path<String>,
+    payload: web::Payload,
+) -> HttpResponse {
+    match handle_upload(&req, &path, payload).await {
+        Ok(resp) => resp,
+        Err(e) => e.to_http_response(&req),
+    }
+}
+
+async fn handle_upload(
+    req: &HttpRequest,
+    path: &str,
+    payload: web::Payload,
+) -> Result<HttpResponse, HttpError> {
+    if !path.starts_with('/') || path.ends_with('/') {
+        return Err(HttpError::bad_request(
+            Message::InvalidRequest,
+            Message::UploadInvalidPath,
+        ));
+    }
+    let full_path = PathBuf::from(format!("{}{}", BASE_PATH, path));
+    let path_str = crate::validate_path(&full_path)?;
+    let path_str = path_str.as_str();
+
+    let config = config::current();
+    let rule = rule_for(&config.upload, path_str)
+        .ok_or_else(|| HttpError::forbidden(Message::AccessDenied, Message::UploadNotAllowed))?;
+    if !is_authorized(req, rule) {
+        log::debug!("Unauthorized upload to {}", path_str);
+        return Err(HttpError::unauthorized(
+            Message::Unauthorized,
+            Message::UnauthorizedDesc,
+            r#"Bearer realm="upload""#.to_string(),
+        ));
+    }
+    let extensions = if rule.extensions.is_empty() {
+        &config.download_rules.extensions
+    } else {
+        &rule.extensions
+    };
+    if !has_matching_extension(path_str, extensions) {
+        return Err(HttpError::forbidden(
+            Message::AccessDenied,
+            Message::UploadExtensionNotAllowed,
+        ));
+    }
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if content_length.is_some_and(|len| len > rule.max_size) {
+        return Err(HttpError::payload_too_large(
+            Message::PayloadTooLarge,
+            Message::PayloadTooLargeDesc,
+        ));
+    }
+    let expected = expected_checksum(req)?;
+    let result = store(
+        path_str,
+        payload,
+        expected,
+        Some(rule.max_size),
+        rule.overwrite,
+    )
+    .await?;
+
+    if rule.purge_cache {
+        // 上传时可能新建了目录，从规则所在目录的上一级开始清除
+        let parent = rule.path.trim_end_matches('/');
+        let parent = parent.rsplit_once('/').map(|(p, _)| p).unwrap_or(parent);
+        storage::purge(&format!("{}/", parent));
+    }
+
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, path_str.to_string()))
+        .json(result))
+}
+
+/// 把请求体写入存储中的`path_str`，`max_size`为`None`时不限制大小
+pub async fn store(
+    path_str: &str,
+    mut payload: web::Payload,
+    expected: Option<String>,
+    max_size: Option<u64>,
+    overwrite: bool,
+) -> Result<UploadResult, HttpError> {
+    let too_large =
+        || HttpError::payload_too_large(Message::PayloadTooLarge, Message::PayloadTooLargeDesc);
+    let (provider, path_in_provider) = select_provider(path_str)
+        .ok_or_else(|| HttpError::not_found(Message::PathNotFound, Message::ResourceNotFound))?;
+    let storage_error = |e: storage::StorageError| {
+        log::error!("Upload to {} failed: {}", path_str, e);
+        HttpError::from_storage(&e, Message::UploadFailed)
+    };
+    let mut upload = provider
+        .put(&path_in_provider)
+        .await
+        .map_err(storage_error)?;
+    // 出错返回时`upload`被丢弃，临时文件随之删除
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| {
+            log::warn!("Upload to {} interrupted: {}", path_str, e);
+            HttpError::bad_request(Message::InvalidRequest, Message::UploadFailed)
+        })?;
+        if max_size.is_some_and(|max| upload.size() + chunk.len() as u64 > max) {
+            return Err(too_large());
+        }
+        upload.write(&chunk).await.map_err(storage_error)?;
+    }
+    let sha256 = upload.finish().await.map_err(storage_error)?;
+    if expected.is_some_and(|expected| expected != sha256) {
+        return Err(HttpError::bad_request(
+            Message::InvalidRequest,
+            Message::ChecksumMismatch,
+        ));
+    }
+    let size = upload.size();
+    if !upload.commit(overwrite).await.map_err(storage_error)? {
+        return Err(HttpError::conflict(Message::Conflict, Message::FileExists));
+    }
+    log::info!("Uploaded {} ({} bytes, sha256 {})", path_str, size, sha256);
+    Ok(UploadResult {
+        path: path_str.to_string(),
+        size,
+        sha256,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn test_expected_checksum() {
+        let digest = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+        let req = TestRequest::default()
+            .insert_header((CHECKSUM_HEADER, digest))
+            .to_http_request();
+        assert_eq!(
+            expected_checksum(&req).unwrap(),
+            Some(digest.to_ascii_lowercase())
+        );
+
+        let req = TestRequest::default()
+            .insert_header((CHECKSUM_HEADER, "abc"))
+            .to_http_request();
+        assert!(expected_checksum(&req).is_err());
+        assert_eq!(
+            expected_checksum(&TestRequest::default().to_http_request()).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,425 @@
+//! 访问源站和上游镜像的HTTP客户端：共用连接池，幂等请求失败时带随机退避地重试，
+//! 同一源站持续失败时熔断，熔断期间的请求直接失败，不再等待超时
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+    Certificate, Client, Proxy, RequestBuilder, Response, StatusCode,
+};
+use serde::Serialize;
+
+use crate::{
+    config::{HttpVersion, UpstreamClientConfig},
+    storage::{StorageError, StorageResult},
+};
+
+lazy_static! {
+    /// 按源站（`scheme://host:port`）记录的熔断状态，所有后端和同步任务共用
+    static ref ORIGINS: Mutex<HashMap<String, OriginStatus>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// 正常放行请求
+    #[default]
+    Closed,
+    /// 熔断中，请求直接失败
+    Open,
+    /// 熔断结束，正在用一个请求试探源站是否恢复
+    HalfOpen,
+}
+
+/// 一个源站的请求统计和熔断状态（只保存在内存中），在`/admin/health`中返回
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OriginStatus {
+    pub origin: String,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// 熔断或试探还要持续的时间（秒）
+    pub open_for: Option<u64>,
+    pub requests: u64,
+    pub failures: u64,
+    pub retries: u64,
+    pub last_error: Option<String>,
+    /// 最近一次失败的Unix时间戳（秒）
+    pub last_failure: Option<u64>,
+    #[serde(skip)]
+    open_until: Option<Instant>,
+}
+
+/// 所有访问过的源站的状态，按源站排序
+pub fn statuses() -> Vec<OriginStatus> {
+    let now = Instant::now();
+    let mut statuses: Vec<OriginStatus> = ORIGINS
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .map(|mut status| {
+            status.open_for = status
+                .open_until
+                .filter(|_| status.state != BreakerState::Closed)
+                .map(|until| until.saturating_duration_since(now).as_secs());
+            status
+        })
+        .collect();
+    statuses.sort_by(|a, b| a.origin.cmp(&b.origin));
+    statuses
+}
+
+/// 解析`Retry-After`中的秒数
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// 源站暂时出错、值得重试的状态码
+fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || (status.is_server_error() && status != StatusCode::NOT_IMPLEMENTED)
+}
+
+/// 访问源站的客户端，克隆后共用同一个连接池
+#[derive(Clone)]
+pub struct UpstreamClient {
+    client: Client,
+    retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    breaker_threshold: u32,
+    breaker_cooldown: Duration,
+}
+
+impl UpstreamClient {
+    pub fn new(config: &UpstreamClientConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: build_client(config)?,
+            retries: config.retries,
+            backoff: Duration::from_millis(config.retry_backoff_ms),
+            max_backoff: Duration::from_millis(config.retry_max_backoff_ms),
+            breaker_threshold: config.breaker_threshold,
+            breaker_cooldown: Duration::from_secs(config.breaker_cooldown),
+        })
+    }
+
+    pub fn get(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn head(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.head(url)
+    }
+
+    /// 发送幂等请求（GET、HEAD）。连接错误、超时、429和5xx按退避时间重试，重试用完后返回最后一次的
+    /// 响应或错误；其他状态码原样返回，由调用者处理。源站熔断时直接返回`Unavailable`
+    pub async fn send(&self, request: RequestBuilder) -> StorageResult<Response> {
+        let request = request.build()?;
+        let origin = request.url().origin().ascii_serialization();
+        let mut attempt = 0;
+        loop {
+            self.admit(&origin)?;
+            let next = request
+                .try_clone()
+                .ok_or_else(|| anyhow!("Request to {} can not be retried", request.url()))?;
+            let result = self.client.execute(next).await;
+            let (error, wait) = match &result {
+                Ok(resp) if is_transient(resp.status()) => (
+                    format!("{} returned {}", resp.url(), resp.status()),
+                    retry_after(resp.headers()),
+                ),
+                Ok(_) => {
+                    self.record(&origin, None);
+                    return Ok(result?);
+                }
+                Err(e) => (e.to_string(), None),
+            };
+            self.record(&origin, Some(&error));
+            let delay = match self.backoff(attempt, wait) {
+                Some(delay) if attempt < self.retries => delay,
+                _ => return Ok(result?),
+            };
+            log::debug!(
+                "Retrying {} in {}ms: {}",
+                request.url(),
+                delay.as_millis(),
+                error
+            );
+            if let Some(status) = ORIGINS.lock().unwrap().get_mut(&origin) {
+                status.retries += 1;
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// 第`attempt`次重试前的等待时间（full jitter），`Retry-After`超过上限时不再重试
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let cap = self
+            .backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_backoff);
+        let jitter = Duration::from_millis(fastrand::u64(0..=cap.as_millis() as u64));
+        match retry_after {
+            Some(wait) if wait > self.max_backoff => None,
+            Some(wait) => Some(wait.max(jitter)),
+            None => Some(jitter),
+        }
+    }
+
+    /// 请求前检查熔断状态。熔断时间结束后进入半开状态，放行一个请求试探，
+    /// 试探期间（最长一个熔断周期）其他请求仍然直接失败
+    fn admit(&self, origin: &str) -> StorageResult<()> {
+        if self.breaker_threshold == 0 {
+            return Ok(());
+        }
+        let mut origins = ORIGINS.lock().unwrap();
+        let status = match origins.get_mut(origin) {
+            Some(status) if status.state != BreakerState::Closed => status,
+            _ => return Ok(()),
+        };
+        let now = Instant::now();
+        match status.open_until {
+            Some(until) if now < until => Err(StorageError::Unavailable {
+                retry_after: Some(until - now).filter(|_| status.state == BreakerState::Open),
+            }),
+            _ => {
+                log::info!("Probing upstream {} after circuit breaker cooldown", origin);
+                status.state = BreakerState::HalfOpen;
+                status.open_until = Some(now + self.breaker_cooldown);
+                Ok(())
+            }
+        }
+    }
+
+    /// 记录一次请求的结果，`error`为`None`表示源站正常响应
+    fn record(&self, origin: &str, error: Option<&str>) {
+        let mut origins = ORIGINS.lock().unwrap();
+        let status = origins
+            .entry(origin.to_string())
+            .or_insert_with(|| OriginStatus {
+                origin: origin.to_string(),
+                ..Default::default()
+            });
+        status.requests += 1;
+        let error = match error {
+            Some(error) => error,
+            None => {
+                if status.state != BreakerState::Closed {
+                    log::info!("Upstream {} recovered, closing circuit breaker", origin);
+                }
+                status.state = BreakerState::Closed;
+                status.consecutive_failures = 0;
+                status.open_until = None;
+                return;
+            }
+        };
+        status.failures += 1;
+        status.consecutive_failures += 1;
+        status.last_error = Some(error.to_string());
+        status.last_failure = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+        if self.breaker_threshold > 0
+            && (status.state == BreakerState::HalfOpen
+                || status.consecutive_failures >= self.breaker_threshold)
+        {
+            if status.state != BreakerState::Open {
+                log::warn!(
+                    "Upstream {} failed {} times in a row, opening circuit breaker for {}s: {}",
+                    origin,
+                    status.consecutive_failures,
+                    self.breaker_cooldown.as_secs(),
+                    error
+                );
+            }
+            status.state = BreakerState::Open;
+            status.open_until = Some(Instant::now() + self.breaker_cooldown);
+        }
+    }
+}
+
+/// 按配置创建reqwest客户端。客户端内部带有连接池，应在整个后端中共用，而不是每个请求创建一个
+fn build_client(config: &UpstreamClientConfig) -> anyhow::Result<Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| anyhow!("Invalid header name '{}': {}", name, e))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| anyhow!("Invalid value for header {}: {}", name, e))?;
+        headers.insert(name, value);
+    }
+
+    let mut builder = Client::builder()
+        .user_agent(&config.user_agent)
+        .default_headers(headers)
+        .connect_timeout(Duration::from_secs(config.connect_timeout))
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout))
+        .tcp_keepalive(Duration::from_secs(60));
+    if config.read_timeout > 0 {
+        builder = builder.read_timeout(Duration::from_secs(config.read_timeout));
+    }
+    if config.timeout > 0 {
+        builder = builder.timeout(Duration::from_secs(config.timeout));
+    }
+    builder = match config.http_version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+    };
+    if let Some(proxy) = &config.proxy {
+        let proxy =
+            Proxy::all(proxy).map_err(|e| anyhow!("Invalid upstream proxy '{}': {}", proxy, e))?;
+        builder = builder.proxy(proxy);
+    }
+    for path in &config.ca_certs {
+        let pem = std::fs::read(path)
+            .map_err(|e| anyhow!("Failed to read CA certificate {}: {}", path, e))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| anyhow!("Invalid CA certificate {}: {}", path, e))?;
+        if certs.is_empty() {
+            return Err(anyhow!("No certificate found in {}", path));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn test_build_client() {
+        let mut config = UpstreamClientConfig::default();
+        config
+            .headers
+            .insert("Authorization".to_string(), "Bearer t".to_string());
+        config.proxy = Some("socks5h://127.0.0.1:1080".to_string());
+        config.http_version = HttpVersion::Http2;
+        assert!(build_client(&config).is_ok());
+
+        config.headers.insert("bad name".to_string(), String::new());
+        assert!(build_client(&config).is_err());
+        config.headers.clear();
+
+        config.ca_certs = vec!["/nonexistent/ca.pem".to_string()];
+        assert!(build_client(&config).is_err());
+    }
+
+    /// 启动一个本地源站，`healthy`为`false`时返回503，返回地址和收到的请求数
+    async fn origin(healthy: Arc<AtomicBool>) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                let status = if healthy.load(Ordering::SeqCst) {
+                    "200 OK"
+                } else {
+                    "503 Service Unavailable"
+                };
+                let resp = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                let _ = stream.write_all(resp.as_bytes()).await;
+            }
+        });
+        (format!("http://{}/", addr), hits)
+    }
+
+    fn status_of(url: &str) -> OriginStatus {
+        let origin = reqwest::Url::parse(url)
+            .unwrap()
+            .origin()
+            .ascii_serialization();
+        statuses().into_iter().find(|s| s.origin == origin).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_retry_and_breaker() {
+        let healthy = Arc::new(AtomicBool::new(false));
+        let (url, hits) = origin(healthy.clone()).await;
+        let mut config = UpstreamClientConfig {
+            retries: 2,
+            retry_backoff_ms: 1,
+            retry_max_backoff_ms: 5,
+            breaker_threshold: 3,
+            breaker_cooldown: 60,
+            ..Default::default()
+        };
+        let client = UpstreamClient::new(&config).unwrap();
+
+        // 重试用完后返回最后一次的响应，连续失败3次后熔断
+        let resp = client.send(client.get(&url)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        let status = status_of(&url);
+        assert_eq!(status.state, BreakerState::Open);
+        assert_eq!(status.retries, 2);
+        assert!(status.open_for.is_some());
+
+        // 熔断期间不再请求源站
+        let err = client.send(client.head(&url)).await.unwrap_err();
+        assert!(matches!(
+            err,
+            StorageError::Unavailable {
+                retry_after: Some(_)
+            }
+        ));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // 熔断结束后放行一个试探请求，成功后恢复
+        config.breaker_cooldown = 0;
+        let client = UpstreamClient::new(&config).unwrap();
+        ORIGINS
+            .lock()
+            .unwrap()
+            .values_mut()
+            .for_each(|s| s.open_until = Some(Instant::now()));
+        healthy.store(true, Ordering::SeqCst);
+        let resp = client.send(client.get(&url)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(status_of(&url).state, BreakerState::Closed);
+        assert_eq!(status_of(&url).consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let client = UpstreamClient::new(&UpstreamClientConfig::default()).unwrap();
+        for attempt in 0..20 {
+            let cap = Duration::from_millis((200u64 << attempt.min(16)).min(5000));
+            assert!(client.backoff(attempt, None).unwrap() <= cap);
+        }
+        assert_eq!(
+            client.backoff(0, Some(Duration::from_secs(2))),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(client.backoff(0, Some(Duration::from_secs(60))), None);
+    }
+}
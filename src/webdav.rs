@@ -0,0 +1,383 @@
+use std::{path::PathBuf, sync::Arc, time::SystemTime};
+
+use actix_web::{
+    http::{
+        header::{self, HttpDate},
+        Method, StatusCode,
+    },
+    route, web, HttpRequest, HttpResponse,
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::{
+    auth,
+    error::HttpError,
+    i18n::Message,
+    storage::{select_provider, StorageEntry, StorageProvider, StorageResult},
+    BASE_PATH,
+};
+
+/// WebDAV的挂载路径，`/dav/...`对应`/pub/...`
+pub const DAV_PATH: &str = "/dav";
+
+const ALLOWED_METHODS: &str = "OPTIONS, GET, HEAD, PROPFIND";
+
+/// href中需要转义的字符
+const HREF_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}')
+    .add(b'&')
+    .add(b'\'');
+
+/// PROPFIND的深度，不支持`infinity`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Depth {
+    Zero,
+    One,
+}
+
+impl Depth {
+    fn from_request(req: &HttpRequest) -> Option<Self> {
+        // 没有Depth头时按infinity处理（RFC 4918 9.1）
+        match req.headers().get("Depth").and_then(|h| h.to_str().ok()) {
+            Some("0") => Some(Self::Zero),
+            Some("1") => Some(Self::One),
+            _ => None,
+        }
+    }
+}
+
+/// 请求路径对应的资源
+enum Resource {
+    Collection {
+        modified: Option<SystemTime>,
+        entries: Vec<StorageEntry>,
+    },
+    File(StorageEntry),
+}
+
+/// 查找资源：先在上级目录中查找同名文件，不是文件时再当作目录列出
+async fn lookup(
+    provider: &Arc<dyn StorageProvider>,
+    path_in_provider: &str,
+) -> StorageResult<Option<Resource>> {
+    let trimmed = path_in_provider.trim_end_matches('/');
+    let (parent, name) = trimmed.rsplit_once('/').unwrap_or(("", trimmed));
+    let parent_entry = if name.is_empty() {
+        None
+    } else {
+        provider
+            .list_directory(&format!("{}/", parent))
+            .await?
+            .and_then(|entries| {
+                entries
+                    .into_iter()
+                    .find(|e| e.name.trim_end_matches('/') == name)
+            })
+    };
+
+    // 上级目录中列出的文件不再当作目录列出：HTTP目录列表的存储会下载整个文件，
+    // 并且可能把无法识别的内容解析成空目录
+    if let Some(entry) = parent_entry.as_ref().filter(|e| is_file(e)) {
+        // 以`/`结尾的路径只能是目录
+        if path_in_provider.ends_with('/') {
+            return Ok(None);
+        }
+        return Ok(Some(Resource::File(entry.clone())));
+    }
+
+    Ok(provider
+        .list_directory(path_in_provider)
+        .await?
+        .map(|entries| Resource::Collection {
+            modified: parent_entry.and_then(|e| e.modified),
+            entries,
+        }))
+}
+
+fn is_file(entry: &StorageEntry) -> bool {
+    entry.size.is_some() && !entry.name.ends_with('/')
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 一个`<D:response>`元素。`path`是相对于挂载点的路径，目录以`/`结尾
+fn write_response(
+    xml: &mut String,
+    path: &str,
+    name: &str,
+    modified: Option<SystemTime>,
+    size: Option<usize>,
+) {
+    let href = format!("{}{}", DAV_PATH, utf8_percent_encode(path, HREF_ENCODE_SET));
+    xml.push_str("<D:response><D:href>");
+    xml.push_str(&xml_escape(&href));
+    xml.push_str("</D:href><D:propstat><D:prop>");
+    xml.push_str(&format!(
+        "<D:displayname>{}</D:displayname>",
+        xml_escape(name)
+    ));
+    match size {
+        None => xml.push_str("<D:resourcetype><D:collection/></D:resourcetype>"),
+        Some(size) => {
+            let content_type = mime_guess::from_path(name).first_or_octet_stream();
+            xml.push_str(&format!(
+                "<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength>\
+                 <D:getcontenttype>{}</D:getcontenttype>",
+                size,
+                xml_escape(content_type.as_ref())
+            ));
+        }
+    }
+    if let Some(modified) = modified {
+        xml.push_str(&format!(
+            "<D:getlastmodified>{}</D:getlastmodified>",
+            HttpDate::from(modified)
+        ));
+    }
+    xml.push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>");
+}
+
+/// 生成PROPFIND的`207 Multi-Status`响应体，`path`是相对于挂载点的路径
+fn multistatus(path: &str, resource: &Resource, depth: Depth) -> String {
+    let mut xml =
+        String::from(r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">"#);
+    let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+    match resource {
+        Resource::File(entry) => {
+            write_response(&mut xml, path, name, entry.modified, entry.size);
+        }
+        Resource::Collection { modified, entries } => {
+            let dir = format!("{}/", path.trim_end_matches('/'));
+            write_response(&mut xml, &dir, name, *modified, None);
+            if depth == Depth::One {
+                for entry in entries {
+                    let entry_name = entry.name.trim_end_matches('/');
+                    let is_dir = entry.size.is_none() || entry.name.ends_with('/');
+                    let entry_path =
+                        format!("{}{}{}", dir, entry_name, if is_dir { "/" } else { "" });
+                    let size = if is_dir { None } else { entry.size };
+                    write_response(&mut xml, &entry_path, entry_name, entry.modified, size);
+                }
+            }
+        }
+    }
+    xml.push_str("</D:multistatus>");
+    xml
+}
+
+/// 只读WebDAV：OPTIONS、PROPFIND（深度0或1）、GET和HEAD
+#[route(
+    "/dav{path:.*}",
+    method = "OPTIONS",
+    method = "PROPFIND",
+    method = "GET",
+    method = "HEAD"
+)]
+pub async fn webdav(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    match handle_webdav(&req, &path).await {
+        Ok(resp) => resp,
+        Err(e) => e.to_http_response(&req),
+    }
+}
+
+async fn handle_webdav(req: &HttpRequest, path: &str) -> Result<HttpResponse, HttpError> {
+    if !path.is_empty() && !path.starts_with('/') {
+        return Err(HttpError::not_found(
+            Message::PageNotFound,
+            Message::PageNotFoundDesc,
+        ));
+    }
+    if req.method() == Method::OPTIONS {
+        return Ok(HttpResponse::Ok()
+            .insert_header(("DAV", "1"))
+            .insert_header((header::ALLOW, ALLOWED_METHODS))
+            .insert_header(("MS-Author-Via", "DAV"))
+            .finish());
+    }
+
+    let path = if path.is_empty() { "/" } else { path };
+    let full_path = PathBuf::from(format!("{}{}", BASE_PATH, path));
+    let path_str = crate::validate_path(&full_path)?;
+    auth::authorize(req, &path_str).await?;
+
+    let (provider, path_in_provider) = select_provider(&path_str)
+        .ok_or_else(|| HttpError::not_found(Message::PathNotFound, Message::ResourceNotFound))?;
+    let resource = lookup(&provider, &path_in_provider)
+        .await
+        .map_err(|e| {
+            log::error!("WebDAV lookup of {} failed: {}", path_str, e);
+            HttpError::from_storage(&e, Message::ListDirectoryFailed)
+        })?
+        .ok_or_else(|| HttpError::not_found(Message::PathNotFound, Message::ResourceNotFound))?;
+
+    if req.method() == Method::GET || req.method() == Method::HEAD {
+        return match resource {
+            Resource::File(_) => crate::serve_file(&path_str, req).await,
+            // 目录返回普通的HTML目录列表
+            Resource::Collection { .. } => Ok(HttpResponse::Found()
+                .insert_header((
+                    header::LOCATION,
+                    format!("{}/", path_str.trim_end_matches('/')),
+                ))
+                .finish()),
+        };
+    }
+
+    let depth = Depth::from_request(req).ok_or_else(|| {
+        HttpError::forbidden(Message::AccessDenied, Message::PropfindInfiniteDepth)
+    })?;
+    let resource = match resource {
+        Resource::Collection { modified, entries } => Resource::Collection {
+            modified,
+            entries: auth::visible_entries(req, &path_str, entries).await,
+        },
+        file => file,
+    };
+    let relative = path_str.strip_prefix(BASE_PATH).unwrap_or(&path_str);
+    Ok(HttpResponse::build(StatusCode::MULTI_STATUS)
+        .content_type("application/xml; charset=utf-8")
+        .body(multistatus(relative, &resource, depth)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn entry(name: &str, size: Option<usize>) -> StorageEntry {
+        StorageEntry {
+            name: name.to_string(),
+            url: String::new(),
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            size,
+            approximate_size: false,
+        }
+    }
+
+    #[test]
+    fn test_multistatus() {
+        let resource = Resource::Collection {
+            modified: None,
+            entries: vec![entry("sub/", None), entry("a & b.iso", Some(42))],
+        };
+        let xml = multistatus("/dragonos", &resource, Depth::One);
+        assert!(xml.contains("<D:href>/dav/dragonos/</D:href>"));
+        assert!(xml.contains("<D:href>/dav/dragonos/sub/</D:href>"));
+        assert!(xml.contains("<D:href>/dav/dragonos/a%20%26%20b.iso</D:href>"));
+        assert!(xml.contains("<D:displayname>a &amp; b.iso</D:displayname>"));
+        assert!(xml.contains("<D:getcontentlength>42</D:getcontentlength>"));
+        assert!(
+            xml.contains("<D:getlastmodified>Tue, 14 Nov 2023 22:13:20 GMT</D:getlastmodified>")
+        );
+        assert_eq!(xml.matches("<D:collection/>").count(), 2);
+
+        let xml = multistatus("/dragonos", &resource, Depth::Zero);
+        assert_eq!(xml.matches("<D:response>").count(), 1);
+
+        let xml = multistatus(
+            "/a.iso",
+            &Resource::File(entry("a.iso", Some(1))),
+            Depth::One,
+        );
+        assert!(xml.contains("<D:href>/dav/a.iso</D:href><D:propstat><D:prop><D:displayname>a.iso"));
+        assert!(xml.contains("<D:getcontentlength>1</D:getcontentlength>"));
+    }
+
+    /// 模拟HTTP目录列表的存储：对文件的列目录请求也会返回一个（空的）列表
+    #[derive(Default)]
+    struct IndexProvider {
+        listed: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl StorageProvider for IndexProvider {
+        async fn list_directory(
+            &self,
+            path_in_provider: &str,
+        ) -> StorageResult<Option<Vec<StorageEntry>>> {
+            self.listed
+                .lock()
+                .unwrap()
+                .push(path_in_provider.to_string());
+            Ok(Some(match path_in_provider {
+                "/" => vec![entry("sub/", None), entry("x.iso", Some(42))],
+                _ => Vec::new(),
+            }))
+        }
+
+        fn path_in_provider(&self, full_path: &str) -> Option<String> {
+            Some(full_path.to_string())
+        }
+
+        async fn get_download_url(
+            &self,
+            _full_path: &str,
+            _client_ip: Option<&str>,
+        ) -> StorageResult<Option<String>> {
+            Ok(None)
+        }
+
+        async fn read_file(
+            &self,
+            _path_in_provider: &str,
+            _max_size: usize,
+        ) -> StorageResult<Option<Vec<u8>>> {
+            Ok(None)
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_lookup_file_on_index_backend() {
+        let index = Arc::new(IndexProvider::default());
+        let provider: Arc<dyn StorageProvider> = index.clone();
+
+        let resource = lookup(&provider, "/x.iso").await.unwrap();
+        assert!(matches!(resource, Some(Resource::File(e)) if e.size == Some(42)));
+        assert_eq!(*index.listed.lock().unwrap(), ["/"]);
+        assert!(lookup(&provider, "/x.iso/").await.unwrap().is_none());
+
+        let resource = lookup(&provider, "/sub").await.unwrap();
+        assert!(matches!(resource, Some(Resource::Collection { .. })));
+        assert!(lookup(&provider, "/").await.unwrap().is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_protected_path_with_dot_segments() {
+        crate::config::init_test_config();
+        for path in ["/prerelease/", "/./prerelease/", "//prerelease/x.iso"] {
+            let req = actix_web::test::TestRequest::default()
+                .method(Method::from_bytes(b"PROPFIND").unwrap())
+                .to_http_request();
+            let err = handle_webdav(&req, path).await.unwrap_err();
+            assert_eq!(
+                err.to_http_response(&req).status(),
+                actix_web::http::StatusCode::UNAUTHORIZED,
+                "{}",
+                path
+            );
+        }
+    }
+}